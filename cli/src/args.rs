@@ -6,9 +6,14 @@ use std::{
 	path::Path,
 };
 
-use clap::{crate_version, App, Arg, ArgMatches};
+use clap::{crate_version, App, Arg, ArgMatches, ErrorKind};
 use miette::{Context, IntoDiagnostic, Result};
 
+use self::project::ProjectConfig;
+
+mod expand;
+mod project;
+
 trait Clap3Compat {
 	/// Does nothing for clap2, but remove this trait for clap3, and get cool new option groups!
 	fn help_heading(self, _heading: impl Into<Option<&'static str>>) -> Self
@@ -36,7 +41,8 @@ pub fn get_args(tagged_filterer: bool) -> Result<ArgMatches<'static>> {
 			.help_heading(Some(OPTSET_COMMAND))
 			.help("Command to execute")
 			.multiple(true)
-			.required(true))
+			.required_unless_one(&["emit-events-to", "on-change"])
+			.conflicts_with("on-change"))
 		.arg(Arg::with_name("paths")
 			.help_heading(Some(OPTSET_FILTERING))
 			.help("Watch a specific file or directory")
@@ -46,11 +52,36 @@ pub fn get_args(tagged_filterer: bool) -> Result<ArgMatches<'static>> {
 			.number_of_values(1)
 			.multiple(true)
 			.takes_value(true))
+		.arg(Arg::with_name("watch-file")
+			.help_heading(Some(OPTSET_FILTERING))
+			.help("Also watch the files and directories listed in this file, one per line")
+			.long("watch-file")
+			.value_name("path")
+			.number_of_values(1)
+			.multiple(true)
+			.takes_value(true))
+		.arg(Arg::with_name("follow-symlinks")
+			.help_heading(Some(OPTSET_FILTERING))
+			.help("Also watch the targets of symlinked directories found in the watched paths")
+			.long("follow-symlinks"))
+		.arg(Arg::with_name("max-depth")
+			.help_heading(Some(OPTSET_FILTERING))
+			.help("Only watch this many levels of directories deep, instead of the whole tree")
+			.long("max-depth")
+			.value_name("depth")
+			.takes_value(true))
 		.arg(Arg::with_name("clear")
 			.help_heading(Some(OPTSET_BEHAVIOUR))
 			.help("Clear screen before executing command")
 			.short("c")
 			.long("clear"))
+		.arg(Arg::with_name("clear-mode")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Clear the screen this way, implies --clear: `success` only clears if the previous run succeeded, `scrollback` clears the scrollback too")
+			.long("clear-mode")
+			.takes_value(true)
+			.value_name("mode")
+			.possible_values(&["always", "success", "scrollback"]))
 		.arg(Arg::with_name("on-busy-update")
 			.help_heading(Some(OPTSET_BEHAVIOUR))
 			.help("Select the behaviour to use when receiving events while the command is running. Current default is queue, will change to do-nothing in 2.0.")
@@ -64,18 +95,61 @@ pub fn get_args(tagged_filterer: bool) -> Result<ArgMatches<'static>> {
 			.long("restart"))
 		.arg(Arg::with_name("signal")
 			.help_heading(Some(OPTSET_BEHAVIOUR))
-			.help("Specify the signal to send when using --on-busy-update=signal")
+			.help("Send this signal to the command when events are received while it's running, instead of restarting it. Shorthand for --on-busy-update=signal")
 			.short("s")
 			.long("signal")
 			.takes_value(true)
 			.value_name("signal")
 			.default_value("SIGTERM")
 			.hidden(cfg!(windows)))
+		.arg(Arg::with_name("stop-signal")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Specify the signal to send to the command to stop it, e.g. when restarting")
+			.long("stop-signal")
+			.takes_value(true)
+			.value_name("signal")
+			.default_value("SIGTERM")
+			.hidden(cfg!(windows)))
+		.arg(Arg::with_name("stop-timeout")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Set how long to wait for the command to exit after the stop signal before killing it, defaults to 250ms")
+			.long("stop-timeout")
+			.takes_value(true)
+			.value_name("milliseconds")
+			.hidden(cfg!(windows)))
 		.arg(Arg::with_name("kill")
 			.help_heading(Some(OPTSET_BEHAVIOUR))
 			.hidden(true)
 			.short("k")
 			.long("kill"))
+		.arg(Arg::with_name("timeout")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Stop the command if it's still running after this many seconds, and go back to watching")
+			.takes_value(true)
+			.value_name("seconds")
+			.long("timeout"))
+		.arg(Arg::with_name("retry")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Re-run the command up to this many times if it fails, until the next change")
+			.takes_value(true)
+			.value_name("count")
+			.long("retry"))
+		.arg(Arg::with_name("retry-delay")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Set how long to wait before each retry, defaults to 0ms")
+			.takes_value(true)
+			.value_name("milliseconds")
+			.long("retry-delay"))
+		.arg(Arg::with_name("restart-on-exit")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Start the command again whenever it exits, even without changes")
+			.long("restart-on-exit")
+			.conflicts_with("once"))
+		.arg(Arg::with_name("stdin-events")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Keep the command running and write changes to its stdin as JSON lines, instead of restarting it")
+			.long("stdin-events")
+			.conflicts_with_all(&["once", "on-change", "on-busy-update", "signal", "watch-when-idle"]))
 		.arg(Arg::with_name("debounce")
 			.help_heading(Some(OPTSET_BEHAVIOUR))
 			.help("Set the timeout between detected change and command execution, defaults to 100ms")
@@ -83,6 +157,37 @@ pub fn get_args(tagged_filterer: bool) -> Result<ArgMatches<'static>> {
 			.value_name("milliseconds")
 			.short("d")
 			.long("debounce"))
+		.arg(Arg::with_name("debounce-mode")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Count the debounce from the first change (fixed), restart it on every change (trailing), or lengthen it while changes keep coming (adaptive)")
+			.takes_value(true)
+			.possible_values(&["fixed", "trailing", "adaptive"])
+			.default_value("fixed")
+			.long("debounce-mode"))
+		.arg(Arg::with_name("debounce-max")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Set the longest the trailing or adaptive debounce can postpone the command, defaults to 5000ms")
+			.takes_value(true)
+			.value_name("milliseconds")
+			.long("debounce-max"))
+		.arg(Arg::with_name("debounce-growth")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Set how much the adaptive debounce grows by for each back-to-back batch, defaults to 2")
+			.takes_value(true)
+			.value_name("factor")
+			.long("debounce-growth"))
+		.arg(Arg::with_name("per-file-cooldown")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Ignore changes to a file for this long after it last triggered a run, while still reacting to other files")
+			.takes_value(true)
+			.value_name("milliseconds")
+			.long("per-file-cooldown"))
+		.arg(Arg::with_name("min-interval")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Wait at least this long after the command ends before running it again")
+			.takes_value(true)
+			.value_name("milliseconds")
+			.long("min-interval"))
 		.arg(Arg::with_name("verbose")
 			.help_heading(Some(OPTSET_DEBUGGING))
 			.help("Print debugging messages (-v, -vv, -vvv, -vvvv; use -vvv for bug reports)")
@@ -94,6 +199,22 @@ pub fn get_args(tagged_filterer: bool) -> Result<ArgMatches<'static>> {
 			.help("Print events that trigger actions")
 			.long("print-events")
 			.alias("changes-only")) // --changes-only is deprecated (remove at v2)
+		.arg(Arg::with_name("heartbeat")
+			.help_heading(Some(OPTSET_DEBUGGING))
+			.help("Print a line every this many seconds to show watchexec is alive, with counts of events and runs")
+			.long("heartbeat")
+			.takes_value(true)
+			.value_name("seconds"))
+		.arg(Arg::with_name("print-config-and-exit")
+			.help_heading(Some(OPTSET_DEBUGGING))
+			.help("Print the paths, command, and filters that would be used as JSON, then exit without watching")
+			.long("print-config-and-exit"))
+		.arg(Arg::with_name("emit-events-to")
+			.help_heading(Some(OPTSET_OUTPUT))
+			.help("Write events that pass the filters as JSON lines to `stdout` or `file:PATH`; the command becomes optional")
+			.takes_value(true)
+			.value_name("target")
+			.long("emit-events-to"))
 		.arg(Arg::with_name("no-vcs-ignore")
 			.help_heading(Some(OPTSET_FILTERING))
 			.help("Skip auto-loading of VCS (Git, etc) ignore files")
@@ -105,21 +226,44 @@ pub fn get_args(tagged_filterer: bool) -> Result<ArgMatches<'static>> {
 			.alias("no-ignore")) // --no-ignore is deprecated (remove at v2)
 		.arg(Arg::with_name("no-default-ignore")
 			.help_heading(Some(OPTSET_FILTERING))
-			.help("Skip auto-ignoring of commonly ignored globs")
+			.help("Skip auto-ignoring of commonly ignored globs: editor swap and backup files, *.pyc and *.pyo, .DS_Store, and VCS directories like .git")
 			.long("no-default-ignore"))
 		.arg(Arg::with_name("no-global-ignore")
 			.help_heading(Some(OPTSET_FILTERING))
 			.help("Skip auto-loading of global or environment-wide ignore files")
 			.long("no-global-ignore"))
+		.arg(Arg::with_name("ignore-files")
+			.help_heading(Some(OPTSET_FILTERING))
+			.help("Load ignore globs from a gitignore-format file, applying within the file's directory, or from stdin with `-`")
+			.long("ignore-file")
+			.number_of_values(1)
+			.multiple(true)
+			.takes_value(true)
+			.value_name("path"))
 		.arg(Arg::with_name("postpone")
 			.help_heading(Some(OPTSET_BEHAVIOUR))
 			.help("Wait until first change to execute command")
 			.short("p")
 			.long("postpone"))
+		.arg(Arg::with_name("delay")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Wait this long before the first run at startup")
+			.long("delay")
+			.value_name("milliseconds")
+			.conflicts_with("postpone"))
+		.arg(Arg::with_name("drop-missing")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Leave out paths which no longer exist by the time the command runs")
+			.long("drop-missing"))
+		.arg(Arg::with_name("loop-guard")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Stop running the command when it keeps triggering itself, until something else changes")
+			.long("loop-guard"))
 		.arg(Arg::with_name("poll")
 			.help_heading(Some(OPTSET_BEHAVIOUR))
 			.help("Force polling mode (interval in milliseconds)")
 			.long("force-poll")
+			.alias("poll")
 			.value_name("interval"))
 		.arg(Arg::with_name("shell")
 			.help_heading(Some(OPTSET_COMMAND))
@@ -136,15 +280,80 @@ pub fn get_args(tagged_filterer: bool) -> Result<ArgMatches<'static>> {
 			.help("Do not wrap command in a shell. Deprecated: use --shell=none instead.")
 			.short("n")
 			.long("no-shell"))
+		.arg(Arg::with_name("prefix")
+			.help_heading(Some(OPTSET_OUTPUT))
+			.help("Prefix each line of the command's output with [label]")
+			.long("prefix")
+			.takes_value(true)
+			.value_name("label"))
+		.arg(Arg::with_name("log-file")
+			.help_heading(Some(OPTSET_OUTPUT))
+			.help("Also append the command's output to this file")
+			.long("log-file")
+			.takes_value(true)
+			.value_name("path"))
+		.arg(Arg::with_name("log-max-size")
+			.help_heading(Some(OPTSET_OUTPUT))
+			.help("Rotate the --log-file to path.1, path.2, etc before it grows past this size")
+			.long("log-max-size")
+			.takes_value(true)
+			.value_name("bytes")
+			.requires("log-file"))
+		.arg(Arg::with_name("dedupe-output")
+			.help_heading(Some(OPTSET_OUTPUT))
+			.help("Replace the command's output with a single line when it's the same as the previous run's")
+			.long("dedupe-output"))
+		.arg(Arg::with_name("pty")
+			.help_heading(Some(OPTSET_OUTPUT))
+			.help("Give the command a pseudo-terminal for its output, so it keeps colours and progress bars (Unix only)")
+			.long("pty"))
 		.arg(Arg::with_name("no-environment")
 			.help_heading(Some(OPTSET_OUTPUT))
 			.help("Do not set WATCHEXEC_*_PATH environment variables for the command")
 			.long("no-environment"))
+		.arg(Arg::with_name("env-clear")
+			.help_heading(Some(OPTSET_COMMAND))
+			.help("Do not pass watchexec's environment on to the command, only set the --env and WATCHEXEC_* variables")
+			.long("env-clear"))
+		.arg(Arg::with_name("env")
+			.help_heading(Some(OPTSET_COMMAND))
+			.help("Add an environment variable for the command (can be repeated)")
+			.long("env")
+			.takes_value(true)
+			.number_of_values(1)
+			.multiple(true)
+			.value_name("KEY=VALUE"))
+		.arg(Arg::with_name("strict-env")
+			.help_heading(Some(OPTSET_COMMAND))
+			.help("Fail on unset environment variables in --watch paths and the command, instead of expanding them to nothing")
+			.long("strict-env"))
 		.arg(Arg::with_name("no-process-group")
 			.help_heading(Some(OPTSET_COMMAND))
 			.help("Do not use a process group when running the command")
 			.long("no-process-group"))
-		.arg(Arg::with_name("once").short("1").hidden(true))
+		.arg(Arg::with_name("workdir")
+			.help_heading(Some(OPTSET_COMMAND))
+			.help("Set the working directory of the command (defaults to the current directory)")
+			.long("workdir")
+			.takes_value(true)
+			.value_name("directory"))
+		.arg(Arg::with_name("on-change")
+			.help_heading(Some(OPTSET_COMMAND))
+			.help("Run a command only for changes to some extensions, e.g. 'rs:cargo test', instead of the main command")
+			.long("on-change")
+			.number_of_values(1)
+			.multiple(true)
+			.takes_value(true)
+			.value_name("exts:command"))
+		.arg(Arg::with_name("once")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Run the command once, then exit with its exit code. Use with --postpone to wait for a change first")
+			.short("1")
+			.long("once"))
+		.arg(Arg::with_name("fail-fast")
+			.help_heading(Some(OPTSET_BEHAVIOUR))
+			.help("Exit if the command can't be run, e.g. because it doesn't exist, instead of waiting for the next change")
+			.long("fail-fast"))
 		.arg(Arg::with_name("watch-when-idle")
 			.help_heading(Some(OPTSET_BEHAVIOUR))
 			.help("Deprecated alias for --on-busy-update=do-nothing, which will become the default in 2.0.")
@@ -155,7 +364,12 @@ pub fn get_args(tagged_filterer: bool) -> Result<ArgMatches<'static>> {
 			.help_heading(Some(OPTSET_OUTPUT))
 			.help("Send a desktop notification when the command ends")
 			.short("N")
-			.long("notify"));
+			.long("notify"))
+		.arg(Arg::with_name("quiet")
+			.help_heading(Some(OPTSET_OUTPUT))
+			.help("Don't print anything of watchexec's own, only the command's output; overrides -v and --clear")
+			.short("q")
+			.long("quiet"));
 
 	let app = if tagged_filterer {
 		app.arg(
@@ -196,7 +410,7 @@ pub fn get_args(tagged_filterer: bool) -> Result<ArgMatches<'static>> {
 		app.arg(
 			Arg::with_name("extensions")
 				.help_heading(Some(OPTSET_FILTERING))
-				.help("Comma-separated list of file extensions to watch (e.g. js,css,html)")
+				.help("List of file extensions to watch, separated by commas or spaces (e.g. js,css,html), prefix with ! to exclude one instead")
 				.short("e")
 				.long("exts")
 				.takes_value(true),
@@ -223,12 +437,69 @@ pub fn get_args(tagged_filterer: bool) -> Result<ArgMatches<'static>> {
 				.takes_value(true)
 				.value_name("pattern"),
 		)
+		.arg(
+			Arg::with_name("filter-files")
+				.help_heading(Some(OPTSET_FILTERING))
+				.help("Load filter globs from a file, one per line, or from stdin with `-`")
+				.long("filter-file")
+				.number_of_values(1)
+				.multiple(true)
+				.takes_value(true)
+				.value_name("path"),
+		)
 		.arg(
 			Arg::with_name("no-meta")
 				.help_heading(Some(OPTSET_FILTERING))
 				.help("Ignore metadata changes")
 				.long("no-meta"),
 		)
+		.arg(
+			Arg::with_name("on")
+				.help_heading(Some(OPTSET_FILTERING))
+				.help("Only react to these kinds of changes (comma-separated), defaults to all")
+				.long("on")
+				.takes_value(true)
+				.multiple(true)
+				.use_delimiter(true)
+				.require_delimiter(true)
+				.possible_values(&["create", "modify", "remove", "rename"])
+				.value_name("kinds"),
+		)
+		.arg(
+			Arg::with_name("ignore-case")
+				.help_heading(Some(OPTSET_FILTERING))
+				.help("Match filters, ignores, and extensions without regard to case")
+				.long("ignore-case"),
+		)
+		.arg(
+			Arg::with_name("ignore-hidden")
+				.help_heading(Some(OPTSET_FILTERING))
+				.help("Ignore hidden files and directories, and everything within them, defaults to false")
+				.long("ignore-hidden")
+				.takes_value(true)
+				.min_values(0)
+				.require_equals(true)
+				.value_name("bool")
+				.possible_values(&["true", "false"]),
+		)
+		.arg(
+			Arg::with_name("rerun-on-recovery")
+				.help_heading(Some(OPTSET_BEHAVIOUR))
+				.help("Run the command once when a watched directory that was removed comes back, whatever the filters")
+				.long("rerun-on-recovery"),
+		)
+		.arg(
+			Arg::with_name("stats")
+				.help_heading(Some(OPTSET_DEBUGGING))
+				.help("Print how many events were passed and rejected by the filters, and why, on exit")
+				.long("stats"),
+		)
+		.arg(
+			Arg::with_name("debug")
+				.help_heading(Some(OPTSET_DEBUGGING))
+				.help("Print the filters, ignores, and extensions in effect, and where each comes from, on startup")
+				.long("debug"),
+		)
 	};
 
 	let mut raw_args: Vec<OsString> = env::args_os().collect();
@@ -252,5 +523,41 @@ pub fn get_args(tagged_filterer: bool) -> Result<ArgMatches<'static>> {
 		}
 	}
 
-	Ok(app.get_matches_from(raw_args))
+	let project = match ProjectConfig::load(Path::new("."))? {
+		Some(project) => project,
+		None => return expand::get_matches(app, raw_args),
+	};
+
+	// find out what was given on the command line, to only take the rest from the file
+	let command = project.command();
+	let with_command = |mut args: Vec<OsString>| {
+		if !args.iter().any(|arg| arg == "--") {
+			args.push("--".into());
+		}
+		args.extend(command.iter().cloned());
+		args
+	};
+	let (given, command_given) = match app.clone().get_matches_from_safe(&raw_args) {
+		Ok(given) => {
+			let command_given = given.is_present("command") || given.is_present("on-change");
+			(given, command_given)
+		}
+		Err(err) if err.kind == ErrorKind::MissingRequiredArgument && !command.is_empty() => {
+			let given = app
+				.clone()
+				.get_matches_from_safe(with_command(raw_args.clone()))
+				.unwrap_or_else(|err| err.exit());
+			(given, false)
+		}
+		Err(err) => err.exit(),
+	};
+
+	let mut args = vec![raw_args.remove(0)];
+	args.extend(project.options(&given, tagged_filterer)?);
+	args.extend(raw_args);
+	if !command_given && !command.is_empty() {
+		args = with_command(args);
+	}
+
+	expand::get_matches(app, args)
 }
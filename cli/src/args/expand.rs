@@ -0,0 +1,223 @@
+//! Expansion of environment variables in watched paths and in the command, and of `--watch-file`
+//! lists into watched paths.
+
+use std::{env, ffi::OsString, fs::read_to_string, path::Path};
+
+use clap::{App, ArgMatches};
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+
+/// Expand `$VAR` and `${VAR}` in `s`, looking variables up with `lookup`.
+///
+/// A `$` that isn't followed by a variable name is left as is, and so are `$WATCHEXEC_*` variables,
+/// which are set for each run of the command rather than in watchexec's environment. Returns the
+/// expanded string and the names of the variables that weren't set, which expand to nothing.
+pub fn expand(s: &str, lookup: impl Fn(&str) -> Option<String>) -> (String, Vec<String>) {
+	let mut expanded = String::with_capacity(s.len());
+	let mut unset = Vec::new();
+	let mut rest = s;
+
+	while let Some(start) = rest.find('$') {
+		expanded.push_str(&rest[..start]);
+		let after = &rest[start + 1..];
+
+		let (name, len) = if let Some(braced) = after.strip_prefix('{') {
+			match braced.find('}') {
+				Some(end) if is_name(&braced[..end]) => (&braced[..end], end + 2),
+				_ => ("", 0),
+			}
+		} else {
+			let end = after
+				.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+				.unwrap_or(after.len());
+			if is_name(&after[..end]) {
+				(&after[..end], end)
+			} else {
+				("", 0)
+			}
+		};
+
+		if name.is_empty() {
+			expanded.push('$');
+			rest = after;
+			continue;
+		}
+
+		if name.starts_with("WATCHEXEC_") {
+			expanded.push_str(&rest[start..=start + len]);
+		} else {
+			match lookup(name) {
+				Some(value) => expanded.push_str(&value),
+				None => unset.push(name.to_string()),
+			}
+		}
+		rest = &after[len..];
+	}
+
+	expanded.push_str(rest);
+	(expanded, unset)
+}
+
+fn is_name(s: &str) -> bool {
+	s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+		&& s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parse the arguments, expanding environment variables in the `--watch` paths, and in the
+/// command when it's run without a shell, and adding the paths listed in `--watch-file`s.
+///
+/// A shell already expands variables itself, when the command runs, so the command is left alone
+/// then. Unset variables expand to nothing with a warning, or are an error
+/// with `--strict-env`.
+pub fn get_matches(app: App<'static, 'static>, args: Vec<OsString>) -> Result<ArgMatches<'static>> {
+	let matches = app.clone().get_matches_from(&args);
+	let strict = matches.is_present("strict-env");
+	let quiet = matches.is_present("quiet");
+	let no_shell = matches.is_present("no-shell")
+		|| matches
+			.value_of("shell")
+			.map_or(false, |s| s.eq_ignore_ascii_case("none"));
+
+	let lookup = |name: &str| env::var(name).ok();
+
+	let mut changed = false;
+	let mut expand_value = |value: &str, what: &str| -> Result<String> {
+		let (expanded, unset) = expand(value, lookup);
+		for name in unset {
+			if strict {
+				return Err(miette!(
+					"environment variable {} is not set, in {} {:?}",
+					name,
+					what,
+					value
+				));
+			} else if !quiet {
+				eprintln!(
+					"[[Warning: environment variable {} is not set, expanding to nothing in {} {:?}]]",
+					name, what, value
+				);
+			}
+		}
+		changed |= expanded != value;
+		Ok(expanded)
+	};
+
+	let mut expanded = Vec::with_capacity(args.len());
+	let mut tokens = args.into_iter();
+	expanded.extend(tokens.next());
+	let mut watch_value_next = false;
+	let mut after_dashes = false;
+	let command: Vec<String> = if no_shell {
+		matches.values_of_lossy("command").unwrap_or_default()
+	} else {
+		Vec::new()
+	};
+
+	for token in tokens {
+		let s = match token.to_str() {
+			Some(s) if s.contains('$') => s,
+			_ => {
+				watch_value_next = !after_dashes && (token == "-w" || token == "--watch");
+				after_dashes |= token == "--";
+				expanded.push(token);
+				continue;
+			}
+		};
+
+		let new = if watch_value_next {
+			expand_value(s, "--watch")?
+		} else if let Some((flag, path)) = ["--watch=", "-w=", "-w"]
+			.iter()
+			.filter(|_| !after_dashes)
+			.find_map(|flag| s.strip_prefix(flag).map(|path| (flag, path)))
+		{
+			format!("{}{}", flag, expand_value(path, "--watch")?)
+		} else if (after_dashes || !s.starts_with('-')) && command.iter().any(|c| c == s) {
+			expand_value(s, "the command")?
+		} else {
+			s.to_string()
+		};
+
+		watch_value_next = false;
+		expanded.push(new.into());
+	}
+
+	// listed paths are relative to the file they're listed in, like for ignore files
+	let mut listed = Vec::new();
+	for file in matches.values_of_os("watch-file").unwrap_or_default() {
+		let file = Path::new(file);
+		let content = read_to_string(file)
+			.into_diagnostic()
+			.wrap_err_with(|| format!("--watch-file: cannot read '{}'", file.display()))?;
+		let dir = file.parent().unwrap_or_else(|| Path::new(""));
+		for line in watch_file_lines(&content) {
+			listed.push(OsString::from("--watch"));
+			let path = dir.join(expand_value(line, "--watch-file")?);
+			// missing paths are kept, to be skipped with a warning like those given to --watch
+			listed.push(dunce::canonicalize(&path).unwrap_or(path).into());
+		}
+	}
+
+	if !listed.is_empty() {
+		expanded.splice(1..1, listed);
+		Ok(app.get_matches_from(expanded))
+	} else if changed {
+		Ok(app.get_matches_from(expanded))
+	} else {
+		Ok(matches)
+	}
+}
+
+/// The paths listed in a `--watch-file`: one per line, skipping blank lines and `#` comments.
+fn watch_file_lines(content: &str) -> impl Iterator<Item = &str> {
+	content
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+#[cfg(test)]
+#[test]
+fn expand_variables() {
+	let lookup = |name: &str| match name {
+		"HOME" => Some("/home/user".to_string()),
+		"EMPTY" => Some(String::new()),
+		_ => None,
+	};
+
+	assert_eq!(
+		expand("$HOME/src", lookup),
+		("/home/user/src".to_string(), vec![])
+	);
+	assert_eq!(
+		expand("${HOME}src:$EMPTY.", lookup),
+		("/home/usersrc:.".to_string(), vec![])
+	);
+	assert_eq!(
+		expand("$NOPE/x/${ALSO_NOPE}", lookup),
+		(
+			"/x/".to_string(),
+			vec!["NOPE".to_string(), "ALSO_NOPE".to_string()]
+		)
+	);
+	assert_eq!(
+		expand("$WATCHEXEC_COMMON_PATH/${WATCHEXEC_WRITTEN_PATH}", lookup),
+		(
+			"$WATCHEXEC_COMMON_PATH/${WATCHEXEC_WRITTEN_PATH}".to_string(),
+			vec![]
+		)
+	);
+	assert_eq!(
+		expand("cost: $5, $ ${not a name} $", lookup),
+		("cost: $5, $ ${not a name} $".to_string(), vec![])
+	);
+}
+
+#[cfg(test)]
+#[test]
+fn watch_file_skips_comments_and_blanks() {
+	let content = "# generated by build.rs\nsrc\n\n  ../shared/lib  \n\t# vendored\n/opt/include\n";
+	assert_eq!(
+		watch_file_lines(content).collect::<Vec<_>>(),
+		vec!["src", "../shared/lib", "/opt/include"]
+	);
+}
@@ -0,0 +1,354 @@
+//! The project config file, which sets defaults for some options in a project's directory.
+
+use std::{ffi::OsString, fs, io::ErrorKind, path::Path};
+
+use clap::ArgMatches;
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// The name of the project config file, looked up in the directory watchexec runs in.
+pub const FILENAME: &str = ".watchexec.toml";
+
+/// Defaults for options, from the project config file.
+///
+/// Options given on the command line take precedence, key by key: for example, `-f` replaces all
+/// the `filters` of the file, and `--on-busy-update` or `--signal` replace `restart`.
+///
+/// This is also its serialised form, so that tools editing the file share one representation; unset
+/// keys are left out when serialising.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	paths: Vec<String>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	command: Option<Command>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	filters: Vec<String>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	ignores: Vec<String>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	extensions: Vec<String>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	debounce: Option<u64>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	restart: Option<bool>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	clear: Option<Clear>,
+}
+
+/// A command line to run with the shell, or the program and its arguments.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(
+	untagged,
+	expecting = "expected a command line, or an array of the program and its arguments"
+)]
+enum Command {
+	Line(String),
+	Args(Vec<String>),
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(
+	untagged,
+	expecting = "expected true, false, or one of \"always\", \"success\", \"scrollback\""
+)]
+enum Clear {
+	Enabled(bool),
+	Mode(ClearMode),
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ClearMode {
+	Always,
+	Success,
+	Scrollback,
+}
+
+impl ProjectConfig {
+	/// Reads the project config file in this directory, if there's one.
+	pub fn load(dir: &Path) -> Result<Option<Self>> {
+		let path = dir.join(FILENAME);
+		let contents = match fs::read_to_string(&path) {
+			Ok(contents) => contents,
+			Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+			Err(err) => {
+				return Err(err)
+					.into_diagnostic()
+					.wrap_err_with(|| format!("cannot read {}", path.display()))
+			}
+		};
+
+		let config: Self = toml::from_str(&contents)
+			.into_diagnostic()
+			.wrap_err_with(|| format!("invalid project config in {}", path.display()))?;
+		config
+			.validate(dir)
+			.wrap_err_with(|| format!("invalid project config in {}", path.display()))?;
+		debug!(?path, ?config, "loaded project config");
+		Ok(Some(config))
+	}
+
+	/// Checks what the format alone can't: that the command isn't empty, and that the paths exist.
+	///
+	/// Relative paths are resolved from `dir`. Paths with environment variables are only checked
+	/// once those are expanded, when watching starts.
+	pub fn validate(&self, dir: &Path) -> Result<()> {
+		let empty_command = match &self.command {
+			None => false,
+			Some(Command::Line(line)) => line.trim().is_empty(),
+			Some(Command::Args(args)) => args.first().map_or(true, |program| program.is_empty()),
+		};
+		if empty_command {
+			return Err(miette!("`command` is empty"));
+		}
+
+		if let Some(path) = self
+			.paths
+			.iter()
+			.filter(|path| !path.contains('$'))
+			.find(|path| !dir.join(path).exists())
+		{
+			return Err(miette!("path '{}' in `paths` does not exist", path));
+		}
+
+		Ok(())
+	}
+
+	/// The command from the file, as arguments.
+	pub fn command(&self) -> Vec<OsString> {
+		match &self.command {
+			None => Vec::new(),
+			Some(Command::Line(line)) => vec![line.into()],
+			Some(Command::Args(args)) => args.iter().map(OsString::from).collect(),
+		}
+	}
+
+	/// The options set in the file, as arguments, leaving out those given on the command line.
+	///
+	/// Options are given with `=`, so that each is exactly one argument and can't be mistaken for
+	/// the command when these are put in front of the ones from the command line.
+	pub fn options(&self, given: &ArgMatches, tagged_filterer: bool) -> Result<Vec<OsString>> {
+		if tagged_filterer
+			&& !(self.filters.is_empty() && self.ignores.is_empty() && self.extensions.is_empty())
+		{
+			return Err(miette!(
+				"{}: filters, ignores, and extensions can't be used with the tagged filterer",
+				FILENAME
+			));
+		}
+
+		let mut args = Vec::new();
+		let mut add = |name: &str, values: &[String], arg: &str| {
+			if !given.is_present(name) {
+				args.extend(values.iter().map(|value| format!("{}={}", arg, value)));
+			}
+		};
+
+		add("paths", &self.paths, "--watch");
+		add("filter", &self.filters, "--filter");
+		add("ignore", &self.ignores, "--ignore");
+		if !self.extensions.is_empty() {
+			add("extensions", &[self.extensions.join(",")], "--exts");
+		}
+		if let Some(debounce) = self.debounce {
+			add("debounce", &[debounce.to_string()], "--debounce");
+		}
+
+		let busy_given = given.is_present("restart")
+			|| given.is_present("on-busy-update")
+			|| given.occurrences_of("signal") > 0;
+		if self.restart == Some(true) && !busy_given {
+			args.push("--restart".into());
+		}
+
+		if !given.is_present("clear") && !given.is_present("clear-mode") {
+			match &self.clear {
+				None | Some(Clear::Enabled(false)) => {}
+				Some(Clear::Enabled(true)) => args.push("--clear".into()),
+				Some(Clear::Mode(mode)) => args.push(
+					match mode {
+						ClearMode::Always => "--clear-mode=always",
+						ClearMode::Success => "--clear-mode=success",
+						ClearMode::Scrollback => "--clear-mode=scrollback",
+					}
+					.into(),
+				),
+			}
+		}
+
+		Ok(args.into_iter().map(OsString::from).collect())
+	}
+}
+
+#[cfg(test)]
+fn given(args: &[&str]) -> ArgMatches<'static> {
+	use clap::{App, Arg};
+	App::new("watchexec")
+		.arg(
+			Arg::with_name("paths")
+				.long("watch")
+				.takes_value(true)
+				.multiple(true)
+				.number_of_values(1),
+		)
+		.arg(
+			Arg::with_name("filter")
+				.long("filter")
+				.takes_value(true)
+				.multiple(true)
+				.number_of_values(1),
+		)
+		.arg(
+			Arg::with_name("debounce")
+				.long("debounce")
+				.takes_value(true),
+		)
+		.arg(Arg::with_name("restart").long("restart"))
+		.arg(
+			Arg::with_name("on-busy-update")
+				.long("on-busy-update")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("signal")
+				.long("signal")
+				.takes_value(true)
+				.default_value("SIGTERM"),
+		)
+		.arg(Arg::with_name("clear").long("clear"))
+		.arg(
+			Arg::with_name("clear-mode")
+				.long("clear-mode")
+				.takes_value(true),
+		)
+		.get_matches_from(std::iter::once("watchexec").chain(args.iter().copied()))
+}
+
+#[cfg(test)]
+#[test]
+fn command_line_takes_precedence() {
+	let project: ProjectConfig = toml::from_str(
+		r#"
+		paths = ["src", "lib"]
+		filters = ["*.rs"]
+		debounce = 500
+		restart = true
+		clear = "success"
+		"#,
+	)
+	.unwrap();
+
+	assert_eq!(
+		project.options(&given(&[]), false).unwrap(),
+		vec![
+			"--watch=src",
+			"--watch=lib",
+			"--filter=*.rs",
+			"--debounce=500",
+			"--restart",
+			"--clear-mode=success"
+		]
+	);
+	assert_eq!(
+		project
+			.options(
+				&given(&["--watch", "test", "--on-busy-update", "queue", "--clear"]),
+				false
+			)
+			.unwrap(),
+		vec!["--filter=*.rs", "--debounce=500"]
+	);
+}
+
+#[cfg(test)]
+#[test]
+fn command_as_line_or_args() {
+	let line: ProjectConfig = toml::from_str(r#"command = "cargo test""#).unwrap();
+	assert_eq!(line.command(), vec!["cargo test"]);
+
+	let args: ProjectConfig = toml::from_str(r#"command = ["cargo", "test"]"#).unwrap();
+	assert_eq!(args.command(), vec!["cargo", "test"]);
+}
+
+#[cfg(test)]
+#[test]
+fn errors_name_the_key() {
+	let err = toml::from_str::<ProjectConfig>("fitlers = []").unwrap_err();
+	assert!(
+		err.to_string().contains("unknown field `fitlers`"),
+		"{}",
+		err
+	);
+
+	let err = toml::from_str::<ProjectConfig>(r#"debounce = "fast""#).unwrap_err();
+	assert!(err.to_string().contains("for key `debounce`"), "{}", err);
+}
+
+#[cfg(test)]
+#[test]
+fn serialises_and_parses_back() {
+	let config = ProjectConfig {
+		paths: vec!["src".into()],
+		command: Some(Command::Args(vec!["cargo".into(), "test".into()])),
+		extensions: vec!["rs".into(), "toml".into()],
+		debounce: Some(500),
+		clear: Some(Clear::Mode(ClearMode::Success)),
+		..Default::default()
+	};
+
+	let serialised = toml::to_string(&config).unwrap();
+	assert_eq!(
+		serialised,
+		"paths = [\"src\"]\ncommand = [\"cargo\", \"test\"]\nextensions = [\"rs\", \"toml\"]\ndebounce = 500\nclear = \"success\"\n"
+	);
+	assert_eq!(
+		toml::from_str::<ProjectConfig>(&serialised).unwrap(),
+		config
+	);
+
+	let line = ProjectConfig {
+		command: Some(Command::Line("make".into())),
+		clear: Some(Clear::Enabled(true)),
+		..Default::default()
+	};
+	assert_eq!(
+		toml::from_str::<ProjectConfig>(&toml::to_string(&line).unwrap()).unwrap(),
+		line
+	);
+}
+
+#[cfg(test)]
+#[test]
+fn validates_command_and_paths() {
+	let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+	let valid: ProjectConfig = toml::from_str(
+		r#"paths = ["src", "$SOMEWHERE/else"]
+command = "cargo test""#,
+	)
+	.unwrap();
+	assert!(valid.validate(dir).is_ok());
+	assert!(ProjectConfig::default().validate(dir).is_ok());
+
+	for (config, error) in [
+		(r#"command = "  ""#, "`command` is empty"),
+		(r#"command = []"#, "`command` is empty"),
+		(r#"command = ["", "x"]"#, "`command` is empty"),
+		(
+			r#"paths = ["src", "nope"]"#,
+			"path 'nope' in `paths` does not exist",
+		),
+	] {
+		let config: ProjectConfig = toml::from_str(config).unwrap();
+		assert_eq!(config.validate(dir).unwrap_err().to_string(), error);
+	}
+}
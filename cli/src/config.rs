@@ -1,5 +1,13 @@
+mod dedupe;
 mod init;
+mod logfile;
+#[cfg(unix)]
+mod pty;
+mod rules;
 mod runtime;
+mod summary;
 
 pub use init::init;
+pub use rules::rules;
 pub use runtime::runtime;
+pub use summary::summary;
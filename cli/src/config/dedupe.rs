@@ -0,0 +1,261 @@
+use std::{
+	collections::hash_map::DefaultHasher,
+	hash::{Hash, Hasher},
+	io::{self, Write},
+	mem::take,
+	sync::{Arc, Mutex},
+};
+
+/// Which of the command's outputs a line was written to.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Stream {
+	Stdout,
+	Stderr,
+}
+
+impl Stream {
+	/// Write a line to this output of watchexec, and flush it.
+	pub fn write(self, line: &[u8]) -> io::Result<()> {
+		match self {
+			Self::Stdout => {
+				let mut out = io::stdout();
+				out.write_all(line).and_then(|_| out.flush())
+			}
+			Self::Stderr => {
+				let mut out = io::stderr();
+				out.write_all(line).and_then(|_| out.flush())
+			}
+		}
+	}
+}
+
+/// Collapses the output of a run into a single line when it's the same as the previous run's, for
+/// `--dedupe-output`.
+///
+/// Lines are written out as they come as soon as they differ from the previous run's. Until then,
+/// they're held back, and when the run's output has ended without any difference, they're
+/// dropped and replaced with a note. Only a hash of each line of the previous run is kept.
+#[derive(Debug)]
+pub struct Dedupe {
+	quiet: bool,
+	state: Mutex<Runs>,
+}
+
+impl Dedupe {
+	pub fn new(quiet: bool) -> Arc<Self> {
+		Arc::new(Self {
+			quiet,
+			state: Mutex::new(Runs::default()),
+		})
+	}
+
+	/// Start comparing the output of a new run, which has this many outputs to read from.
+	///
+	/// Lines still coming from an earlier run after that are written out as they are.
+	pub fn start(self: &Arc<Self>, outputs: usize, number: u64) -> DedupeRun {
+		let mut state = self.state.lock().expect("dedupe lock poisoned");
+		let held = state.start(outputs, number);
+		drop(state);
+		self.write(held);
+
+		DedupeRun {
+			dedupe: self.clone(),
+			generation: number,
+		}
+	}
+
+	fn write(&self, lines: Vec<(Stream, Vec<u8>)>) {
+		for (stream, line) in lines {
+			stream.write(&line).ok();
+		}
+	}
+}
+
+/// The output of one run, given to each task copying an output of the command.
+#[derive(Clone, Debug)]
+pub struct DedupeRun {
+	dedupe: Arc<Dedupe>,
+	generation: u64,
+}
+
+impl DedupeRun {
+	/// Write a line, or hold it back.
+	pub fn line(&self, stream: Stream, line: &[u8]) -> io::Result<()> {
+		let mut state = self.dedupe.state.lock().expect("dedupe lock poisoned");
+		if state.number != self.generation {
+			drop(state);
+			return stream.write(line);
+		}
+
+		let lines = state.line(stream, line);
+		drop(state);
+		for (stream, line) in lines {
+			stream.write(&line)?;
+		}
+		Ok(())
+	}
+
+	/// Mark one of the run's outputs as ended. Once they all have, the run is compared.
+	pub fn done(&self) {
+		let mut state = self.dedupe.state.lock().expect("dedupe lock poisoned");
+		if state.number != self.generation {
+			return;
+		}
+
+		match state.done() {
+			Some(Ended::Same) if !self.dedupe.quiet => {
+				eprintln!("[[Same output, run #{}]]", state.number);
+			}
+			Some(Ended::Held(lines)) => {
+				drop(state);
+				self.dedupe.write(lines);
+			}
+			Some(Ended::Same) | None => {}
+		}
+	}
+}
+
+/// How a run's output ended.
+#[derive(Debug, Eq, PartialEq)]
+enum Ended {
+	/// It was the same as the previous run's.
+	Same,
+
+	/// It differed (or there's no previous run), and these lines were still held back.
+	Held(Vec<(Stream, Vec<u8>)>),
+}
+
+#[derive(Debug, Default)]
+struct Runs {
+	/// The number of the current run, which also tells lines from earlier runs apart.
+	number: u64,
+
+	/// The hashes of the lines of the last run that ended, if any.
+	previous: Option<Vec<u64>>,
+
+	/// The hashes of the lines of the current run so far.
+	current: Vec<u64>,
+
+	/// The lines held back while the current run's output is the same as the previous run's.
+	held: Vec<(Stream, Vec<u8>)>,
+
+	/// Whether the current run's output has differed from the previous run's.
+	diverged: bool,
+
+	/// How many of the current run's outputs are still open.
+	open: usize,
+}
+
+impl Runs {
+	/// Start a new run, returning the held lines of the current run if it hadn't ended.
+	///
+	/// A run which hadn't ended isn't compared against, as its output may have been cut short.
+	fn start(&mut self, outputs: usize, number: u64) -> Vec<(Stream, Vec<u8>)> {
+		let held = if self.open > 0 {
+			match self.end(false) {
+				Ended::Held(lines) => lines,
+				Ended::Same => Vec::new(),
+			}
+		} else {
+			Vec::new()
+		};
+
+		self.number = number;
+		self.open = outputs;
+		held
+	}
+
+	/// Take a line of the current run, returning the lines to write out now.
+	fn line(&mut self, stream: Stream, line: &[u8]) -> Vec<(Stream, Vec<u8>)> {
+		let mut hasher = DefaultHasher::new();
+		(stream, line).hash(&mut hasher);
+		let hash = hasher.finish();
+
+		let index = self.current.len();
+		self.current.push(hash);
+
+		let same = self
+			.previous
+			.as_ref()
+			.map_or(false, |previous| previous.get(index) == Some(&hash));
+		if !self.diverged && same {
+			self.held.push((stream, line.to_vec()));
+			return Vec::new();
+		}
+
+		self.diverged = true;
+		let mut lines = take(&mut self.held);
+		lines.push((stream, line.to_vec()));
+		lines
+	}
+
+	/// Close one of the current run's outputs, ending the run if it was the last.
+	fn done(&mut self) -> Option<Ended> {
+		self.open = self.open.saturating_sub(1);
+		if self.open == 0 {
+			Some(self.end(true))
+		} else {
+			None
+		}
+	}
+
+	fn end(&mut self, complete: bool) -> Ended {
+		let current = take(&mut self.current);
+		let held = take(&mut self.held);
+		let diverged = take(&mut self.diverged);
+		let same = complete
+			&& !diverged
+			&& !current.is_empty()
+			&& self
+				.previous
+				.as_ref()
+				.map_or(false, |previous| previous.len() == current.len());
+
+		if complete {
+			self.previous = Some(current);
+		}
+
+		if same {
+			Ended::Same
+		} else {
+			Ended::Held(held)
+		}
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn collapses_same_output() {
+	let out = |line: &str| (Stream::Stdout, line.as_bytes().to_vec());
+	let mut runs = Runs::default();
+
+	assert_eq!(runs.start(1, 1), vec![]);
+	assert_eq!(
+		runs.line(Stream::Stdout, b"building\n"),
+		vec![out("building\n")]
+	);
+	assert_eq!(runs.line(Stream::Stdout, b"done\n"), vec![out("done\n")]);
+	assert_eq!(runs.done(), Some(Ended::Held(vec![])));
+
+	// same output: held back, then collapsed
+	runs.start(1, 2);
+	assert_eq!(runs.line(Stream::Stdout, b"building\n"), vec![]);
+	assert_eq!(runs.line(Stream::Stdout, b"done\n"), vec![]);
+	assert_eq!(runs.done(), Some(Ended::Same));
+
+	// differs partway: the held lines are written out with the first different one
+	runs.start(2, 3);
+	assert_eq!(runs.line(Stream::Stdout, b"building\n"), vec![]);
+	assert_eq!(
+		runs.line(Stream::Stderr, b"error\n"),
+		vec![out("building\n"), (Stream::Stderr, b"error\n".to_vec())]
+	);
+	assert_eq!(runs.line(Stream::Stdout, b"done\n"), vec![out("done\n")]);
+	assert_eq!(runs.done(), None);
+	assert_eq!(runs.done(), Some(Ended::Held(vec![])));
+
+	// shorter than the previous run: it's not the same, so what was held is written out
+	runs.start(1, 4);
+	assert_eq!(runs.line(Stream::Stdout, b"building\n"), vec![]);
+	assert_eq!(runs.done(), Some(Ended::Held(vec![out("building\n")])));
+}
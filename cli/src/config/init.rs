@@ -1,28 +1,82 @@
-use std::convert::Infallible;
+use std::error::Error;
 
 use clap::ArgMatches;
 use miette::Result;
-use watchexec::{config::InitConfig, handler::SyncFnHandler};
+use watchexec::{
+	config::InitConfig,
+	error::{CriticalError, RuntimeError},
+	handler::Handler,
+};
 
-pub fn init(_args: &ArgMatches<'static>) -> Result<InitConfig> {
+pub fn init(args: &ArgMatches<'static>) -> Result<InitConfig> {
 	let mut config = InitConfig::default();
-	config.on_error(SyncFnHandler::from(
-		|data| -> std::result::Result<(), Infallible> {
-			// if let RuntimeError::IoError { .. } = data {
-			// 	// these are often spurious, so condemn them to -v only
-			// 	error!("{}", data);
-			// 	return Ok(());
-			// }
-
-			if cfg!(debug_assertions) {
-				eprintln!("[[{:?}]]", data);
-			} else {
-				eprintln!("[[{}]]", data);
+	config.on_error(ErrorHandler {
+		quiet: args.is_present("quiet"),
+		fail_fast: args.is_present("fail-fast") || args.is_present("once"),
+	});
+
+	Ok(config)
+}
+
+struct ErrorHandler {
+	quiet: bool,
+	fail_fast: bool,
+}
+
+impl Handler<RuntimeError> for ErrorHandler {
+	fn handle(&mut self, data: RuntimeError) -> std::result::Result<(), Box<dyn Error>> {
+		if let RuntimeError::FsWatcherTooManyWatches { .. } = data {
+			if !self.quiet {
+				eprintln!("{}", too_many_watches_help());
 			}
 
-			Ok(())
-		},
-	));
+			return Err(Box::new(CriticalError::Elevated(data)));
+		}
 
-	Ok(config)
+		if let RuntimeError::CommandSpawn { .. } = data {
+			if !self.quiet {
+				eprintln!("[[Error: {}]]", data);
+			}
+
+			// with --once there won't be a completion to exit on
+			return if self.fail_fast {
+				Err(Box::new(CriticalError::Elevated(data)))
+			} else {
+				Ok(())
+			};
+		}
+
+		if self.quiet {
+			return Ok(());
+		}
+
+		// if let RuntimeError::IoError { .. } = data {
+		// 	// these are often spurious, so condemn them to -v only
+		// 	error!("{}", data);
+		// 	return Ok(());
+		// }
+
+		if cfg!(debug_assertions) {
+			eprintln!("[[{:?}]]", data);
+		} else {
+			eprintln!("[[{}]]", data);
+		}
+
+		Ok(())
+	}
+}
+
+fn too_many_watches_help() -> String {
+	let current = std::fs::read_to_string("/proc/sys/fs/inotify/max_user_watches")
+		.map(|max| format!(" (currently {})", max.trim()))
+		.unwrap_or_default();
+
+	format!(
+		"[[Error: the limit on watched files was reached, so changes would be missed.]]\n\
+		On Linux, that limit is the fs.inotify.max_user_watches sysctl{}. To raise it:\n\
+		\n    sudo sysctl fs.inotify.max_user_watches=524288\n\
+		\nand add `fs.inotify.max_user_watches=524288` to /etc/sysctl.conf to keep it after a reboot.\n\
+		Otherwise, watch less (with --watch, --max-depth, or ignores), or use --force-poll instead.",
+		current
+	)
 }
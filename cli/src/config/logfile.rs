@@ -0,0 +1,116 @@
+use std::{
+	fs::{remove_file, rename, File, OpenOptions},
+	io::{self, Write},
+	path::{Path, PathBuf},
+};
+
+use tracing::debug;
+
+/// How many rotated logs are kept, as `path.1` (the most recent) to `path.5`.
+const KEEP_ROTATED: usize = 5;
+
+/// A log file for the command's output, from `--log-file`.
+///
+/// With a maximum size, the log is rotated before a write would make it larger than that: it's
+/// renamed to `path.1`, the previous `path.1` to `path.2`, and so on, and a new log is started.
+/// A single line larger than the maximum is still written whole, to a fresh log.
+#[derive(Debug)]
+pub struct LogFile {
+	path: PathBuf,
+	max_size: Option<u64>,
+	file: File,
+	size: u64,
+}
+
+impl LogFile {
+	/// Open the log for appending, creating it if needed.
+	pub fn open(path: impl Into<PathBuf>, max_size: Option<u64>) -> io::Result<Self> {
+		let path = path.into();
+		let file = OpenOptions::new().create(true).append(true).open(&path)?;
+		let size = file.metadata()?.len();
+		Ok(Self {
+			path,
+			max_size,
+			file,
+			size,
+		})
+	}
+
+	/// Append output to the log, rotating it first if needed.
+	pub fn write(&mut self, output: &[u8]) -> io::Result<()> {
+		let len = output.len() as u64;
+		if let Some(max) = self.max_size {
+			if self.size > 0 && self.size + len > max {
+				self.rotate()?;
+			}
+		}
+
+		self.file.write_all(output)?;
+		self.size += len;
+		Ok(())
+	}
+
+	fn rotate(&mut self) -> io::Result<()> {
+		debug!(path=?self.path, size=self.size, "rotating log file");
+		match remove_file(rotated(&self.path, KEEP_ROTATED)) {
+			Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+			_ => {}
+		}
+
+		for n in (1..KEEP_ROTATED).rev() {
+			match rename(rotated(&self.path, n), rotated(&self.path, n + 1)) {
+				Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+				_ => {}
+			}
+		}
+
+		rename(&self.path, rotated(&self.path, 1))?;
+		self.file = OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(&self.path)?;
+		self.size = 0;
+		Ok(())
+	}
+}
+
+/// The path of the nth rotated log.
+fn rotated(path: &Path, n: usize) -> PathBuf {
+	let mut name = path.as_os_str().to_owned();
+	name.push(format!(".{}", n));
+	name.into()
+}
+
+#[cfg(test)]
+#[test]
+fn log_file_rotates() {
+	use std::fs::{create_dir_all, read_to_string, remove_dir_all};
+
+	let dir = std::env::temp_dir().join(format!("watchexec-log-{}", std::process::id()));
+	create_dir_all(&dir).unwrap();
+	let path = dir.join("out.log");
+
+	let mut log = LogFile::open(&path, Some(10)).unwrap();
+	for line in ["one\n", "two\n", "three\n", "a line longer than the max\n"] {
+		log.write(line.as_bytes()).unwrap();
+	}
+
+	assert_eq!(
+		read_to_string(&path).unwrap(),
+		"a line longer than the max\n"
+	);
+	assert_eq!(read_to_string(rotated(&path, 1)).unwrap(), "three\n");
+	assert_eq!(read_to_string(rotated(&path, 2)).unwrap(), "one\ntwo\n");
+	assert!(!rotated(&path, 3).exists());
+
+	// reopening carries on from the existing size
+	let mut log = LogFile::open(&path, Some(30)).unwrap();
+	log.write(b"four\n").unwrap();
+	assert_eq!(
+		read_to_string(rotated(&path, 1)).unwrap(),
+		"a line longer than the max\n"
+	);
+	assert_eq!(read_to_string(&path).unwrap(), "four\n");
+
+	remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,245 @@
+use std::{
+	fs::File,
+	io::{self, Write},
+	os::unix::io::{AsRawFd, FromRawFd, RawFd},
+	pin::Pin,
+	process::Stdio,
+	ptr,
+	sync::Mutex,
+	task::{Context, Poll},
+};
+
+use tokio::{
+	io::{AsyncRead, AsyncReadExt, ReadBuf},
+	signal::unix::{signal, SignalKind},
+};
+use tracing::{debug, warn};
+
+/// A pseudo-terminal for one run of the command, from `--pty`.
+///
+/// The command gets the terminal side as its stdout and stderr, so it sees it's writing to a
+/// terminal and keeps its colours and progress bars, while watchexec reads what it writes from the
+/// other side. Its stdin is left alone. As a terminal has a single output, stderr ends up mixed in
+/// with stdout.
+#[derive(Debug)]
+pub struct Pty {
+	master: File,
+	slave: File,
+}
+
+impl Pty {
+	/// Open a pseudo-terminal, the same size as watchexec's terminal if it's running in one.
+	pub fn open() -> io::Result<Self> {
+		let mut master = 0;
+		let mut slave = 0;
+		let mut size = terminal_size();
+
+		// SAFETY: on success the two descriptors are new, and owned by the files from then on
+		let (master, slave) = unsafe {
+			if libc::openpty(
+				&mut master,
+				&mut slave,
+				ptr::null_mut(),
+				ptr::null_mut(),
+				// the winsize is only read, but it's a *mut on some platforms
+				ptr::addr_of_mut!(size),
+			) != 0
+			{
+				return Err(io::Error::last_os_error());
+			}
+			(File::from_raw_fd(master), File::from_raw_fd(slave))
+		};
+
+		// openpty doesn't set these, and neither side should leak into other processes: the
+		// command gets copies of the terminal side as its stdout and stderr
+		set_cloexec(master.as_raw_fd())?;
+		set_cloexec(slave.as_raw_fd())?;
+
+		// pass newlines through as is rather than as \r\n: if watchexec's stdout is a terminal, it
+		// does that itself, and if it's not, the output shouldn't have carriage returns added
+		// SAFETY: tcgetattr fully initialises the termios when it succeeds
+		unsafe {
+			let mut termios = std::mem::zeroed::<libc::termios>();
+			if libc::tcgetattr(slave.as_raw_fd(), &mut termios) == 0 {
+				termios.c_oflag &= !libc::ONLCR;
+				libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios);
+			}
+		}
+
+		Ok(Self { master, slave })
+	}
+
+	/// The stdout and stderr to give the command.
+	pub fn stdio(&self) -> io::Result<(Stdio, Stdio)> {
+		Ok((
+			self.slave.try_clone()?.into(),
+			self.slave.try_clone()?.into(),
+		))
+	}
+
+	/// Close watchexec's copy of the terminal side, keeping the side the output is read from.
+	///
+	/// Once the command is spawned, this must be dropped for the output to end when it exits.
+	pub fn into_master(self) -> File {
+		self.master
+	}
+}
+
+fn set_cloexec(fd: RawFd) -> io::Result<()> {
+	// SAFETY: only changes the flags of a descriptor we own
+	if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+		Err(io::Error::last_os_error())
+	} else {
+		Ok(())
+	}
+}
+
+/// The size of the terminal watchexec is running in, or 80x24 if it isn't.
+fn terminal_size() -> libc::winsize {
+	for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO, libc::STDIN_FILENO] {
+		let mut size = libc::winsize {
+			ws_row: 0,
+			ws_col: 0,
+			ws_xpixel: 0,
+			ws_ypixel: 0,
+		};
+		// SAFETY: TIOCGWINSZ only writes to the winsize
+		if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0 {
+			return size;
+		}
+	}
+
+	libc::winsize {
+		ws_row: 24,
+		ws_col: 80,
+		ws_xpixel: 0,
+		ws_ypixel: 0,
+	}
+}
+
+/// The pseudo-terminal of the latest run, which watchexec's terminal size is forwarded to.
+///
+/// The command's terminal isn't its controlling terminal (that would take it out of its process
+/// group), so the kernel doesn't signal it when the size changes, and that's done here too.
+#[derive(Debug, Default)]
+pub struct Resizer {
+	master: Mutex<Option<File>>,
+	output: Mutex<Option<File>>,
+	process: Mutex<Option<(u32, bool)>>,
+}
+
+impl Resizer {
+	/// Start forwarding to the terminal of a new run.
+	pub fn set_master(&self, master: File) -> io::Result<()> {
+		*self.output.lock().expect("pty lock poisoned") = Some(master.try_clone()?);
+		*self.master.lock().expect("pty lock poisoned") = Some(master);
+		Ok(())
+	}
+
+	/// Take the output of the new run, to read it once it's spawned.
+	pub fn take_output(&self) -> Option<PtyOutput> {
+		let output = self.output.lock().expect("pty lock poisoned").take();
+		output.map(PtyOutput::new)
+	}
+
+	/// Set the process (or process group) of the new run, to signal when the size changes.
+	pub fn set_process(&self, id: u32, grouped: bool) {
+		*self.process.lock().expect("pty lock poisoned") = Some((id, grouped));
+	}
+
+	/// Forward changes of watchexec's terminal size until the end.
+	pub async fn forward(&self) {
+		let mut winch = match signal(SignalKind::window_change()) {
+			Ok(winch) => winch,
+			Err(err) => {
+				warn!(%err, "cannot listen for terminal resizes");
+				return;
+			}
+		};
+
+		while winch.recv().await.is_some() {
+			if let Some(master) = &*self.master.lock().expect("pty lock poisoned") {
+				let size = terminal_size();
+				debug!(rows=%size.ws_row, cols=%size.ws_col, "resizing command terminal");
+				// SAFETY: TIOCSWINSZ only reads the winsize
+				if unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &size) } == -1 {
+					warn!(err=%io::Error::last_os_error(), "cannot resize command terminal");
+					continue;
+				}
+			}
+
+			if let Some((id, grouped)) = *self.process.lock().expect("pty lock poisoned") {
+				let pid = if grouped { -(id as i32) } else { id as i32 };
+				// SAFETY: sending a signal, which the process may not be around for anymore
+				unsafe { libc::kill(pid, libc::SIGWINCH) };
+			}
+		}
+	}
+}
+
+/// Reads the command's output from its side of a [`Pty`].
+///
+/// Once the command has exited and the terminal side is closed, reading fails (with `EIO` on
+/// Linux) instead of returning the end of the output, which this turns into the end.
+#[derive(Debug)]
+pub struct PtyOutput(tokio::fs::File);
+
+impl PtyOutput {
+	pub fn new(master: File) -> Self {
+		Self(tokio::fs::File::from_std(master))
+	}
+
+	/// Copy the output to watchexec's stdout as it comes, without waiting for whole lines.
+	pub async fn copy_to_stdout(mut self) {
+		let mut buf = vec![0; 4096];
+		loop {
+			match self.read(&mut buf).await {
+				Ok(0) => break,
+				Ok(n) => {
+					let mut out = io::stdout();
+					if let Err(err) = out.write_all(&buf[..n]).and_then(|_| out.flush()) {
+						warn!(%err, "cannot write command output");
+						break;
+					}
+				}
+				Err(err) => {
+					warn!(%err, "cannot read command output");
+					break;
+				}
+			}
+		}
+	}
+}
+
+impl AsyncRead for PtyOutput {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut ReadBuf<'_>,
+	) -> Poll<io::Result<()>> {
+		match Pin::new(&mut self.0).poll_read(cx, buf) {
+			Poll::Ready(Err(err)) if err.raw_os_error() == Some(libc::EIO) => Poll::Ready(Ok(())),
+			poll => poll,
+		}
+	}
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn command_sees_a_terminal() {
+	let pty = Pty::open().unwrap();
+	let (stdout, stderr) = pty.stdio().unwrap();
+	let mut child = tokio::process::Command::new("sh")
+		.arg("-c")
+		.arg("test -t 1 && echo out && echo err >&2")
+		.stdout(stdout)
+		.stderr(stderr)
+		.spawn()
+		.unwrap();
+	let mut output = PtyOutput::new(pty.into_master());
+
+	assert!(child.wait().await.unwrap().success());
+	let mut text = String::new();
+	output.read_to_string(&mut text).await.unwrap();
+	assert_eq!(text, "out\nerr\n");
+}
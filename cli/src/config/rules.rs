@@ -0,0 +1,161 @@
+use std::{env::current_dir, ffi::OsStr};
+
+use clap::ArgMatches;
+use miette::{miette, IntoDiagnostic, Result};
+use tracing::debug;
+use watchexec::{action::Outcome, event::Event, filter::globset::GlobsetFilterer};
+
+use crate::filterer::{route, split_extensions};
+
+/// A command to run only for changes to files with some extensions, from `--on-change`.
+#[derive(Debug)]
+pub struct Rule {
+	pub command: Vec<String>,
+	filterer: GlobsetFilterer,
+}
+
+impl Rule {
+	/// Whether any of the paths in the events are for this rule.
+	fn matches(&self, events: &[Event]) -> bool {
+		events
+			.iter()
+			.any(|event| !self.filterer.passing_paths(event).is_empty())
+	}
+
+	/// The events, with only the paths that are for this rule.
+	///
+	/// Events without paths are kept as they are.
+	pub fn route(&self, events: &[Event]) -> Vec<Event> {
+		route(&self.filterer, events)
+	}
+}
+
+/// Parse the `--on-change` rules, in the order they were given.
+///
+/// Each is a list of extensions, separated by commas or spaces as for `--exts`, then a colon, then
+/// the command.
+pub async fn rules(args: &ArgMatches<'static>) -> Result<Vec<Rule>> {
+	let no_shell = args.is_present("no-shell")
+		|| args
+			.value_of("shell")
+			.map_or(false, |s| s.eq_ignore_ascii_case("none"));
+	let origin = current_dir().into_diagnostic()?;
+
+	let mut rules = Vec::new();
+	for rule in args.values_of("on-change").unwrap_or_default() {
+		let (exts, command) = rule
+			.split_once(':')
+			.filter(|(exts, command)| !exts.is_empty() && !command.trim().is_empty())
+			.ok_or_else(|| {
+				miette!(
+					"--on-change expects `EXTENSIONS:COMMAND`, like `rs:cargo test`, got '{}'",
+					rule
+				)
+			})?;
+
+		let mut builder = GlobsetFilterer::builder(&origin);
+		for ext in split_extensions("--on-change", OsStr::new(exts))? {
+			builder.extension(ext, None);
+		}
+		builder.extensions_case_insensitive(args.is_present("ignore-case"));
+
+		let command = if no_shell {
+			command.split_whitespace().map(String::from).collect()
+		} else {
+			vec![command.to_owned()]
+		};
+
+		debug!(?exts, ?command, "adding command rule");
+		rules.push(Rule {
+			command,
+			filterer: builder.build().await.into_diagnostic()?,
+		});
+	}
+
+	Ok(rules)
+}
+
+/// The outcome which starts the commands for these events.
+///
+/// Without rules, that's the main command. Otherwise, every rule with a path in the events runs,
+/// one after the other in the order they were given. If there are no paths at all, as for the first
+/// run at startup, all of them run.
+pub fn start(rules: &[Rule], events: &[Event]) -> Outcome {
+	if rules.is_empty() {
+		return Outcome::Start;
+	}
+
+	let has_paths = events.iter().any(|event| event.paths().next().is_some());
+	rules
+		.iter()
+		.filter(|rule| !has_paths || rule.matches(events))
+		.map(|rule| Outcome::StartWith(rule.command.clone()))
+		.reduce(|all, next| Outcome::both(all, Outcome::wait(next)))
+		.unwrap_or(Outcome::DoNothing)
+}
+
+#[cfg(test)]
+async fn test_rule(ext: &str, command: &str) -> Rule {
+	Rule {
+		command: vec![command.into()],
+		filterer: GlobsetFilterer::builder("/")
+			.extension(ext, None)
+			.build()
+			.await
+			.unwrap(),
+	}
+}
+
+#[cfg(test)]
+fn test_event(paths: &[&str]) -> Event {
+	use watchexec::event::Tag;
+
+	Event::new(
+		paths
+			.iter()
+			.map(|path| Tag::Path {
+				path: path.into(),
+				file_type: None,
+			})
+			.collect(),
+		Default::default(),
+	)
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn start_runs_matching_rules_in_order() {
+	let rules = vec![
+		test_rule("rs", "cargo test").await,
+		test_rule("ts", "npm run build").await,
+		test_rule("rs", "cargo doc").await,
+	];
+
+	assert_eq!(
+		start(&rules, &[test_event(&["/src/main.rs"])]),
+		Outcome::both(
+			Outcome::StartWith(vec!["cargo test".into()]),
+			Outcome::wait(Outcome::StartWith(vec!["cargo doc".into()]))
+		)
+	);
+	assert_eq!(
+		start(&rules, &[test_event(&["/src/app.ts"])]),
+		Outcome::StartWith(vec!["npm run build".into()])
+	);
+	assert_eq!(
+		start(&rules, &[test_event(&["/README.md"])]),
+		Outcome::DoNothing
+	);
+	assert_eq!(start(&[], &[test_event(&["/README.md"])]), Outcome::Start);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn route_keeps_only_the_rule_paths() {
+	let rule = test_rule("rs", "cargo test").await;
+	let routed = rule.route(&[test_event(&["/src/main.rs", "/src/app.ts"])]);
+	assert_eq!(
+		routed[0].paths().map(|(path, _)| path).collect::<Vec<_>>(),
+		vec![std::path::Path::new("/src/main.rs")]
+	);
+}
@@ -1,54 +1,230 @@
-use std::{convert::Infallible, env::current_dir, path::Path, str::FromStr, time::Duration};
+use std::{
+	convert::Infallible,
+	env::{current_dir, var},
+	fs::OpenOptions,
+	io::{self, Write},
+	mem::take,
+	path::{Path, PathBuf},
+	process::Stdio,
+	str::FromStr,
+	sync::{
+		atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicUsize, Ordering},
+		Arc, Mutex,
+	},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use clap::ArgMatches;
-use miette::{IntoDiagnostic, Result};
+use miette::{miette, Context, IntoDiagnostic, Result};
 use notify_rust::Notification;
+use serde_json::json;
+use tokio::{
+	io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
+	process::ChildStdin,
+	spawn,
+	sync::mpsc,
+	time::sleep,
+};
+use tracing::{debug, warn};
 use watchexec::{
-	action::{Action, Outcome, PostSpawn, PreSpawn},
+	action::{Action, Outcome, PostSpawn, PreSpawn, ThrottleMode},
 	command::Shell,
 	config::RuntimeConfig,
-	event::ProcessEnd,
+	event::{
+		filekind::{FileEventKind, ModifyKind},
+		Event, ProcessEnd, Source, Tag,
+	},
 	fs::Watcher,
 	handler::SyncFnHandler,
-	paths::summarise_events_to_env,
+	paths::{
+		changed_paths, summarise_events_to_env_with_separator, symlink_targets, PATH_SEPARATOR,
+	},
 	signal::{process::SubSignal, source::MainSignal},
 };
 
-pub fn runtime(args: &ArgMatches<'static>) -> Result<RuntimeConfig> {
+use crate::filterer::{WatchedFiles, WatchexecFilterer};
+
+#[cfg(unix)]
+use super::pty::{Pty, Resizer};
+use super::{
+	dedupe::{Dedupe, DedupeRun, Stream},
+	logfile::LogFile,
+	rules::{self, Rule},
+};
+
+pub fn runtime(
+	args: &ArgMatches<'static>,
+	exit_code: Arc<AtomicI32>,
+	runs: Arc<AtomicU64>,
+	retries: mpsc::Sender<Event>,
+	rules: Vec<Rule>,
+	filterer: Option<Arc<WatchexecFilterer>>,
+) -> Result<RuntimeConfig> {
 	let mut config = RuntimeConfig::default();
+	let quiet = args.is_present("quiet");
+	// only checking the options: files are left alone, and nothing is started
+	let print_config = args.is_present("print-config-and-exit");
 
-	config.command(
-		args.values_of_lossy("command")
-			.expect("(clap) Bug: command is not present")
-			.iter(),
-	);
+	// the command is only optional when emitting events, or when given as rules
+	let command = args.values_of_lossy("command").unwrap_or_default();
+	let has_command = !command.is_empty() || !rules.is_empty();
+	let has_placeholder = command
+		.iter()
+		.chain(rules.iter().flat_map(|rule| &rule.command))
+		.any(|part| has_placeholders(part));
+	config.command(command.iter());
+
+	let mut emit_events: Option<Box<dyn Write + Send>> = match args.value_of("emit-events-to") {
+		None => None,
+		Some("stdout") => Some(Box::new(io::stdout())),
+		Some(target) => {
+			let path = target
+				.strip_prefix("file:")
+				.ok_or_else(|| miette!("--emit-events-to expects `stdout` or `file:PATH`"))?;
+			if print_config {
+				None
+			} else {
+				let file = OpenOptions::new()
+					.create(true)
+					.append(true)
+					.open(path)
+					.into_diagnostic()
+					.wrap_err_with(|| format!("--emit-events-to: cannot open '{}'", path))?;
+				Some(Box::new(file))
+			}
+		}
+	};
+
+	let mut pathset: Vec<PathBuf> = match args.values_of_os("paths") {
+		Some(paths) => {
+			let paths: Vec<PathBuf> = paths
+				.map(|os| Path::new(os).to_owned())
+				.filter(|path| match dunce::canonicalize(path) {
+					Ok(_) => true,
+					Err(err) => {
+						if !quiet {
+							eprintln!("[[Warning: not watching {}: {}]]", path.display(), err);
+						}
+						false
+					}
+				})
+				.collect();
 
-	config.pathset(match args.values_of_os("paths") {
-		Some(paths) => paths.map(|os| Path::new(os).to_owned()).collect(),
+			if paths.is_empty() {
+				return Err(miette!("none of the paths to watch are valid"));
+			}
+
+			paths
+		}
 		None => vec![current_dir().into_diagnostic()?],
-	});
+	};
+
+	// files are watched through their parent directories, see WatchedFiles
+	let files = WatchedFiles::new(args);
+	pathset.retain(|path| !path.is_file());
+
+	if args.is_present("follow-symlinks") {
+		let targets = symlink_targets(&pathset);
+		debug!(?targets, "also watching symlink targets");
+		pathset.extend(targets);
+	}
+
+	let parents = files.parents();
+	debug!(?parents, "watching directories of files on their own");
+	config.pathset(pathset);
+	config.pathset_non_recursive(parents);
+
+	if let Some(depth) = args.value_of("max-depth") {
+		let depth: usize = depth
+			.parse()
+			.into_diagnostic()
+			.wrap_err("--max-depth expects a whole number")?;
+		if depth == 0 {
+			return Err(miette!("--max-depth must be at least 1"));
+		}
+		debug!(?depth, "limiting watch depth");
+		config.pathset_max_depth(Some(depth));
+	}
 
-	config.action_throttle(Duration::from_millis(
+	let debounce = Duration::from_millis(
 		args.value_of("debounce")
 			.unwrap_or("100")
 			.parse()
-			.into_diagnostic()?,
-	));
+			.into_diagnostic()
+			.wrap_err("--debounce expects a whole number of milliseconds")?,
+	);
+	debug!(?debounce, "effective debounce");
+	config.action_throttle(debounce);
 
-	if let Some(interval) = args.value_of("poll") {
-		config.file_watcher(Watcher::Poll(Duration::from_millis(
-			interval.parse().into_diagnostic()?,
-		)));
+	config.action_throttle_mode(match args.value_of("debounce-mode") {
+		Some("trailing") => ThrottleMode::Trailing,
+		Some("adaptive") => ThrottleMode::Adaptive,
+		_ => ThrottleMode::Fixed,
+	});
+
+	if let Some(max) = args.value_of("debounce-max") {
+		let max = Duration::from_millis(
+			max.parse()
+				.into_diagnostic()
+				.wrap_err("--debounce-max expects a whole number of milliseconds")?,
+		);
+		debug!(?max, "effective debounce maximum");
+		config.action_throttle_max(max);
 	}
 
+	if let Some(growth) = args.value_of("debounce-growth") {
+		let growth: f64 = growth
+			.parse()
+			.into_diagnostic()
+			.wrap_err("--debounce-growth expects a number")?;
+		if growth < 1.0 || !growth.is_finite() {
+			return Err(miette!("--debounce-growth must be at least 1"));
+		}
+		debug!(?growth, "effective adaptive debounce growth");
+		config.action_throttle_growth(growth);
+	}
+
+	config.action_drop_missing(args.is_present("drop-missing"));
+
+	config.command_stop_signal(
+		args.value_of("stop-signal")
+			.map(SubSignal::from_str)
+			.transpose()
+			.into_diagnostic()?
+			.unwrap_or(SubSignal::Terminate),
+	);
+
+	config.command_stop_timeout(Duration::from_millis(
+		args.value_of("stop-timeout")
+			.unwrap_or("250")
+			.parse()
+			.into_diagnostic()
+			.wrap_err("--stop-timeout expects a whole number of milliseconds")?,
+	));
+
+	let watcher = if let Some(interval) = args.value_of("poll") {
+		Watcher::Poll(Duration::from_millis(
+			interval
+				.parse()
+				.into_diagnostic()
+				.wrap_err("--force-poll expects a whole number of milliseconds")?,
+		))
+	} else {
+		Watcher::Native
+	};
+	debug!(?watcher, "file watcher backend");
+	config.file_watcher(watcher);
+
 	if args.is_present("no-process-group") {
 		config.command_grouped(false);
 	}
 
-	config.command_shell(if args.is_present("no-shell") {
+	let shell = if args.is_present("no-shell") {
 		Shell::None
 	} else if let Some(s) = args.value_of("shell") {
-		if s.eq_ignore_ascii_case("powershell") {
+		if s.trim().is_empty() {
+			return Err(miette!("--shell expects a shell program, or `none`"));
+		} else if s.eq_ignore_ascii_case("powershell") {
 			Shell::Powershell
 		} else if s.eq_ignore_ascii_case("none") {
 			Shell::None
@@ -59,10 +235,67 @@ pub fn runtime(args: &ArgMatches<'static>) -> Result<RuntimeConfig> {
 		}
 	} else {
 		default_shell()
-	});
+	};
+	config.command_shell(shell.clone());
+
+	// only a warning: the program may well take e.g. `<` as an argument
+	if let (Shell::None, false) = (&shell, quiet) {
+		if let Some(op) = command
+			.iter()
+			.chain(rules.iter().flat_map(|rule| &rule.command))
+			.find(|part| is_shell_operator(part))
+		{
+			eprintln!(
+				"[[Warning: `{}` looks like a shell operator, but with --shell=none it's passed to the program as a plain argument; drop --shell=none (or -n) to use pipes, redirects, and command lists]]",
+				op
+			);
+		}
+	}
 
-	let clear = args.is_present("clear");
+	let workdir = match args.value_of_os("workdir") {
+		Some(dir) => {
+			let dir = dunce::canonicalize(dir)
+				.into_diagnostic()
+				.wrap_err_with(|| {
+					format!("--workdir: cannot use '{}'", Path::new(dir).display())
+				})?;
+			if !dir.is_dir() {
+				return Err(miette!("--workdir: '{}' is not a directory", dir.display()));
+			}
+			debug!(?dir, "command working directory");
+			Some(dir)
+		}
+		None => None,
+	};
+
+	let clear_mode = args.value_of("clear-mode");
+	let clear_asked = args.is_present("clear") || clear_mode.is_some();
+	let clear = clear_asked && !quiet && atty::is(atty::Stream::Stdout);
+	if clear_asked && !clear && !quiet {
+		debug!("stdout is not a terminal, not clearing the screen");
+	}
+	let clear_on_success = clear_mode == Some("success");
+	let clear_outcome = if clear_mode == Some("scrollback") {
+		Outcome::ClearScrollback
+	} else {
+		Outcome::Clear
+	};
+	let mut last_success = true;
 	let notif = args.is_present("notif");
+	let command_line = if rules.is_empty() {
+		command.join(" ")
+	} else {
+		rules
+			.iter()
+			.map(|rule| rule.command.join(" "))
+			.collect::<Vec<_>>()
+			.join("`, `")
+	};
+	let rules = Arc::new(rules);
+	let action_rules = rules.clone();
+	let mut last_start = Outcome::Start;
+	let run_paths = Arc::new(AtomicUsize::new(0));
+	let run_paths_spawn = run_paths.clone();
 	let mut on_busy = args
 		.value_of("on-busy-update")
 		.unwrap_or("queue")
@@ -76,6 +309,24 @@ pub fn runtime(args: &ArgMatches<'static>) -> Result<RuntimeConfig> {
 		on_busy = "do-nothing".into();
 	}
 
+	// an explicit --signal means the user wants it sent, rather than the default queue/restart
+	if args.occurrences_of("signal") > 0 && !args.is_present("on-busy-update") {
+		if args.is_present("restart") && !quiet {
+			eprintln!("[[Warning: --signal takes precedence over --restart, the command will be signaled instead of restarted]]");
+		}
+
+		on_busy = "signal".into();
+	}
+
+	// changes go to the command's stdin, and if it closes that, it's restarted instead
+	let stdin_events = args.is_present("stdin-events");
+	if stdin_events {
+		on_busy = "restart".into();
+	}
+	let feed: StdinFeed = Default::default();
+	let feed_spawn = feed.clone();
+	let feed_retries = retries.clone();
+
 	let mut signal = args
 		.value_of("signal")
 		.map(SubSignal::from_str)
@@ -87,8 +338,66 @@ pub fn runtime(args: &ArgMatches<'static>) -> Result<RuntimeConfig> {
 		signal = SubSignal::ForceStop;
 	}
 
+	let max_retries: u32 = args
+		.value_of("retry")
+		.unwrap_or("0")
+		.parse()
+		.into_diagnostic()
+		.wrap_err("--retry expects a whole number")?;
+	let retry_delay = Duration::from_millis(
+		args.value_of("retry-delay")
+			.unwrap_or("0")
+			.parse()
+			.into_diagnostic()
+			.wrap_err("--retry-delay expects a whole number of milliseconds")?,
+	);
+	let timeout = args
+		.value_of("timeout")
+		.map(|secs| match secs.parse() {
+			Ok(0) | Err(_) => Err(miette!(
+				"--timeout expects a whole number of seconds, at least 1"
+			)),
+			Ok(secs) => Ok(Duration::from_secs(secs)),
+		})
+		.transpose()?;
+	let timeouts = retries.clone();
+	let runs_action = runs.clone();
+	let mut timed_out = false;
+	let min_interval = args
+		.value_of("min-interval")
+		.map(|ms| {
+			ms.parse()
+				.into_diagnostic()
+				.wrap_err("--min-interval expects a whole number of milliseconds")
+		})
+		.transpose()?
+		.map(Duration::from_millis)
+		.filter(|interval| !interval.is_zero());
+	debug!(?min_interval, "effective minimum interval between runs");
+	let mut last_end: Option<Instant> = None;
+	let held: Arc<Mutex<Vec<Event>>> = Default::default();
+	let running = Arc::new(AtomicBool::new(false));
+	let running_spawn = running.clone();
+	let last_spawn: Arc<Mutex<Option<Instant>>> = Default::default();
+	let last_spawn_pre = last_spawn.clone();
+
+	let mut retry_attempt = 0;
+	let mut retry_pending: Option<usize> = None;
+	let mut retries_scheduled: usize = 0;
+
 	let print_events = args.is_present("print-events");
 	let once = args.is_present("once");
+	let restart_on_exit = args.is_present("restart-on-exit");
+	// a shell reports a command it can't find or run with these, instead of failing to spawn
+	let fail_fast_codes: &[i32] = match (args.is_present("fail-fast"), &shell) {
+		(true, Shell::None) | (false, _) => &[],
+		(true, _) => &[126, 127],
+	};
+	let mut started = false;
+	let loop_guard = args.is_present("loop-guard");
+	let mut loop_streak = 0;
+	let mut loop_paths: Vec<PathBuf> = Vec::new();
+	let mut run_ended: Option<Instant> = None;
 
 	config.on_action(move |action: Action| {
 		let fut = async { Ok::<(), Infallible>(()) };
@@ -99,9 +408,19 @@ pub fn runtime(args: &ArgMatches<'static>) -> Result<RuntimeConfig> {
 			}
 		}
 
-		if once {
-			action.outcome(Outcome::both(Outcome::Start, Outcome::wait(Outcome::Exit)));
-			return fut;
+		if let Some(out) = emit_events.as_mut() {
+			for line in action.events.iter().filter_map(event_json) {
+				if let Err(err) = writeln!(out, "{}", line).and_then(|_| out.flush()) {
+					if !quiet {
+						eprintln!("[[Warning: cannot emit event: {}]]", err);
+					}
+				}
+			}
+		}
+
+		let paths = changed_paths(action.events.iter());
+		if !paths.is_empty() {
+			debug!(?paths, "{} paths changed", paths.len());
 		}
 
 		let signals: Vec<MainSignal> = action.events.iter().flat_map(|e| e.signals()).collect();
@@ -112,16 +431,87 @@ pub fn runtime(args: &ArgMatches<'static>) -> Result<RuntimeConfig> {
 			.next()
 			.is_some();
 
+		// a completion can come in along with changes, which skips handling it below; one from the
+		// command being killed, like when restarting, comes in late and isn't the current run's
+		if action
+			.events
+			.iter()
+			.flat_map(|e| e.completions())
+			.any(|end| !matches!(end, Some(ProcessEnd::ExitSignal(_))))
+		{
+			run_ended = Some(Instant::now());
+		}
+
+		// forward the signal to the command, so it can shut down as if it had received it directly
 		if signals.contains(&MainSignal::Terminate) {
-			action.outcome(Outcome::both(Outcome::Stop, Outcome::Exit));
+			action.outcome(Outcome::both(
+				Outcome::StopWith(SubSignal::Terminate),
+				Outcome::Exit,
+			));
 			return fut;
 		}
 
 		if signals.contains(&MainSignal::Interrupt) {
-			action.outcome(Outcome::both(Outcome::Stop, Outcome::Exit));
+			action.outcome(Outcome::both(
+				Outcome::StopWith(SubSignal::Interrupt),
+				Outcome::Exit,
+			));
 			return fut;
 		}
 
+		if !has_command {
+			action.outcome(Outcome::DoNothing);
+			return fut;
+		}
+
+		let start = rules::start(&action_rules, &action.events);
+
+		if has_paths && (retry_pending.is_some() || retry_attempt > 0) {
+			debug!("new changes, abandoning retries");
+			retry_attempt = 0;
+			retry_pending = None;
+		}
+
+		// looked at before anything else, as the changes batched along with it would otherwise wait
+		// for, or be ignored because of, a command that may never end by itself
+		if let Some(current) = timeout_for(&action.events, runs_action.load(Ordering::Relaxed)) {
+			// a run that has ended or was replaced since doesn't need stopping
+			let stop = current && running.load(Ordering::SeqCst);
+			if stop {
+				timed_out = true;
+				if !quiet {
+					eprintln!(
+						"[[Command timed out after {}s, stopping it]]",
+						timeout.unwrap_or_default().as_secs()
+					);
+				}
+			} else {
+				debug!("timeout is for a run that ended, ignoring");
+			}
+
+			if stop && has_paths {
+				debug!("handling the changes once the timed out command has stopped");
+				resend(
+					action
+						.events
+						.iter()
+						.filter(|e| e.paths().next().is_some())
+						.cloned()
+						.collect(),
+					&retries,
+				);
+			}
+
+			if stop || !has_paths {
+				action.outcome(if stop {
+					Outcome::if_running(Outcome::Stop, Outcome::DoNothing)
+				} else {
+					Outcome::DoNothing
+				});
+				return fut;
+			}
+		}
+
 		if !has_paths {
 			if !signals.is_empty() {
 				let mut out = Outcome::DoNothing;
@@ -133,6 +523,24 @@ pub fn runtime(args: &ArgMatches<'static>) -> Result<RuntimeConfig> {
 				return fut;
 			}
 
+			let retry = action
+				.events
+				.iter()
+				.find_map(|e| e.metadata.get("retry"))
+				.and_then(|ids| ids.first())
+				.map(|id| id.parse::<usize>().ok());
+			if let Some(id) = retry {
+				if id.is_some() && id == retry_pending {
+					retry_pending = None;
+					action.outcome(Outcome::if_running(Outcome::DoNothing, last_start.clone()));
+				} else {
+					debug!("retry was abandoned, not running");
+					action.outcome(Outcome::DoNothing);
+				}
+
+				return fut;
+			}
+
 			let completion = action.events.iter().flat_map(|e| e.completions()).next();
 			if let Some(status) = completion {
 				let (msg, printit) = match status {
@@ -153,19 +561,116 @@ pub fn runtime(args: &ArgMatches<'static>) -> Result<RuntimeConfig> {
 					None => ("Command completed".to_string(), false),
 				};
 
-				if printit {
+				let code = process_exit_code(status);
+				debug!(?status, %code, "{}", msg);
+				last_success = code == 0;
+
+				let not_run = fail_fast_codes.contains(&code);
+				// a command that hangs would likely hang again, so it's not retried
+				let timed_out = take(&mut timed_out);
+				if code != 0 && !not_run && !timed_out && retry_attempt < max_retries {
+					retry_attempt += 1;
+					retries_scheduled += 1;
+					retry_pending = Some(retries_scheduled);
+					debug!(
+						attempt=%retry_attempt,
+						max=%max_retries,
+						delay=?retry_delay,
+						"retrying failed command"
+					);
+
+					schedule_retry(&retries, retry_delay, retries_scheduled);
+					action.outcome(Outcome::DoNothing);
+					return fut;
+				}
+
+				retry_attempt = 0;
+				running.store(false, Ordering::SeqCst);
+				last_end = Some(Instant::now());
+				if let Some(interval) = min_interval {
+					if !held.lock().expect("held events lock poisoned").is_empty() {
+						release_held(&held, interval, &retries);
+					}
+				}
+
+				if printit && !quiet {
 					eprintln!("[[{}]]", msg);
 				}
 
 				if notif {
-					Notification::new()
-						.summary("Watchexec: command ended")
-						.body(&msg)
-						.show()
-						.map(drop)
-						.unwrap_or_else(|err| {
-							eprintln!("[[Failed to send desktop notification: {}]]", err);
-						});
+					let paths = run_paths.load(Ordering::SeqCst);
+					notify(
+						if code == 0 {
+							"Watchexec: command succeeded"
+						} else {
+							"Watchexec: command failed"
+						},
+						&format!(
+							"`{}`\n{} ({} changed {})",
+							command_line,
+							msg,
+							paths,
+							if paths == 1 { "path" } else { "paths" }
+						),
+					);
+				}
+
+				if once || not_run {
+					exit_code.store(code, Ordering::SeqCst);
+					action.outcome(Outcome::Exit);
+				} else {
+					if restart_on_exit {
+						// goes through the same path as retries, without counting as one, so that a
+						// change in the meantime takes over instead
+						retries_scheduled += 1;
+						retry_pending = Some(retries_scheduled);
+						debug!(delay=?retry_delay, "command exited, restarting it");
+						schedule_retry(&retries, retry_delay, retries_scheduled);
+					}
+
+					action.outcome(Outcome::DoNothing);
+				}
+
+				return fut;
+			}
+		}
+
+		if once {
+			// run a single time, then exit when the completion comes in (above)
+			if started {
+				action.outcome(Outcome::DoNothing);
+			} else {
+				started = true;
+				action.outcome(Outcome::if_running(Outcome::DoNothing, start));
+			}
+
+			return fut;
+		}
+
+		if let Some(interval) = min_interval {
+			// hold changes back until the interval has passed, then replay them as one run
+			let busy = running.load(Ordering::SeqCst);
+			let too_soon = last_end.map_or(false, |end| end.elapsed() < interval);
+			if (busy && on_busy == "queue") || (!busy && too_soon) {
+				let mut events = held.lock().expect("held events lock poisoned");
+				let first = events.is_empty();
+				events.extend(
+					action
+						.events
+						.iter()
+						.filter(|e| e.paths().next().is_some())
+						.cloned(),
+				);
+				drop(events);
+
+				debug!(
+					?interval,
+					"too soon after the last run, holding changes back"
+				);
+				if first && !busy {
+					let remaining = last_end
+						.map_or(Duration::ZERO, |end| interval.saturating_sub(end.elapsed()));
+					release_held(&held, remaining, &retries);
 				}
 
 				action.outcome(Outcome::DoNothing);
@@ -173,22 +678,112 @@ pub fn runtime(args: &ArgMatches<'static>) -> Result<RuntimeConfig> {
 			}
 		}
 
+		if start == Outcome::DoNothing {
+			debug!("no --on-change rule matches these changes, not running");
+			action.outcome(start);
+			return fut;
+		}
+
+		if stdin_events && has_paths && running.load(Ordering::SeqCst) {
+			let fed = feed
+				.lock()
+				.expect("stdin feed lock poisoned")
+				.as_ref()
+				.map_or(false, |feed| feed.send(action.events.clone()).is_ok());
+			if fed {
+				debug!("writing the changes to the command's stdin");
+				action.outcome(Outcome::if_running(Outcome::DoNothing, start));
+				return fut;
+			}
+
+			debug!("command closed its stdin, restarting it");
+		}
+
+		// changes seen before the command last started were already there for it to see, so they
+		// don't need another run: a burst of changes during a run queues exactly one more
+		if on_busy == "queue" && has_paths && action_rules.is_empty() {
+			let spawned = *last_spawn.lock().expect("last spawn lock poisoned");
+			let seen_before = |event: &Event| {
+				spawned
+					.zip(event.observed)
+					.map_or(false, |(spawned, observed)| observed.instant < spawned)
+			};
+			if action
+				.events
+				.iter()
+				.filter(|e| e.paths().next().is_some())
+				.all(seen_before)
+			{
+				debug!("changes predate the last run, not queueing another");
+				action.outcome(Outcome::DoNothing);
+				return fut;
+			}
+		}
+
+		if has_paths {
+			let spawned = *last_spawn.lock().expect("last spawn lock poisoned");
+			let own = self_triggered(&action.events, spawned, run_ended);
+			if own.is_empty() {
+				loop_streak = 0;
+			} else {
+				// it's only a loop if the same paths keep coming back
+				if own.iter().any(|path| loop_paths.contains(path)) {
+					loop_streak += 1;
+				} else {
+					loop_streak = 1;
+				}
+				debug!(?own, streak=%loop_streak, "changes were made by the last run");
+
+				if loop_streak == LOOP_RUNS && !quiet {
+					let mut paths: Vec<String> = own
+						.iter()
+						.take(3)
+						.map(|path| path.display().to_string())
+						.collect();
+					if own.len() > 3 {
+						paths.push(format!("{} more", own.len() - 3));
+					}
+
+					eprintln!(
+						"[[Warning: the last {} runs were each triggered by changes the command made itself, to {}: {}]]",
+						LOOP_RUNS,
+						paths.join(", "),
+						if loop_guard {
+							"pausing until something else changes"
+						} else {
+							"ignore those with -i, or use --loop-guard to pause"
+						}
+					);
+				}
+
+				loop_paths = own;
+				if loop_guard && loop_streak >= LOOP_RUNS {
+					debug!("loop guard: not running again for the command's own changes");
+					action.outcome(Outcome::DoNothing);
+					return fut;
+				}
+			}
+		}
+
+		last_start = start.clone();
+		let clear = clear && (last_success || !clear_on_success);
 		let when_running = match (clear, on_busy.as_str()) {
 			(_, "do-nothing") => Outcome::DoNothing,
-			(true, "restart") => {
-				Outcome::both(Outcome::Stop, Outcome::both(Outcome::Clear, Outcome::Start))
-			}
-			(false, "restart") => Outcome::both(Outcome::Stop, Outcome::Start),
+			(true, "restart") => Outcome::both(
+				Outcome::Stop,
+				Outcome::both(clear_outcome.clone(), start.clone()),
+			),
+			(false, "restart") => Outcome::both(Outcome::Stop, start.clone()),
 			(_, "signal") => Outcome::Signal(signal),
-			(true, "queue") => Outcome::wait(Outcome::both(Outcome::Clear, Outcome::Start)),
-			(false, "queue") => Outcome::wait(Outcome::Start),
+			(true, "queue") => Outcome::wait(Outcome::both(clear_outcome.clone(), start.clone())),
+			(false, "queue") => Outcome::wait(start.clone()),
 			_ => Outcome::DoNothing,
 		};
 
 		let when_idle = if clear {
-			Outcome::both(Outcome::Clear, Outcome::Start)
+			Outcome::both(clear_outcome.clone(), start)
 		} else {
-			Outcome::Start
+			start
 		};
 
 		action.outcome(Outcome::if_running(when_running, when_idle));
@@ -197,29 +792,245 @@ pub fn runtime(args: &ArgMatches<'static>) -> Result<RuntimeConfig> {
 	});
 
 	let no_env = args.is_present("no-environment");
-	config.on_pre_spawn(move |prespawn: PreSpawn| async move {
-		if !no_env {
-			let envs = summarise_events_to_env(prespawn.events.iter());
-			if let Some(mut command) = prespawn.command().await {
-				for (k, v) in envs {
-					command.env(format!("WATCHEXEC_{}_PATH", k), v);
+	// opt in to the same separator on every platform, for scripts shared between them
+	let path_separator = match var("WATCHEXEC_PATH_SEPARATOR") {
+		Ok(sep) if sep == "newline" => "\n".to_owned(),
+		Ok(sep) if !sep.is_empty() => sep,
+		_ => PATH_SEPARATOR.to_owned(),
+	};
+	let env_clear = args.is_present("env-clear");
+	let env_vars = args
+		.values_of("env")
+		.unwrap_or_default()
+		.map(|var| {
+			var.split_once('=')
+				.filter(|(key, _)| !key.is_empty())
+				.map(|(key, value)| (key.to_owned(), value.to_owned()))
+				.ok_or_else(|| miette!("--env expects `KEY=VALUE`, got '{}'", var))
+		})
+		.collect::<Result<Vec<_>>>()?;
+	let prefix = args.value_of("prefix").map(|label| format!("[{}] ", label));
+	let output_prefix = prefix.clone();
+
+	let log_max_size: Option<u64> = args
+		.value_of("log-max-size")
+		.map(|size| {
+			size.parse()
+				.into_diagnostic()
+				.wrap_err("--log-max-size expects a whole number of bytes")
+		})
+		.transpose()?;
+	let log_file = args
+		.value_of("log-file")
+		.filter(|_| !print_config)
+		.map(|path| {
+			LogFile::open(path, log_max_size)
+				.map(|log| Arc::new(Mutex::new(log)))
+				.into_diagnostic()
+				.wrap_err_with(|| format!("--log-file: cannot open '{}'", path))
+		})
+		.transpose()?;
+	let dedupe = args.is_present("dedupe-output").then(|| Dedupe::new(quiet));
+	let capture_output = prefix.is_some() || log_file.is_some() || dedupe.is_some();
+	let runs_post = runs.clone();
+
+	#[cfg(unix)]
+	let pty = (args.is_present("pty") && !print_config).then(|| {
+		let resizer = Arc::new(Resizer::default());
+		let forward = resizer.clone();
+		spawn(async move { forward.forward().await });
+		resizer
+	});
+	#[cfg(unix)]
+	let pty_post = pty.clone();
+	#[cfg(not(unix))]
+	if args.is_present("pty") && !quiet {
+		eprintln!(
+			"[[Warning: --pty is only supported on Unix, running the command without a terminal]]"
+		);
+	}
+
+	config.on_pre_spawn(move |prespawn: PreSpawn| {
+		let shell = shell.clone();
+		let workdir = workdir.clone();
+		let rules = rules.clone();
+		let filterer = filterer.clone();
+		let env_vars = env_vars.clone();
+		let path_separator = path_separator.clone();
+		#[cfg(unix)]
+		let pty = pty.clone();
+		*last_spawn_pre.lock().expect("last spawn lock poisoned") = Some(Instant::now());
+		runs.fetch_add(1, Ordering::Relaxed);
+		async move {
+			// give a rule's command only the changes that are for it, and the main command only
+			// those which passed the filters, as an event passes if any of its paths does
+			let events = match rules.iter().find(|rule| rule.command == prespawn.command) {
+				Some(rule) => rule.route(&prespawn.events),
+				None => match &filterer {
+					Some(filterer) => filterer.route(&prespawn.events),
+					None => prespawn.events.to_vec(),
+				},
+			};
+
+			if has_placeholder {
+				let paths = changed_paths(events.iter());
+				let trigger = events.iter().find_map(|event| {
+					let (path, _) = event.paths().next()?;
+					Some((event_op(event).unwrap_or("other"), path))
+				});
+				if paths.is_empty() && !quiet {
+					eprintln!("[[Warning: no paths changed for this run, so `{{}}`, `{{op}}`, and `{{path}}` in the command are replaced with nothing; write `{{{{}}}}`, `{{{{op}}}}`, or `{{{{path}}}}` for them as is, or use --postpone to not run at startup]]");
+				}
+				let cmd = substitute_paths(&prespawn.command, &paths, trigger, &shell);
+				debug!(?cmd, "substituted changed paths into command");
+				if !cmd.is_empty() {
+					if let Some(mut command) = prespawn.command().await {
+						*command = shell.to_command(&cmd);
+					}
 				}
 			}
-		}
 
-		Ok::<(), Infallible>(())
+			if capture_output {
+				if let Some(mut command) = prespawn.command().await {
+					command.stdout(Stdio::piped()).stderr(Stdio::piped());
+				}
+			}
+
+			#[cfg(unix)]
+			if let Some(resizer) = &pty {
+				match Pty::open().and_then(|pty| {
+					let stdio = pty.stdio()?;
+					resizer.set_master(pty.into_master())?;
+					Ok(stdio)
+				}) {
+					Ok((stdout, stderr)) => {
+						if let Some(mut command) = prespawn.command().await {
+							command.stdout(stdout).stderr(stderr);
+						}
+					}
+					Err(err) => {
+						if !quiet {
+							eprintln!("[[Warning: cannot open a pseudo-terminal, running the command without one: {}]]", err);
+						}
+					}
+				}
+			}
+
+			if stdin_events {
+				if let Some(mut command) = prespawn.command().await {
+					command.stdin(Stdio::piped());
+				}
+			}
+
+			if let Some(dir) = &workdir {
+				if let Some(mut command) = prespawn.command().await {
+					command.current_dir(dir);
+				}
+			}
+
+			if env_clear || !env_vars.is_empty() {
+				if let Some(mut command) = prespawn.command().await {
+					if env_clear {
+						command.env_clear();
+					}
+					command.envs(env_vars.iter().cloned());
+				}
+			}
+
+			if !no_env {
+				let envs = summarise_events_to_env_with_separator(events.iter(), &path_separator);
+				if let Some(mut command) = prespawn.command().await {
+					for (k, v) in envs {
+						command.env(format!("WATCHEXEC_{}_PATH", k), v);
+					}
+				}
+			}
+
+			Ok::<(), Infallible>(())
+		}
 	});
 
 	config.on_post_spawn(SyncFnHandler::from(move |postspawn: PostSpawn| {
+		running_spawn.store(true, Ordering::SeqCst);
+
+		let stdout = postspawn.stdout.take();
+		let stderr = postspawn.stderr.take();
+		#[cfg(unix)]
+		let pty_output = pty_post.as_ref().and_then(|resizer| {
+			resizer.set_process(postspawn.id, postspawn.grouped);
+			resizer.take_output()
+		});
+		#[cfg(not(unix))]
+		let pty_output: Option<()> = None;
+
+		let outputs = [stdout.is_some(), stderr.is_some(), pty_output.is_some()];
+		let run = dedupe.as_ref().map(|dedupe| {
+			let outputs = outputs.iter().filter(|open| **open).count();
+			dedupe.start(outputs, runs_post.load(Ordering::Relaxed))
+		});
+
+		if let Some(stdout) = stdout {
+			spawn(copy_lines(
+				stdout,
+				output_prefix.clone(),
+				Stream::Stdout,
+				log_file.clone(),
+				run.clone(),
+			));
+		}
+		if let Some(stderr) = stderr {
+			spawn(copy_lines(
+				stderr,
+				output_prefix.clone(),
+				Stream::Stderr,
+				log_file.clone(),
+				run.clone(),
+			));
+		}
+
+		#[cfg(unix)]
+		if let Some(output) = pty_output {
+			if capture_output {
+				spawn(copy_lines(
+					output,
+					output_prefix.clone(),
+					Stream::Stdout,
+					log_file.clone(),
+					run,
+				));
+			} else {
+				spawn(output.copy_to_stdout());
+			}
+		}
+
+		if let Some(stdin) = postspawn.stdin.take() {
+			let (batches_s, batches_r) = mpsc::unbounded_channel();
+			*feed_spawn.lock().expect("stdin feed lock poisoned") = Some(batches_s);
+			spawn(feed_stdin(stdin, batches_r, feed_retries.clone()));
+		}
+
+		if let Some(timeout) = timeout {
+			let run = runs_post.load(Ordering::Relaxed);
+			let timeouts = timeouts.clone();
+			spawn(async move {
+				sleep(timeout).await;
+				let event = Event::new(
+					vec![Tag::Source(Source::Time)],
+					[("timeout".to_string(), vec![run.to_string()])].into(),
+				);
+				timeouts.send(event).await.ok();
+			});
+		}
+
 		if notif {
-			Notification::new()
-				.summary("Watchexec: change detected")
-				.body(&format!("Running `{}`", postspawn.command.join(" ")))
-				.show()
-				.map(drop)
-				.unwrap_or_else(|err| {
-					eprintln!("[[Failed to send desktop notification: {}]]", err);
-				});
+			run_paths_spawn.store(
+				changed_paths(postspawn.events.iter()).len(),
+				Ordering::SeqCst,
+			);
+			notify(
+				"Watchexec: change detected",
+				&format!("Running `{}`", postspawn.command.join(" ")),
+			);
 		}
 
 		Ok::<(), Infallible>(())
@@ -228,6 +1039,447 @@ pub fn runtime(args: &ArgMatches<'static>) -> Result<RuntimeConfig> {
 	Ok(config)
 }
 
+/// Sends the event which starts a retry (or a restart) after the delay, unless it was abandoned by
+/// then. The `id` is that of the pending retry.
+fn schedule_retry(retries: &mpsc::Sender<Event>, delay: Duration, id: usize) {
+	let retries = retries.clone();
+	spawn(async move {
+		sleep(delay).await;
+		let event = Event::new(
+			vec![Tag::Source(Source::Time)],
+			[("retry".to_string(), vec![id.to_string()])].into(),
+		);
+		retries.send(event).await.ok();
+	});
+}
+
+/// Whether these events include a --timeout for the given run, or `None` if there's no timeout.
+fn timeout_for(events: &[Event], run: u64) -> Option<bool> {
+	events
+		.iter()
+		.find_map(|e| e.metadata.get("timeout"))
+		.map(|runs| runs.first().and_then(|id| id.parse::<u64>().ok()) == Some(run))
+}
+
+/// Sends these events back through watchexec, to be handled in a later batch.
+fn resend(events: Vec<Event>, sender: &mpsc::Sender<Event>) {
+	let sender = sender.clone();
+	spawn(async move {
+		for event in events {
+			if sender.send(event).await.is_err() {
+				break;
+			}
+		}
+	});
+}
+
+/// Sender for the batches of changes written to the running command's stdin, if any.
+type StdinFeed = Arc<Mutex<Option<mpsc::UnboundedSender<Arc<Vec<Event>>>>>>;
+
+/// Writes each batch of changes to the command's stdin, as JSON lines like for --emit-events-to.
+///
+/// If the command has closed its stdin, the feed is closed and the changes are sent back through
+/// watchexec, which then restarts the command with them instead.
+async fn feed_stdin(
+	mut stdin: ChildStdin,
+	mut batches: mpsc::UnboundedReceiver<Arc<Vec<Event>>>,
+	events: mpsc::Sender<Event>,
+) {
+	while let Some(batch) = batches.recv().await {
+		let mut lines = String::new();
+		for line in batch.iter().filter_map(event_json) {
+			lines.push_str(&line.to_string());
+			lines.push('\n');
+		}
+
+		let written = match stdin.write_all(lines.as_bytes()).await {
+			Ok(()) => stdin.flush().await,
+			Err(err) => Err(err),
+		};
+		if let Err(err) = written {
+			debug!(%err, "cannot write to the command's stdin");
+			batches.close();
+			let mut unsent = vec![batch];
+			while let Ok(batch) = batches.try_recv() {
+				unsent.push(batch);
+			}
+
+			for event in unsent.iter().flat_map(|batch| batch.iter()) {
+				if events.send(event.clone()).await.is_err() {
+					break;
+				}
+			}
+			break;
+		}
+	}
+}
+
+/// How many runs in a row started by the command's own changes are taken as a loop.
+const LOOP_RUNS: usize = 3;
+
+/// How long after the command ends its changes may still come in, and count as its own.
+const LOOP_GRACE: Duration = Duration::from_millis(500);
+
+/// The paths of these events, if they were all changed while the last run was going.
+///
+/// A change counts as the last run's own if it was observed after the command started, and before
+/// it ended or shortly after. If any change came from elsewhere, or can't be placed, this is empty.
+/// So is it while the command is still running, as changes made to the files it works on while it
+/// runs, like edits while a server is up, aren't distinguishable from its own then.
+fn self_triggered(
+	events: &[Event],
+	spawned: Option<Instant>,
+	ended: Option<Instant>,
+) -> Vec<PathBuf> {
+	// an end from before the last start is the previous run's: this one is still going
+	let (spawned, ended) = match spawned.zip(ended) {
+		Some((spawned, ended)) if ended >= spawned => (spawned, ended),
+		_ => return Vec::new(),
+	};
+	let during_run = |event: &Event| {
+		event.observed.map_or(false, |observed| {
+			observed.instant >= spawned && observed.instant <= ended + LOOP_GRACE
+		})
+	};
+
+	let events: Vec<&Event> = events
+		.iter()
+		.filter(|e| e.paths().next().is_some())
+		.collect();
+	if !events.iter().all(|event| during_run(event)) {
+		return Vec::new();
+	}
+
+	let mut paths: Vec<PathBuf> = Vec::new();
+	for (path, _) in events.iter().flat_map(|event| event.paths()) {
+		if !paths.iter().any(|p| p == path) {
+			paths.push(path.to_owned());
+		}
+	}
+	paths
+}
+
+/// Sends the held events back through watchexec after the delay, so they trigger a single run.
+fn release_held(held: &Arc<Mutex<Vec<Event>>>, delay: Duration, events: &mpsc::Sender<Event>) {
+	let held = held.clone();
+	let events = events.clone();
+	spawn(async move {
+		sleep(delay).await;
+		let released = take(&mut *held.lock().expect("held events lock poisoned"));
+		for event in released {
+			if events.send(event).await.is_err() {
+				break;
+			}
+		}
+	});
+}
+
+/// Copies the output of the command line by line, prepending the prefix to each line, and also
+/// appending it to the log file if there's one.
+///
+/// Each line is written and flushed as soon as it's complete, so output from the two streams is
+/// interleaved as it comes in rather than in blocks. The log gets the same lines as the terminal,
+/// except that it always gets all of them, where the terminal may have the run's output collapsed
+/// by [`Dedupe`] instead.
+async fn copy_lines(
+	output: impl AsyncRead + Unpin,
+	prefix: Option<String>,
+	stream: Stream,
+	log: Option<Arc<Mutex<LogFile>>>,
+	dedupe: Option<DedupeRun>,
+) {
+	let mut output = BufReader::new(output);
+	let mut line = Vec::new();
+	loop {
+		line.clear();
+		match output.read_until(b'\n', &mut line).await {
+			Ok(0) => break,
+			Ok(_) => {
+				if let Some(prefix) = &prefix {
+					if !line.ends_with(b"\n") {
+						line.push(b'\n');
+					}
+					line.splice(0..0, prefix.bytes());
+				}
+
+				let written = match &dedupe {
+					Some(run) => run.line(stream, &line),
+					None => stream.write(&line),
+				};
+				if let Err(err) = written {
+					warn!(%err, "cannot write command output");
+					break;
+				}
+
+				if let Some(log) = &log {
+					if let Err(err) = log.lock().expect("log file lock poisoned").write(&line) {
+						warn!(%err, "cannot write command output to the log file");
+					}
+				}
+			}
+			Err(err) => {
+				warn!(%err, "cannot read command output");
+				break;
+			}
+		}
+	}
+
+	if let Some(run) = dedupe {
+		run.done();
+	}
+}
+
+fn notify(summary: &str, body: &str) {
+	Notification::new()
+		.summary(summary)
+		.body(body)
+		.show()
+		.map(drop)
+		.unwrap_or_else(|err| {
+			warn!(%err, "failed to send desktop notification");
+		});
+}
+
+/// What can be substituted in the command: all the changed paths, then the operation and the path
+/// of the first event in the batch with a path.
+const PLACEHOLDERS: [&str; 3] = ["{}", "{op}", "{path}"];
+
+/// Written in the command to get a placeholder as is, e.g. `{{}}` for a literal `{}`.
+const ESCAPED_PLACEHOLDERS: [&str; 3] = ["{{}}", "{{op}}", "{{path}}"];
+
+/// A placeholder in the command, or an escaped one, by its index in [`PLACEHOLDERS`].
+enum Token {
+	Placeholder(usize),
+	Escaped(usize),
+}
+
+/// Finds the first placeholder or escaped placeholder in a part of the command, with its position
+/// and length.
+fn next_token(part: &str) -> Option<(usize, usize, Token)> {
+	let escaped = ESCAPED_PLACEHOLDERS
+		.iter()
+		.enumerate()
+		.filter_map(|(i, esc)| part.find(esc).map(|at| (at, esc.len(), Token::Escaped(i))));
+	let placeholders = PLACEHOLDERS.iter().enumerate().filter_map(|(i, ph)| {
+		part.find(ph)
+			.map(|at| (at, ph.len(), Token::Placeholder(i)))
+	});
+
+	// an escape starts before the placeholder it contains, so it's found first
+	escaped.chain(placeholders).min_by_key(|(at, _, _)| *at)
+}
+
+/// Whether a part of the command has any placeholder to substitute, not counting escaped ones.
+fn has_placeholders(mut part: &str) -> bool {
+	while let Some((at, len, token)) = next_token(part) {
+		if let Token::Placeholder(_) = token {
+			return true;
+		}
+		part = &part[at + len..];
+	}
+
+	false
+}
+
+/// Replaces the [`PLACEHOLDERS`] in the command with the changed paths, and the operation and path
+/// of the trigger. Escaped placeholders are left as the plain placeholder.
+///
+/// Without a shell, a `{}` argument becomes one argument per path. Otherwise, and within a larger
+/// argument, it becomes the space-separated paths, quoted for the shell and for the quotes they're
+/// substituted within, if any. Without a trigger, as at startup, `{op}` and `{path}` become nothing,
+/// and without a shell an argument of only one of them is left out.
+fn substitute_paths(
+	cmd: &[String],
+	paths: &[&Path],
+	trigger: Option<(&str, &Path)>,
+	shell: &Shell,
+) -> Vec<String> {
+	let value = |i: usize, quotes: &Quotes| match i {
+		0 => paths
+			.iter()
+			.map(|path| quotes.quote(shell, &path.display().to_string()))
+			.collect::<Vec<_>>()
+			.join(" "),
+		1 => trigger.map(|(op, _)| op.to_owned()).unwrap_or_default(),
+		_ => trigger
+			.map(|(_, path)| quotes.quote(shell, &path.display().to_string()))
+			.unwrap_or_default(),
+	};
+
+	// with a shell, the parts are joined into one command line, so quotes can span them
+	let mut quotes = Quotes::default();
+	cmd.iter()
+		.flat_map(|part| {
+			// without a shell, whole-argument placeholders expand to as many arguments as they
+			// have values, which for {} is one per path, and for the others none at startup
+			if let Shell::None = shell {
+				if part == "{}" {
+					return paths
+						.iter()
+						.map(|path| path.display().to_string())
+						.collect();
+				} else if let Some(i) = PLACEHOLDERS.iter().position(|ph| part == ph) {
+					return trigger.map(|_| value(i, &quotes)).into_iter().collect();
+				}
+			}
+
+			vec![replace_placeholders(part, shell, &mut quotes, value)]
+		})
+		.collect()
+}
+
+/// Replaces the [`PLACEHOLDERS`] in a single pass, so substituted values are never expanded again.
+fn replace_placeholders(
+	mut part: &str,
+	shell: &Shell,
+	quotes: &mut Quotes,
+	value: impl Fn(usize, &Quotes) -> String,
+) -> String {
+	let mut replaced = String::with_capacity(part.len());
+	while let Some((at, len, token)) = next_token(part) {
+		replaced.push_str(&part[..at]);
+		quotes.read(shell, &part[..at]);
+		match token {
+			Token::Placeholder(i) => replaced.push_str(&value(i, quotes)),
+			Token::Escaped(i) => replaced.push_str(PLACEHOLDERS[i]),
+		}
+		part = &part[at + len..];
+	}
+
+	replaced.push_str(part);
+	quotes.read(shell, part);
+	replaced
+}
+
+/// Whether a whole argument is a shell operator, like `|`, `&&`, `;`, `>`, or `2>&1`.
+fn is_shell_operator(arg: &str) -> bool {
+	let op = arg
+		.trim_start_matches(|c: char| c.is_ascii_digit())
+		.trim_end_matches(|c: char| c.is_ascii_digit());
+	!op.is_empty() && op.chars().all(|c| "|&;<>".contains(c))
+}
+
+/// The quotes a shell is within, reading a command line up to some point.
+#[derive(Debug, Default)]
+struct Quotes {
+	single: bool,
+	double: bool,
+	escaped: bool,
+}
+
+impl Quotes {
+	fn read(&mut self, shell: &Shell, text: &str) {
+		let (escape, single_quotes) = match shell {
+			Shell::Unix(_) => (Some('\\'), true),
+			Shell::Powershell => (Some('`'), true),
+			_ => (None, false),
+		};
+
+		for c in text.chars() {
+			if self.escaped {
+				self.escaped = false;
+			} else if self.single {
+				self.single = c != '\'';
+			} else if Some(c) == escape {
+				self.escaped = true;
+			} else if c == '"' {
+				self.double = !self.double;
+			} else if c == '\'' && single_quotes && !self.double {
+				self.single = true;
+			}
+		}
+	}
+
+	/// Quotes a value for the shell, or escapes it if it's within quotes already.
+	fn quote(&self, shell: &Shell, s: &str) -> String {
+		match shell {
+			Shell::None => s.to_owned(),
+			#[cfg(windows)]
+			Shell::Cmd if self.double => s.to_owned(),
+			#[cfg(windows)]
+			Shell::Cmd => format!("\"{}\"", s),
+			Shell::Powershell if self.single => s.replace('\'', "''"),
+			Shell::Powershell if self.double => {
+				s.replace('`', "``").replace('"', "`\"").replace('$', "`$")
+			}
+			Shell::Powershell => format!("'{}'", s.replace('\'', "''")),
+			Shell::Unix(_) if self.single => s.replace('\'', "'\\''"),
+			Shell::Unix(_) if self.double => s
+				.replace('\\', "\\\\")
+				.replace('"', "\\\"")
+				.replace('$', "\\$")
+				.replace('`', "\\`"),
+			Shell::Unix(_) => format!("'{}'", s.replace('\'', "'\\''")),
+		}
+	}
+}
+
+/// Serialises a filesystem event for --emit-events-to, or `None` if it has no paths.
+fn event_json(event: &Event) -> Option<serde_json::Value> {
+	let paths: Vec<String> = event
+		.paths()
+		.map(|(path, _)| path.display().to_string())
+		.collect();
+	if paths.is_empty() {
+		return None;
+	}
+
+	let kind = event_kind(event);
+	let op = event_op(event);
+	let timestamp = event
+		.observed
+		.map_or_else(SystemTime::now, |at| at.system)
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs_f64())
+		.unwrap_or_default();
+
+	Some(json!({
+		"paths": paths,
+		"op": op,
+		"kind": kind.map(|kind| format!("{:?}", kind)),
+		"timestamp": timestamp,
+	}))
+}
+
+fn event_kind(event: &Event) -> Option<&FileEventKind> {
+	event.tags.iter().find_map(|tag| match tag {
+		Tag::FileEventKind(kind) => Some(kind),
+		_ => None,
+	})
+}
+
+/// The name of the event's operation, as in `{op}` and the `op` of --emit-events-to.
+fn event_op(event: &Event) -> Option<&'static str> {
+	event_kind(event).map(|kind| match kind {
+		FileEventKind::Create(_) => "create",
+		FileEventKind::Modify(ModifyKind::Name(_)) => "rename",
+		FileEventKind::Modify(_) => "modify",
+		FileEventKind::Remove(_) => "remove",
+		FileEventKind::Access(_) => "access",
+		FileEventKind::Any | FileEventKind::Other => "other",
+	})
+}
+
+fn process_exit_code(status: Option<ProcessEnd>) -> i32 {
+	match status {
+		None | Some(ProcessEnd::Success) | Some(ProcessEnd::Continued) => 0,
+		Some(ProcessEnd::ExitError(code)) => i32::try_from(code.get()).unwrap_or(1),
+		Some(ProcessEnd::ExitSignal(sig)) => signal_exit_code(sig),
+		Some(ProcessEnd::ExitStop(_)) | Some(ProcessEnd::Exception(_)) => 1,
+	}
+}
+
+// same as shells do: 128 + the signal number
+#[cfg(unix)]
+fn signal_exit_code(sig: SubSignal) -> i32 {
+	sig.to_nix().map_or(1, |sig| 128 + sig as i32)
+}
+
+#[cfg(not(unix))]
+fn signal_exit_code(_: SubSignal) -> i32 {
+	1
+}
+
 // until 2.0, then Powershell
 #[cfg(windows)]
 fn default_shell() -> Shell {
@@ -236,7 +1488,7 @@ fn default_shell() -> Shell {
 
 #[cfg(not(windows))]
 fn default_shell() -> Shell {
-	Shell::default()
+	Shell::Unix("sh".into())
 }
 
 // because Shell::Cmd is only on windows
@@ -249,3 +1501,244 @@ fn cmd_shell(_: String) -> Shell {
 fn cmd_shell(s: String) -> Shell {
 	Shell::Unix(s)
 }
+
+#[cfg(test)]
+#[test]
+fn substitute_paths_no_shell() {
+	let cmd = vec!["prettier".into(), "--write".into(), "{}".into()];
+	let paths = [Path::new("/a b"), Path::new("/c")];
+
+	assert_eq!(
+		substitute_paths(&cmd, &paths, None, &Shell::None),
+		vec!["prettier", "--write", "/a b", "/c"]
+	);
+	assert_eq!(
+		substitute_paths(&cmd, &[], None, &Shell::None),
+		vec!["prettier", "--write"]
+	);
+	assert_eq!(
+		substitute_paths(&["--files={}".into()], &paths, None, &Shell::None),
+		vec!["--files=/a b /c"]
+	);
+}
+
+#[cfg(test)]
+#[test]
+fn shell_operators() {
+	for op in ["|", "||", "&&", ";", ">", ">>", "<", "2>", "2>&1", "&"] {
+		assert!(is_shell_operator(op), "{} is an operator", op);
+	}
+	for arg in ["build", "1", "a|b", "--flag=>", ""] {
+		assert!(!is_shell_operator(arg), "{} is not an operator", arg);
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn substitute_paths_unix_shell() {
+	let cmd = vec!["prettier --write {}".into()];
+	let paths = [Path::new("/a b"), Path::new("/it's")];
+
+	assert_eq!(
+		substitute_paths(&cmd, &paths, None, &Shell::Unix("sh".into())),
+		vec![r#"prettier --write '/a b' '/it'\''s'"#]
+	);
+}
+
+#[cfg(test)]
+#[test]
+fn substitute_paths_escaped() {
+	let paths = [Path::new("/a")];
+
+	assert_eq!(
+		substitute_paths(
+			&["find . -exec touch {{}} ; -newer {}".into()],
+			&paths,
+			None,
+			&Shell::Unix("sh".into())
+		),
+		vec!["find . -exec touch {} ; -newer '/a'"]
+	);
+	assert_eq!(
+		substitute_paths(&["{{}}".into(), "{}".into()], &paths, None, &Shell::None),
+		vec!["{}", "/a"]
+	);
+	assert_eq!(
+		substitute_paths(
+			&["echo {{op}}={op} {{path}}={path}".into()],
+			&paths,
+			Some(("create", Path::new("/a"))),
+			&Shell::Unix("sh".into())
+		),
+		vec!["echo {op}=create {path}='/a'"]
+	);
+	assert!(!has_placeholders("find -exec {{}} ;"));
+	assert!(!has_placeholders("{{op}} {{path}}"));
+	assert!(has_placeholders("{{}} {}"));
+}
+
+#[cfg(test)]
+#[test]
+fn substitute_paths_within_quotes() {
+	let sh = Shell::Unix("sh".into());
+	let paths = [Path::new("/a b"), Path::new("/it's")];
+
+	assert_eq!(
+		substitute_paths(&["echo '{}'".into()], &paths, None, &sh),
+		vec![r#"echo '/a b /it'\''s'"#]
+	);
+	assert_eq!(
+		substitute_paths(
+			&[r#"echo "{}""#.into()],
+			&[Path::new("/$a \"b\"")],
+			None,
+			&sh
+		),
+		vec![r#"echo "/\$a \"b\"""#]
+	);
+	assert_eq!(
+		substitute_paths(
+			&["echo".into(), "\"it's\"".into(), "{}".into()],
+			&paths,
+			None,
+			&sh
+		),
+		vec!["echo", "\"it's\"", r#"'/a b' '/it'\''s'"#]
+	);
+	assert_eq!(
+		substitute_paths(&["echo '{}'".into()], &paths, None, &Shell::Powershell),
+		vec!["echo '/a b /it''s'"]
+	);
+}
+
+#[cfg(test)]
+#[test]
+fn substitute_op_and_path() {
+	let paths = [Path::new("/a b"), Path::new("/c")];
+	let trigger = Some(("create", Path::new("/a b")));
+
+	assert_eq!(
+		substitute_paths(
+			&["handle.sh {op} {path} -- {}".into()],
+			&paths,
+			trigger,
+			&Shell::Unix("sh".into())
+		),
+		vec!["handle.sh create '/a b' -- '/a b' '/c'"]
+	);
+	assert_eq!(
+		substitute_paths(
+			&["handle.sh".into(), "{op}".into(), "{path}".into()],
+			&paths,
+			trigger,
+			&Shell::None
+		),
+		vec!["handle.sh", "create", "/a b"]
+	);
+	assert_eq!(
+		substitute_paths(
+			&["handle.sh".into(), "{op}".into(), "--path={path}".into()],
+			&[],
+			None,
+			&Shell::None
+		),
+		vec!["handle.sh", "--path="]
+	);
+	assert_eq!(
+		substitute_paths(
+			&["echo {}".into()],
+			&[Path::new("/{path}")],
+			Some(("modify", Path::new("/{path}"))),
+			&Shell::Unix("sh".into())
+		),
+		vec!["echo '/{path}'"]
+	);
+}
+
+#[cfg(test)]
+#[test]
+fn event_json_uses_observed_time() {
+	use watchexec::event::Timestamp;
+
+	let observed = Timestamp {
+		instant: std::time::Instant::now(),
+		system: UNIX_EPOCH + Duration::from_secs(1_000),
+	};
+	let mut event = Event::new(
+		vec![Tag::Path {
+			path: "/a".into(),
+			file_type: None,
+		}],
+		Default::default(),
+	);
+	event.observed = Some(observed);
+
+	let json = event_json(&event).expect("event has a path");
+	assert_eq!(json["timestamp"], 1000.0);
+}
+
+#[cfg(test)]
+#[test]
+fn timeout_batched_with_changes() {
+	let timeout = |run: &str| {
+		Event::new(
+			vec![Tag::Source(Source::Time)],
+			[("timeout".to_string(), vec![run.to_string()])].into(),
+		)
+	};
+	let change = Event::new(
+		vec![Tag::Path {
+			path: "/a".into(),
+			file_type: None,
+		}],
+		Default::default(),
+	);
+
+	assert_eq!(timeout_for(&[change.clone(), timeout("3")], 3), Some(true));
+	assert_eq!(timeout_for(&[timeout("3"), change.clone()], 4), Some(false));
+	assert_eq!(timeout_for(&[change], 3), None);
+}
+
+#[cfg(test)]
+#[test]
+fn self_triggered_changes() {
+	use watchexec::event::Timestamp;
+
+	let start = Instant::now();
+	let at = |ms: u64| start + Duration::from_millis(ms);
+	let event = |path: &str, observed: Option<Instant>| {
+		let mut event = Event::new(
+			vec![Tag::Path {
+				path: path.into(),
+				file_type: None,
+			}],
+			Default::default(),
+		);
+		event.observed = observed.map(|instant| Timestamp {
+			instant,
+			system: SystemTime::now(),
+		});
+		event
+	};
+
+	let during = [event("/out", Some(at(100))), event("/out", Some(at(150)))];
+	assert_eq!(
+		self_triggered(&during, Some(at(0)), Some(at(200))),
+		vec![PathBuf::from("/out")]
+	);
+	// still running, as the end is the previous run's
+	assert!(self_triggered(&during, Some(at(50)), Some(at(10))).is_empty());
+	assert!(self_triggered(&during, Some(at(50)), None).is_empty());
+	// shortly after the end still counts, but not long after
+	assert_eq!(
+		self_triggered(&[event("/out", Some(at(600)))], Some(at(0)), Some(at(200))),
+		vec![PathBuf::from("/out")]
+	);
+	assert!(self_triggered(&[event("/out", Some(at(900)))], Some(at(0)), Some(at(200))).is_empty());
+
+	// any change from elsewhere, or that can't be placed, means it's not a loop
+	let mixed = [event("/out", Some(at(100))), event("/src", Some(at(300)))];
+	assert!(self_triggered(&mixed, Some(at(200)), Some(at(400))).is_empty());
+	assert!(self_triggered(&[event("/out", None)], Some(at(0)), Some(at(200))).is_empty());
+	assert!(self_triggered(&during, None, None).is_empty());
+}
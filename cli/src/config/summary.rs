@@ -0,0 +1,80 @@
+use clap::ArgMatches;
+use serde_json::{json, Value};
+use watchexec::{command::Shell, config::RuntimeConfig};
+
+use crate::filterer::FilterConfig;
+
+/// The resolved configuration, for `--print-config-and-exit`.
+///
+/// The filters are only known for the default filterer: with the tagged filterer, they are `null`.
+pub fn summary(
+	args: &ArgMatches<'static>,
+	runtime: &RuntimeConfig,
+	filters: Option<&FilterConfig>,
+) -> Value {
+	json!({
+		"paths": runtime.fs.pathset.iter().map(|path| json!({
+			"path": path.as_ref(),
+			"recursive": path.is_recursive(),
+		})).collect::<Vec<_>>(),
+		"command": runtime.action.command,
+		"on_change": args.values_of_lossy("on-change").unwrap_or_default(),
+		"shell": match &runtime.action.shell {
+			Shell::None => None,
+			Shell::Unix(shell) => Some(shell.as_str()),
+			#[cfg(windows)]
+			Shell::Cmd => Some("cmd"),
+			Shell::Powershell => Some("powershell"),
+		},
+		"filters": filters,
+	})
+}
+
+#[cfg(test)]
+#[test]
+fn summary_of_runtime() {
+	let args = clap::App::new("test")
+		.arg(
+			clap::Arg::with_name("on-change")
+				.long("on-change")
+				.takes_value(true),
+		)
+		.get_matches_from(vec!["test"]);
+
+	let mut runtime = RuntimeConfig::default();
+	runtime
+		.pathset(["/src"])
+		.pathset_non_recursive(["/etc"])
+		.command(["cargo", "test"])
+		.command_shell(Shell::Unix("bash".into()));
+
+	let filters = FilterConfig {
+		filters: vec![],
+		ignores: vec![],
+		ignore_files: vec!["/src/.gitignore".into()],
+		extensions: vec!["rs".into()],
+		ignore_hidden: false,
+	};
+
+	assert_eq!(
+		summary(&args, &runtime, Some(&filters)),
+		json!({
+			"paths": [
+				{ "path": "/src", "recursive": true },
+				{ "path": "/etc", "recursive": false },
+			],
+			"command": ["cargo", "test"],
+			"on_change": [],
+			"shell": "bash",
+			"filters": {
+				"filters": [],
+				"ignores": [],
+				"ignore_files": ["/src/.gitignore"],
+				"extensions": ["rs"],
+				"ignore_hidden": false,
+			},
+		})
+	);
+
+	assert_eq!(summary(&args, &runtime, None)["filters"], Value::Null);
+}
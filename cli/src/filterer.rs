@@ -2,5 +2,6 @@ mod common;
 mod globset;
 mod tagged;
 
-pub use globset::globset;
+pub use common::WatchedFiles;
+pub use globset::{globset, route, split_extensions, FilterConfig, WatchexecFilterer};
 pub use tagged::tagged;
@@ -6,18 +6,28 @@ use std::{
 
 use clap::ArgMatches;
 use dunce::canonicalize;
-use miette::{IntoDiagnostic, Result};
-use tracing::{debug, warn};
+use miette::{IntoDiagnostic, Result, WrapErr};
+use tracing::{debug, trace, warn};
 use watchexec::{
+	error::RuntimeError,
+	event::Event,
+	filter::Filterer,
 	ignore::{self, IgnoreFile},
-	paths::common_prefix,
+	paths::{common_prefix, symlink_targets},
 	project::{self, ProjectType},
 };
 
 pub async fn dirs(args: &ArgMatches<'static>) -> Result<(PathBuf, PathBuf)> {
 	let mut origins = HashSet::new();
 	for path in args.values_of("paths").unwrap_or_default() {
-		let path = canonicalize(path).into_diagnostic()?;
+		// invalid paths are skipped (with a warning) when building the pathset
+		let path = match canonicalize(path) {
+			Ok(path) => path,
+			Err(err) => {
+				debug!(?path, %err, "skipping invalid path when resolving origins");
+				continue;
+			}
+		};
 		origins.extend(project::origins(&path).await);
 	}
 
@@ -36,6 +46,30 @@ pub async fn dirs(args: &ArgMatches<'static>) -> Result<(PathBuf, PathBuf)> {
 	Ok((project_origin, workdir))
 }
 
+/// The watched roots: the directories given to `-w` or containing the files given to it, or the
+/// working directory if there are none.
+pub fn roots(args: &ArgMatches<'static>, workdir: &Path) -> Vec<PathBuf> {
+	let roots: Vec<PathBuf> = args
+		.values_of_os("paths")
+		.unwrap_or_default()
+		.filter_map(|path| canonicalize(path).ok())
+		.filter_map(|path| {
+			if path.is_file() {
+				path.parent().map(Path::to_owned)
+			} else {
+				Some(path)
+			}
+		})
+		.collect();
+
+	if roots.is_empty() {
+		vec![workdir.to_owned()]
+	} else {
+		debug!(?roots, "resolved watched roots");
+		roots
+	}
+}
+
 pub async fn vcs_types(origin: &Path) -> Vec<ProjectType> {
 	let vcs_types = project::types(origin)
 		.await
@@ -125,7 +159,7 @@ pub async fn ignores(
 			.filter(|ig| {
 				!ig.applies_in
 					.as_ref()
-					.map_or(false, |p| p.starts_with(&origin))
+					.map_or(false, |p| p.starts_with(origin))
 			})
 			.collect::<Vec<_>>();
 		debug!(
@@ -137,7 +171,7 @@ pub async fn ignores(
 	if args.is_present("no-global-ignore") {
 		ignores = ignores
 			.into_iter()
-			.filter(|ig| !matches!(ig.applies_in, None))
+			.filter(|ig| ig.applies_in.is_some())
 			.collect::<Vec<_>>();
 		debug!(?ignores, "filtered ignores to exclude global ignores");
 	}
@@ -145,10 +179,131 @@ pub async fn ignores(
 	if args.is_present("no-vcs-ignore") {
 		ignores = ignores
 			.into_iter()
-			.filter(|ig| matches!(ig.applies_to, None))
+			.filter(|ig| ig.applies_to.is_none())
 			.collect::<Vec<_>>();
 		debug!(?ignores, "filtered ignores to exclude VCS-specific ignores");
 	}
 
+	if let Some(files) = args.values_of("ignore-files") {
+		// stdin is read as a list of patterns by the globset filterer instead
+		for path in files.filter(|path| *path != "-") {
+			let path = canonicalize(path)
+				.into_diagnostic()
+				.wrap_err_with(|| format!("--ignore-file: cannot read '{}'", path))?;
+			let applies_in = path.parent().map(Path::to_path_buf);
+			ignores.push(IgnoreFile {
+				path,
+				applies_in,
+				applies_to: None,
+			});
+		}
+		debug!(?ignores, "added ignore files given on the command line");
+	}
+
 	Ok(ignores)
 }
+
+/// The files given to `-w`, as opposed to directories.
+///
+/// Watching a file directly is unreliable: many editors save by replacing the file, which some
+/// backends then lose track of. Instead, the directory containing each file is watched on its own
+/// (not recursively), and this filterer rejects events about the other entries in it, unless they
+/// are also within one of the directories given to `-w`.
+#[derive(Debug, Default)]
+pub struct WatchedFiles {
+	files: Vec<PathBuf>,
+	dirs: Vec<PathBuf>,
+}
+
+impl WatchedFiles {
+	pub fn new(args: &ArgMatches<'static>) -> Self {
+		let mut watched = Self::default();
+		for path in args.values_of_os("paths").unwrap_or_default() {
+			// invalid paths are skipped (with a warning) when building the pathset
+			match canonicalize(path) {
+				Ok(path) if path.is_file() => watched.files.push(path),
+				Ok(path) => watched.dirs.push(path),
+				Err(_) => {}
+			}
+		}
+
+		if !watched.files.is_empty() && args.is_present("follow-symlinks") {
+			let targets = symlink_targets(&watched.dirs);
+			watched.dirs.extend(targets);
+		}
+
+		debug!(files=?watched.files, "resolved files to watch");
+		watched
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.files.is_empty()
+	}
+
+	/// The directories to watch for the files, which aren't already watched recursively.
+	pub fn parents(&self) -> Vec<PathBuf> {
+		let mut parents = Vec::new();
+		for parent in self.files.iter().filter_map(|file| file.parent()) {
+			if !self.is_within_dirs(parent) && !parents.iter().any(|p| p == parent) {
+				parents.push(parent.to_owned());
+			}
+		}
+		parents
+	}
+
+	fn is_within_dirs(&self, path: &Path) -> bool {
+		self.dirs.iter().any(|dir| path.starts_with(dir))
+	}
+}
+
+impl Filterer for WatchedFiles {
+	/// Events pass if any of their paths is a watched file or within a watched directory.
+	///
+	/// Events without paths always pass.
+	fn check_event(&self, event: &Event) -> Result<bool, RuntimeError> {
+		let mut paths = event.paths().map(|(path, _)| path).peekable();
+		if paths.peek().is_none() {
+			return Ok(true);
+		}
+
+		let pass =
+			paths.any(|path| self.files.iter().any(|f| f == path) || self.is_within_dirs(path));
+		if !pass {
+			trace!(?event, "not a watched file");
+		}
+		Ok(pass)
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn watched_files_pass_only_themselves() {
+	use watchexec::event::Tag;
+
+	let event = |path: &str| {
+		Event::new(
+			vec![Tag::Path {
+				path: path.into(),
+				file_type: None,
+			}],
+			Default::default(),
+		)
+	};
+
+	let watched = WatchedFiles {
+		files: vec![PathBuf::from("/project/Cargo.toml")],
+		dirs: vec![PathBuf::from("/project/src")],
+	};
+	assert_eq!(watched.parents(), vec![PathBuf::from("/project")]);
+	assert!(watched.check_event(&event("/project/Cargo.toml")).unwrap());
+	assert!(watched.check_event(&event("/project/src/main.rs")).unwrap());
+	assert!(!watched.check_event(&event("/project/README.md")).unwrap());
+	assert!(watched.check_event(&Event::default()).unwrap());
+
+	let watched = WatchedFiles {
+		files: vec![PathBuf::from("/project/src/main.rs")],
+		dirs: vec![PathBuf::from("/project")],
+	};
+	assert!(watched.parents().is_empty());
+	assert!(watched.check_event(&event("/project/README.md")).unwrap());
+}
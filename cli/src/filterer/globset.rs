@@ -1,11 +1,22 @@
 use std::{
+	collections::HashMap,
 	ffi::{OsStr, OsString},
-	path::MAIN_SEPARATOR,
-	sync::Arc,
+	fmt,
+	fs::read_to_string,
+	io::{stdin, Read},
+	path::{Path, PathBuf, MAIN_SEPARATOR},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::{Duration, Instant},
 };
 
 use clap::ArgMatches;
-use miette::{IntoDiagnostic, Result};
+use ignore::gitignore::GitignoreBuilder;
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+use serde::Serialize;
+use tracing::{debug, trace};
 use watchexec::{
 	error::RuntimeError,
 	event::{
@@ -25,77 +36,513 @@ pub async fn globset(args: &ArgMatches<'static>) -> Result<Arc<WatchexecFilterer
 
 	if !args.is_present("no-default-ignore") {
 		ignores.extend([
-			(format!("**{s}.DS_Store", s = MAIN_SEPARATOR), None),
-			(String::from("*.py[co]"), None),
-			(String::from("#*#"), None),
-			(String::from(".#*"), None),
-			(String::from(".*.kate-swp"), None),
-			(String::from(".*.sw?"), None),
-			(String::from(".*.sw?x"), None),
+			(
+				format!("**{s}.DS_Store", s = MAIN_SEPARATOR),
+				None,
+				GlobSource::Default,
+			),
+			(String::from("*.py[co]"), None, GlobSource::Default),
+			(String::from("#*#"), None, GlobSource::Default),
+			(String::from(".#*"), None, GlobSource::Default),
+			(String::from(".*.kate-swp"), None, GlobSource::Default),
+			(String::from(".*.sw?"), None, GlobSource::Default),
+			(String::from(".*.sw?x"), None, GlobSource::Default),
 		]);
 
 		if vcs_types.contains(&ProjectType::Git) {
-			ignores.push((format!("**{s}.git{s}**", s = MAIN_SEPARATOR), None));
+			ignores.push((
+				format!("**{s}.git{s}**", s = MAIN_SEPARATOR),
+				None,
+				GlobSource::Default,
+			));
 		}
 
 		if vcs_types.contains(&ProjectType::Mercurial) {
-			ignores.push((format!("**{s}.hg{s}**", s = MAIN_SEPARATOR), None));
+			ignores.push((
+				format!("**{s}.hg{s}**", s = MAIN_SEPARATOR),
+				None,
+				GlobSource::Default,
+			));
 		}
 
 		if vcs_types.contains(&ProjectType::Subversion) {
-			ignores.push((format!("**{s}.svn{s}**", s = MAIN_SEPARATOR), None));
+			ignores.push((
+				format!("**{s}.svn{s}**", s = MAIN_SEPARATOR),
+				None,
+				GlobSource::Default,
+			));
 		}
 
 		if vcs_types.contains(&ProjectType::Bazaar) {
-			ignores.push((format!("**{s}.bzr{s}**", s = MAIN_SEPARATOR), None));
+			ignores.push((
+				format!("**{s}.bzr{s}**", s = MAIN_SEPARATOR),
+				None,
+				GlobSource::Default,
+			));
 		}
 
 		if vcs_types.contains(&ProjectType::Darcs) {
-			ignores.push((format!("**{s}_darcs{s}**", s = MAIN_SEPARATOR), None));
+			ignores.push((
+				format!("**{s}_darcs{s}**", s = MAIN_SEPARATOR),
+				None,
+				GlobSource::Default,
+			));
 		}
 
 		if vcs_types.contains(&ProjectType::Fossil) {
 			ignores.push((
 				format!("**{s}.fossil-settings{s}**", s = MAIN_SEPARATOR),
 				None,
+				GlobSource::Default,
 			));
 		}
 
 		if vcs_types.contains(&ProjectType::Pijul) {
-			ignores.push((format!("**{s}.pijul{s}**", s = MAIN_SEPARATOR), None));
+			ignores.push((
+				format!("**{s}.pijul{s}**", s = MAIN_SEPARATOR),
+				None,
+				GlobSource::Default,
+			));
 		}
+
+		debug!(
+			defaults=?ignores.iter().map(|(glob, _, _)| glob).collect::<Vec<_>>(),
+			"applying default ignores (disable with --no-default-ignore)"
+		);
 	}
 
-	let filters = args
+	let mut filters = args
 		.values_of("filter")
 		.unwrap_or_default()
-		.map(|f| (f.to_owned(), Some(workdir.clone())));
+		.map(|f| {
+			(
+				f.to_owned(),
+				Some(workdir.clone()),
+				GlobSource::Arg("--filter"),
+			)
+		})
+		.collect::<Vec<_>>();
 
-	ignores.extend(
-		args.values_of("ignore")
-			.unwrap_or_default()
-			.map(|f| (f.to_owned(), Some(workdir.clone()))),
-	);
+	ignores.extend(args.values_of("ignore").unwrap_or_default().map(|f| {
+		(
+			f.to_owned(),
+			Some(workdir.clone()),
+			GlobSource::Arg("--ignore"),
+		)
+	}));
 
-	let exts = args
-		.values_of_os("extensions")
+	let filter_files = args.values_of("filter-files").unwrap_or_default();
+	let ignore_stdin = args
+		.values_of("ignore-files")
 		.unwrap_or_default()
-		.map(|s| s.split(b','))
-		.flatten();
+		.filter(|path| *path == "-");
+	let stdin_readers = filter_files
+		.clone()
+		.chain(ignore_stdin.clone())
+		.filter(|path| *path == "-")
+		.count();
+	if stdin_readers > 1 {
+		return Err(miette!(
+			"only one of --filter-file and --ignore-file can read from stdin"
+		));
+	}
+
+	for path in filter_files {
+		filters.extend(
+			read_patterns("--filter-file", path, &workdir)?
+				.into_iter()
+				.map(|f| (f, Some(workdir.clone()), GlobSource::File(path))),
+		);
+	}
+
+	for path in ignore_stdin {
+		ignores.extend(
+			read_patterns("--ignore-file", path, &workdir)?
+				.into_iter()
+				.map(|f| (f, Some(workdir.clone()), GlobSource::File(path))),
+		);
+	}
+
+	override_defaults(&mut ignores, &filters);
+
+	let exts = args
+		.value_of_os("extensions")
+		.map(|list| split_extensions("--exts", list))
+		.transpose()?
+		.unwrap_or_default();
+
+	let ignore_hidden =
+		args.is_present("ignore-hidden") && args.value_of("ignore-hidden") != Some("false");
+
+	let cooldown = args
+		.value_of("per-file-cooldown")
+		.map(|ms| {
+			ms.parse()
+				.into_diagnostic()
+				.wrap_err("--per-file-cooldown expects a whole number of milliseconds")
+		})
+		.transpose()?
+		.filter(|ms| *ms > 0)
+		.map(Duration::from_millis);
+
+	let config = FilterConfig {
+		filters: filters.iter().map(GlobConfig::from).collect(),
+		ignores: ignores.iter().map(GlobConfig::from).collect(),
+		ignore_files: ignore_files.iter().map(|file| file.path.clone()).collect(),
+		extensions: exts
+			.iter()
+			.map(|ext| ext.to_string_lossy().into_owned())
+			.collect(),
+		ignore_hidden,
+	};
+
+	if args.is_present("debug") {
+		eprintln!("[[Filter configuration:]]");
+		for glob in &config.filters {
+			eprintln!("  filter {} ({})", glob.glob, glob.source);
+		}
+		for glob in &config.ignores {
+			eprintln!("  ignore {} ({})", glob.glob, glob.source);
+		}
+		for file in &config.ignore_files {
+			eprintln!("  ignore file {}", file.display());
+		}
+		for ext in &config.extensions {
+			eprintln!("  extension {} (--exts)", ext);
+		}
+		if config.ignore_hidden {
+			eprintln!("  ignore hidden paths (--ignore-hidden)");
+		}
+	}
+
+	let mut builder = GlobsetFilterer::builder(project_origin);
+	for (glob, in_path, _) in filters {
+		builder.filter(glob, in_path);
+	}
+	for (glob, in_path, _) in ignores {
+		builder.ignore(glob, in_path);
+	}
+	for file in ignore_files {
+		builder.ignore_file(file);
+	}
+	for ext in exts {
+		builder.extension(ext, None);
+	}
+	let roots = super::common::roots(args, &workdir);
+	for root in &roots {
+		builder.root(root);
+	}
+
+	let ignore_case = args.is_present("ignore-case");
+	builder
+		.case_insensitive(ignore_case)
+		.extensions_case_insensitive(ignore_case)
+		.ignore_hidden(ignore_hidden);
 
 	Ok(Arc::new(WatchexecFilterer {
-		inner: GlobsetFilterer::new(project_origin, filters, ignores, ignore_files, exts)
-			.await
-			.into_diagnostic()?,
+		inner: builder.build().await.into_diagnostic()?,
+		config,
+		recovery_roots: if args.is_present("rerun-on-recovery") {
+			roots
+		} else {
+			Vec::new()
+		},
 		no_meta: args.is_present("no-meta"),
+		meta_rejected: AtomicU64::new(0),
+		log_file: args.value_of_os("log-file").map(|path| {
+			// the file may not exist yet, but its directory must for it to be opened
+			let path = Path::new(path);
+			let dir = match path.parent() {
+				Some(dir) if !dir.as_os_str().is_empty() => dir,
+				_ => Path::new("."),
+			};
+			match (dunce::canonicalize(dir), path.file_name()) {
+				(Ok(dir), Some(name)) => dir.join(name),
+				_ => path.to_owned(),
+			}
+		}),
+		log_rejected: AtomicU64::new(0),
+		on_rejected: AtomicU64::new(0),
+		on: args.values_of("on").map(|ops| {
+			ops.map(|op| match op {
+				"create" => EventOp::Create,
+				"modify" => EventOp::Modify,
+				"remove" => EventOp::Remove,
+				"rename" => EventOp::Rename,
+				_ => unreachable!("clap restricts the possible values"),
+			})
+			.collect()
+		}),
+		cooldown,
+		last_triggered: Mutex::new(HashMap::new()),
+		cooldown_rejected: AtomicU64::new(0),
 	}))
 }
 
-/// A custom filterer that combines the library's Globset filterer and a switch for --no-meta
+/// The filters, ignores, and extensions in effect, for `--debug` and `--print-config-and-exit`.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FilterConfig {
+	pub filters: Vec<GlobConfig>,
+	pub ignores: Vec<GlobConfig>,
+	pub ignore_files: Vec<PathBuf>,
+	pub extensions: Vec<String>,
+	pub ignore_hidden: bool,
+}
+
+/// A glob, and where it comes from.
+#[derive(Clone, Debug, Serialize)]
+pub struct GlobConfig {
+	pub glob: String,
+	pub source: String,
+}
+
+impl From<&(String, Option<PathBuf>, GlobSource<'_>)> for GlobConfig {
+	fn from((glob, _, source): &(String, Option<PathBuf>, GlobSource<'_>)) -> Self {
+		Self {
+			glob: glob.clone(),
+			source: source.to_string(),
+		}
+	}
+}
+
+/// Where a glob comes from, for `--debug`.
+#[derive(Clone, Copy, Debug)]
+enum GlobSource<'a> {
+	Default,
+	Arg(&'static str),
+	File(&'a str),
+}
+
+impl fmt::Display for GlobSource<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Default => write!(f, "default"),
+			Self::Arg(flag) => write!(f, "{}", flag),
+			Self::File("-") => write!(f, "stdin"),
+			Self::File(path) => write!(f, "{}", path),
+		}
+	}
+}
+
+/// Add the negated (`!`) filters to the ignores, just after the default ones.
+///
+/// As in a gitignore, the last pattern to match a path wins, so this lets `-f '!*.swp'` bring back
+/// paths which a default ignore like `.*.sw?` would skip, while the ignores given by the user still
+/// take precedence. Among the filters, a negated one keeps excluding paths the others match.
+fn override_defaults<'a>(
+	ignores: &mut Vec<(String, Option<PathBuf>, GlobSource<'a>)>,
+	filters: &[(String, Option<PathBuf>, GlobSource<'a>)],
+) {
+	let after_defaults = ignores
+		.iter()
+		.rposition(|(_, _, source)| matches!(source, GlobSource::Default))
+		.map_or(0, |n| n + 1);
+	let negated = filters
+		.iter()
+		.filter(|(glob, _, _)| glob.starts_with('!'))
+		.cloned()
+		.collect::<Vec<_>>();
+	if !negated.is_empty() {
+		debug!(
+			globs=?negated.iter().map(|(glob, _, _)| glob).collect::<Vec<_>>(),
+			"negated filters override the default ignores"
+		);
+	}
+
+	ignores.splice(after_defaults..after_defaults, negated);
+}
+
+/// Read glob patterns from a file, or from stdin if the path is `-`.
+///
+/// There is one pattern per line, and blank lines and `#` comments are skipped. Each pattern is
+/// checked as it's read, so that an invalid one is reported with the file and line it came from.
+fn read_patterns(flag: &str, path: &str, workdir: &Path) -> Result<Vec<String>> {
+	let (name, content) = if path == "-" {
+		let mut content = String::new();
+		stdin()
+			.read_to_string(&mut content)
+			.into_diagnostic()
+			.wrap_err_with(|| format!("{}: cannot read from stdin", flag))?;
+		(String::from("<stdin>"), content)
+	} else {
+		let content = read_to_string(path)
+			.into_diagnostic()
+			.wrap_err_with(|| format!("{}: cannot read '{}'", flag, path))?;
+		(path.to_owned(), content)
+	};
+
+	let mut check = GitignoreBuilder::new(workdir);
+	let mut patterns = Vec::new();
+	for (n, line) in content.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+
+		check.add_line(None, line).map_err(|err| {
+			miette!(
+				"{}: invalid pattern '{}' at {}:{}: {}",
+				flag,
+				line,
+				name,
+				n + 1,
+				err
+			)
+		})?;
+		patterns.push(line.to_owned());
+	}
+
+	debug!(file=%name, n=patterns.len(), "read patterns for {}", flag);
+	Ok(patterns)
+}
+
+/// A custom filterer combining the library's Globset filterer and switches for --no-meta and --on
 #[derive(Debug)]
 pub struct WatchexecFilterer {
 	inner: GlobsetFilterer,
+	config: FilterConfig,
+	recovery_roots: Vec<PathBuf>,
 	no_meta: bool,
+	meta_rejected: AtomicU64,
+	log_file: Option<PathBuf>,
+	log_rejected: AtomicU64,
+	on_rejected: AtomicU64,
+	on: Option<Vec<EventOp>>,
+	cooldown: Option<Duration>,
+	last_triggered: Mutex<HashMap<PathBuf, Instant>>,
+	cooldown_rejected: AtomicU64,
+}
+
+impl WatchexecFilterer {
+	/// The filters, ignores, and extensions this was built with.
+	pub fn config(&self) -> &FilterConfig {
+		&self.config
+	}
+
+	/// The events, with only the paths which pass the filters, and not the log file.
+	///
+	/// See [`route()`].
+	pub fn route(&self, events: &[Event]) -> Vec<Event> {
+		let mut events = route(&self.inner, events);
+		if let Some(log) = &self.log_file {
+			for event in &mut events {
+				event.tags.retain(|tag| match tag {
+					Tag::Path { path, .. } => !is_log_file(path, log),
+					_ => true,
+				});
+			}
+		}
+		events
+	}
+
+	/// A one-line summary of the events passed and rejected so far, for `--stats`.
+	pub fn stats(&self) -> String {
+		let inner = self.inner.stats();
+		let meta = self.meta_rejected.load(Ordering::Relaxed);
+		let on = self.on_rejected.load(Ordering::Relaxed);
+		let log = self.log_rejected.load(Ordering::Relaxed);
+		let cooldown = self.cooldown_rejected.load(Ordering::Relaxed);
+
+		let reasons: Vec<String> = [
+			(inner.ignore_file, "by ignore files"),
+			(inner.ignored, "by ignore globs"),
+			(inner.hidden, "as hidden"),
+			(inner.not_filtered, "by filter globs"),
+			(inner.extension, "by extension"),
+			(inner.denied_extension, "by denied extension"),
+			(meta, "as metadata changes"),
+			(on, "by kind of change"),
+			(log, "as writes to the log file"),
+			(cooldown, "by per-file cooldown"),
+			(inner.errored, "on errors"),
+		]
+		.iter()
+		.filter(|(n, _)| *n > 0)
+		.map(|(n, reason)| format!("{} {}", n, reason))
+		.collect();
+
+		let mut summary = format!(
+			"{} events passed, {} rejected",
+			inner.passed - cooldown,
+			inner.rejected() + meta + on + log + cooldown
+		);
+		if !reasons.is_empty() {
+			summary.push_str(&format!(" ({})", reasons.join(", ")));
+		}
+		summary
+	}
+
+	/// How many events were checked so far, and how many of those were rejected, for `--heartbeat`.
+	pub fn counts(&self) -> (u64, u64) {
+		let inner = self.inner.stats();
+		// events rejected by the cooldown were first passed by the inner filterer
+		let rejected = inner.rejected()
+			+ self.meta_rejected.load(Ordering::Relaxed)
+			+ self.on_rejected.load(Ordering::Relaxed)
+			+ self.log_rejected.load(Ordering::Relaxed);
+		(
+			inner.passed + rejected,
+			rejected + self.cooldown_rejected.load(Ordering::Relaxed),
+		)
+	}
+
+	/// Whether every path of the event which passes the filters already triggered a run less than
+	/// the `--per-file-cooldown` ago. If not, the other paths are noted as triggering now.
+	///
+	/// Only accepted events restart a path's cooldown, so a file that's written constantly still
+	/// triggers once per cooldown, and changes to other files go through meanwhile.
+	fn in_cooldown(&self, event: &Event) -> bool {
+		let cooldown = match self.cooldown {
+			Some(cooldown) => cooldown,
+			None => return false,
+		};
+
+		let paths = self.inner.passing_paths(event);
+		if paths.is_empty() {
+			return false;
+		}
+
+		let now = Instant::now();
+		let mut last = self.last_triggered.lock().expect("cooldown lock poisoned");
+		last.retain(|_, at| now.duration_since(*at) < cooldown);
+
+		let fresh: Vec<&Path> = paths
+			.into_iter()
+			.map(|(path, _)| path)
+			.filter(|path| !last.contains_key(*path))
+			.collect();
+		if fresh.is_empty() {
+			trace!(?event, "all paths are in their cooldown");
+			return true;
+		}
+
+		for path in fresh {
+			last.insert(path.to_owned(), now);
+		}
+		false
+	}
+}
+
+/// The kinds of filesystem operations which can be selected with --on
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum EventOp {
+	Create,
+	Modify,
+	Remove,
+	Rename,
+}
+
+impl EventOp {
+	fn matches(self, kind: &FileEventKind) -> bool {
+		match (self, kind) {
+			(Self::Create, FileEventKind::Create(_)) => true,
+			(Self::Rename, FileEventKind::Modify(ModifyKind::Name(_))) => true,
+			(Self::Modify, FileEventKind::Modify(ModifyKind::Name(_))) => false,
+			(Self::Modify, FileEventKind::Modify(_)) => true,
+			(Self::Remove, FileEventKind::Remove(_)) => true,
+			_ => false,
+		}
+	}
 }
 
 impl Filterer for WatchexecFilterer {
@@ -107,14 +554,125 @@ impl Filterer for WatchexecFilterer {
 			)
 		});
 
-		if self.no_meta && is_meta {
+		// events without a kind (e.g. from other sources than the filesystem) are not affected
+		let is_selected_op = self.on.as_ref().map_or(true, |ops| {
+			let mut kinds = event
+				.tags
+				.iter()
+				.filter_map(|tag| match tag {
+					Tag::FileEventKind(kind) => Some(kind),
+					_ => None,
+				})
+				.peekable();
+
+			kinds.peek().is_none() || kinds.any(|kind| ops.iter().any(|op| op.matches(kind)))
+		});
+
+		// writing the command's output to the log file mustn't trigger another run
+		let is_log = self.log_file.as_deref().map_or(false, |log| {
+			let mut paths = event.paths().peekable();
+			paths.peek().is_some() && paths.all(|(path, _)| is_log_file(path, log))
+		});
+
+		// the library re-watches a removed root when it comes back, and reports it as created
+		let is_recovery = !self.recovery_roots.is_empty()
+			&& event
+				.tags
+				.iter()
+				.any(|tag| matches!(tag, Tag::FileEventKind(FileEventKind::Create(_))))
+			&& event.paths().any(|(path, _)| {
+				// roots are canonicalised, but the library reports them as given on the command line
+				let path = dunce::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+				self.recovery_roots.contains(&path)
+			});
+
+		if is_recovery {
+			debug!(
+				?event,
+				"watched root is back, running regardless of the filters"
+			);
+			Ok(true)
+		} else if is_log {
+			self.log_rejected.fetch_add(1, Ordering::Relaxed);
+			Ok(false)
+		} else if self.no_meta && is_meta {
+			self.meta_rejected.fetch_add(1, Ordering::Relaxed);
+			Ok(false)
+		} else if !is_selected_op {
+			self.on_rejected.fetch_add(1, Ordering::Relaxed);
+			Ok(false)
+		} else if !self.inner.check_event(event)? {
+			Ok(false)
+		} else if self.in_cooldown(event) {
+			self.cooldown_rejected.fetch_add(1, Ordering::Relaxed);
 			Ok(false)
 		} else {
-			self.inner.check_event(event)
+			Ok(true)
 		}
 	}
 }
 
+/// The events, with only the paths which pass the filterer.
+///
+/// An event passes if any of its paths does, so this gives the command only the paths it's meant
+/// to see. Events without paths are kept as they are.
+pub fn route(filterer: &GlobsetFilterer, events: &[Event]) -> Vec<Event> {
+	events
+		.iter()
+		.map(|event| {
+			let paths = filterer.filter_paths(event);
+			let mut event = event.clone();
+			event.tags.retain(|tag| match tag {
+				Tag::Path { path, .. } => paths.iter().any(|(p, passed)| *passed && p == path),
+				_ => true,
+			});
+			event
+		})
+		.collect()
+}
+
+/// Whether the path is the log file or one of its rotations, like `out.log.1`.
+fn is_log_file(path: &Path, log: &Path) -> bool {
+	if path == log {
+		return true;
+	}
+
+	let rotation = path
+		.file_name()
+		.and_then(|name| name.to_str())
+		.zip(log.file_name().and_then(|name| name.to_str()))
+		.and_then(|(name, log_name)| name.strip_prefix(log_name)?.strip_prefix('.'));
+	path.parent() == log.parent()
+		&& rotation.map_or(false, |n| {
+			!n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())
+		})
+}
+
+/// Split a list of extensions, given to `flag`, on commas and whitespace.
+///
+/// This makes `js,css`, `js, css`, and `js css` all the same. Empty entries are skipped, but a list
+/// with no extensions at all is an error rather than silently turning the filter off.
+pub fn split_extensions(flag: &str, list: &OsStr) -> Result<Vec<OsString>> {
+	let exts: Vec<OsString> = match list.to_str() {
+		Some(list) => list
+			.split(|c: char| c == ',' || c.is_whitespace())
+			.filter(|ext| !ext.is_empty())
+			.map(OsString::from)
+			.collect(),
+		None => list.split(b',').filter(|ext| !ext.is_empty()).collect(),
+	};
+
+	if exts.is_empty() {
+		Err(miette!(
+			"{} expects at least one extension, got '{}'",
+			flag,
+			list.to_string_lossy()
+		))
+	} else {
+		Ok(exts)
+	}
+}
+
 trait OsStringSplit {
 	fn split(&self, sep: u8) -> OsSplit;
 }
@@ -183,11 +741,31 @@ impl Iterator for OsSplit {
 	}
 }
 
+#[cfg(test)]
+#[test]
+fn split_extensions_on_commas_and_spaces() {
+	let split = |list: &str| split_extensions("--exts", OsStr::new(list));
+	let exts = |list: &[&str]| list.iter().map(OsString::from).collect::<Vec<_>>();
+
+	assert_eq!(split("js,css,html").unwrap(), exts(&["js", "css", "html"]));
+	assert_eq!(
+		split("js, css , html").unwrap(),
+		exts(&["js", "css", "html"])
+	);
+	assert_eq!(split("js css\thtml").unwrap(), exts(&["js", "css", "html"]));
+	assert_eq!(split(",js,,!min.js, ").unwrap(), exts(&["js", "!min.js"]));
+	assert!(split(" , ").is_err());
+	assert!(split("").is_err());
+}
+
 #[cfg(test)]
 #[test]
 fn os_split_none() {
 	let os = OsString::from("");
-	assert_eq!(os.split(b',').collect::<Vec<OsString>>(), Vec::<OsString>::new());
+	assert_eq!(
+		os.split(b',').collect::<Vec<OsString>>(),
+		Vec::<OsString>::new()
+	);
 
 	let mut split = os.split(b',');
 	assert_eq!(split.next(), None);
@@ -239,3 +817,358 @@ fn os_split_leading() {
 	assert_eq!(split.next(), Some(OsString::from("c")));
 	assert_eq!(split.next(), None);
 }
+
+#[cfg(test)]
+#[test]
+fn event_op_matches() {
+	use watchexec::event::filekind::{CreateKind, DataChange, RemoveKind, RenameMode};
+
+	let create = FileEventKind::Create(CreateKind::File);
+	let write = FileEventKind::Modify(ModifyKind::Data(DataChange::Content));
+	let rename = FileEventKind::Modify(ModifyKind::Name(RenameMode::Both));
+	let remove = FileEventKind::Remove(RemoveKind::File);
+
+	assert!(EventOp::Create.matches(&create));
+	assert!(!EventOp::Create.matches(&write));
+	assert!(EventOp::Modify.matches(&write));
+	assert!(!EventOp::Modify.matches(&rename));
+	assert!(EventOp::Rename.matches(&rename));
+	assert!(!EventOp::Rename.matches(&write));
+	assert!(EventOp::Remove.matches(&remove));
+	assert!(!EventOp::Remove.matches(&create));
+}
+
+#[cfg(test)]
+#[test]
+fn log_file_and_rotations() {
+	let log = Path::new("/srv/app/out.log");
+
+	assert!(is_log_file(Path::new("/srv/app/out.log"), log));
+	assert!(is_log_file(Path::new("/srv/app/out.log.1"), log));
+	assert!(is_log_file(Path::new("/srv/app/out.log.12"), log));
+	assert!(!is_log_file(Path::new("/srv/app/out.log."), log));
+	assert!(!is_log_file(Path::new("/srv/app/out.log.old"), log));
+	assert!(!is_log_file(Path::new("/srv/app/out.logs"), log));
+	assert!(!is_log_file(Path::new("/srv/other/out.log"), log));
+	assert!(!is_log_file(Path::new("/srv/app/main.js"), log));
+}
+
+#[cfg(test)]
+#[test]
+fn read_patterns_from_file() {
+	let dir = std::env::temp_dir();
+	let path = dir.join(format!("watchexec-patterns-{}", std::process::id()));
+
+	std::fs::write(&path, "# generated\n\n*.js\n  dist/**  \n").unwrap();
+	let patterns = read_patterns("--filter-file", path.to_str().unwrap(), &dir);
+	assert_eq!(
+		patterns.unwrap(),
+		vec![String::from("*.js"), String::from("dist/**")]
+	);
+
+	std::fs::write(&path, "*.js\n\na[b\n").unwrap();
+	let err = read_patterns("--filter-file", path.to_str().unwrap(), &dir).unwrap_err();
+	assert!(err
+		.to_string()
+		.contains(&format!("'a[b' at {}:3", path.display())));
+
+	std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn stats_include_cli_rejections() {
+	use watchexec::event::filekind::{CreateKind, MetadataKind};
+
+	let filterer = WatchexecFilterer {
+		inner: GlobsetFilterer::builder("/")
+			.extension("rs", None)
+			.build()
+			.await
+			.unwrap(),
+		config: FilterConfig::default(),
+		recovery_roots: Vec::new(),
+		no_meta: true,
+		meta_rejected: AtomicU64::new(0),
+		log_file: None,
+		log_rejected: AtomicU64::new(0),
+		on_rejected: AtomicU64::new(0),
+		on: Some(vec![EventOp::Modify]),
+		cooldown: None,
+		last_triggered: Mutex::new(HashMap::new()),
+		cooldown_rejected: AtomicU64::new(0),
+	};
+	let event = |path: &str, kind| {
+		Event::new(
+			vec![
+				Tag::Path {
+					path: path.into(),
+					file_type: None,
+				},
+				Tag::FileEventKind(kind),
+			],
+			Default::default(),
+		)
+	};
+
+	let write = FileEventKind::Modify(ModifyKind::Any);
+	for event in [
+		event("/main.rs", write.clone()),
+		event("/main.ts", write),
+		event(
+			"/main.rs",
+			FileEventKind::Modify(ModifyKind::Metadata(MetadataKind::Any)),
+		),
+		event("/main.rs", FileEventKind::Create(CreateKind::File)),
+	] {
+		filterer.check_event(&event).unwrap();
+	}
+
+	assert_eq!(
+		filterer.stats(),
+		"1 events passed, 3 rejected (1 by extension, 1 as metadata changes, 1 by kind of change)"
+	);
+	assert_eq!(filterer.counts(), (4, 3));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn per_file_cooldown() {
+	let filterer = WatchexecFilterer {
+		inner: GlobsetFilterer::builder("/")
+			.ignore("*.tmp", None)
+			.build()
+			.await
+			.unwrap(),
+		config: FilterConfig::default(),
+		recovery_roots: Vec::new(),
+		no_meta: false,
+		meta_rejected: AtomicU64::new(0),
+		log_file: None,
+		log_rejected: AtomicU64::new(0),
+		on_rejected: AtomicU64::new(0),
+		on: None,
+		cooldown: Some(Duration::from_millis(200)),
+		last_triggered: Mutex::new(HashMap::new()),
+		cooldown_rejected: AtomicU64::new(0),
+	};
+	let event = |paths: &[&str]| {
+		Event::new(
+			paths
+				.iter()
+				.map(|path| Tag::Path {
+					path: path.into(),
+					file_type: None,
+				})
+				.collect(),
+			Default::default(),
+		)
+	};
+
+	assert!(filterer.check_event(&event(&["/app.log"])).unwrap());
+	assert!(!filterer.check_event(&event(&["/app.log"])).unwrap());
+	assert!(filterer.check_event(&event(&["/main.rs"])).unwrap());
+
+	// a path which doesn't pass the filters doesn't count
+	assert!(!filterer
+		.check_event(&event(&["/app.log", "/main.tmp"]))
+		.unwrap());
+	assert!(!filterer
+		.check_event(&event(&["/app.log", "/main.rs"]))
+		.unwrap());
+	assert!(filterer
+		.check_event(&event(&["/app.log", "/lib.rs"]))
+		.unwrap());
+
+	std::thread::sleep(Duration::from_millis(250));
+	assert!(filterer.check_event(&event(&["/app.log"])).unwrap());
+
+	assert_eq!(
+		filterer.stats(),
+		"4 events passed, 3 rejected (3 by per-file cooldown)"
+	);
+	assert_eq!(filterer.counts(), (7, 3));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn recovered_root_passes_filters() {
+	use watchexec::event::{filekind::CreateKind, FileType};
+
+	let filterer = WatchexecFilterer {
+		inner: GlobsetFilterer::builder("/")
+			.filter("*.rs", None)
+			.build()
+			.await
+			.unwrap(),
+		config: FilterConfig::default(),
+		recovery_roots: vec!["/src".into()],
+		no_meta: false,
+		meta_rejected: AtomicU64::new(0),
+		log_file: None,
+		log_rejected: AtomicU64::new(0),
+		on_rejected: AtomicU64::new(0),
+		on: Some(vec![EventOp::Modify]),
+		cooldown: None,
+		last_triggered: Mutex::new(HashMap::new()),
+		cooldown_rejected: AtomicU64::new(0),
+	};
+	let event = |path: &str, kind| {
+		Event::new(
+			vec![
+				Tag::Path {
+					path: path.into(),
+					file_type: Some(FileType::Dir),
+				},
+				Tag::FileEventKind(kind),
+			],
+			Default::default(),
+		)
+	};
+
+	let created = FileEventKind::Create(CreateKind::Folder);
+	assert!(filterer
+		.check_event(&event("/src", created.clone()))
+		.unwrap());
+	assert!(!filterer.check_event(&event("/other", created)).unwrap());
+	assert!(!filterer
+		.check_event(&event("/src", FileEventKind::Modify(ModifyKind::Any)))
+		.unwrap());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn recovered_relative_root_passes_filters() {
+	use watchexec::event::{filekind::CreateKind, FileType};
+
+	let filterer = WatchexecFilterer {
+		inner: GlobsetFilterer::builder("/")
+			.filter("*.rs", None)
+			.build()
+			.await
+			.unwrap(),
+		config: FilterConfig::default(),
+		recovery_roots: vec![dunce::canonicalize("src").unwrap()],
+		no_meta: false,
+		meta_rejected: AtomicU64::new(0),
+		log_file: None,
+		log_rejected: AtomicU64::new(0),
+		on_rejected: AtomicU64::new(0),
+		on: None,
+		cooldown: None,
+		last_triggered: Mutex::new(HashMap::new()),
+		cooldown_rejected: AtomicU64::new(0),
+	};
+	let event = |path: &str| {
+		Event::new(
+			vec![
+				Tag::Path {
+					path: path.into(),
+					file_type: Some(FileType::Dir),
+				},
+				Tag::FileEventKind(FileEventKind::Create(CreateKind::Folder)),
+			],
+			Default::default(),
+		)
+	};
+
+	// as with `-w src`, which the library reports back unchanged
+	assert!(filterer.check_event(&event("src")).unwrap());
+	assert!(filterer.check_event(&event("./src/")).unwrap());
+	assert!(!filterer.check_event(&event("tests")).unwrap());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn route_keeps_only_passing_paths() {
+	let filterer = WatchexecFilterer {
+		inner: GlobsetFilterer::builder("/")
+			.extension("rs", None)
+			.build()
+			.await
+			.unwrap(),
+		config: FilterConfig::default(),
+		recovery_roots: Vec::new(),
+		no_meta: false,
+		meta_rejected: AtomicU64::new(0),
+		log_file: Some("/srv/out.rs".into()),
+		log_rejected: AtomicU64::new(0),
+		on_rejected: AtomicU64::new(0),
+		on: None,
+		cooldown: None,
+		last_triggered: Mutex::new(HashMap::new()),
+		cooldown_rejected: AtomicU64::new(0),
+	};
+	let path = |path: &str| Tag::Path {
+		path: path.into(),
+		file_type: None,
+	};
+	let rename = Tag::FileEventKind(FileEventKind::Modify(ModifyKind::Any));
+	let events = [
+		Event::new(
+			vec![path("/srv/old.txt"), path("/srv/new.rs"), rename.clone()],
+			Default::default(),
+		),
+		Event::new(
+			vec![path("/srv/lib.rs"), path("/srv/out.rs")],
+			Default::default(),
+		),
+		Event::default(),
+	];
+
+	assert_eq!(
+		filterer.route(&events),
+		vec![
+			Event::new(vec![path("/srv/new.rs"), rename], Default::default()),
+			Event::new(vec![path("/srv/lib.rs")], Default::default()),
+			Event::default(),
+		]
+	);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn negated_filter_overrides_default_ignores() {
+	let mut ignores = vec![
+		(String::from(".*.sw?"), None, GlobSource::Default),
+		(
+			String::from(".secret.swp"),
+			None,
+			GlobSource::Arg("--ignore"),
+		),
+	];
+	let filters = vec![(String::from("!*.swp"), None, GlobSource::Arg("--filter"))];
+	override_defaults(&mut ignores, &filters);
+	assert_eq!(
+		ignores
+			.iter()
+			.map(|(glob, _, _)| glob.as_str())
+			.collect::<Vec<_>>(),
+		vec![".*.sw?", "!*.swp", ".secret.swp"]
+	);
+
+	let mut builder = GlobsetFilterer::builder("/");
+	for (glob, in_path, _) in filters {
+		builder.filter(glob, in_path);
+	}
+	for (glob, in_path, _) in ignores {
+		builder.ignore(glob, in_path);
+	}
+	let filterer = builder.build().await.unwrap();
+	let passes = |path: &str| {
+		filterer
+			.check_event(&Event::new(
+				vec![Tag::Path {
+					path: path.into(),
+					file_type: None,
+				}],
+				Default::default(),
+			))
+			.unwrap()
+	};
+
+	assert!(passes("/.main.rs.swp"));
+	assert!(!passes("/.main.rs.swo"));
+	assert!(!passes("/.secret.swp"));
+	assert!(passes("/main.rs"));
+}
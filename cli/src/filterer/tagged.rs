@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use clap::ArgMatches;
 use futures::future::try_join_all;
-use miette::{IntoDiagnostic, Result};
+use miette::{miette, IntoDiagnostic, Result};
 use tracing::{debug, trace, warn};
 use watchexec::{
 	filter::tagged::{
@@ -16,6 +16,16 @@ use watchexec::{
 pub async fn tagged(args: &ArgMatches<'static>) -> Result<Arc<TaggedFilterer>> {
 	let (project_origin, workdir) = super::common::dirs(args).await?;
 	let vcs_types = super::common::vcs_types(&project_origin).await;
+	if args
+		.values_of("ignore-files")
+		.unwrap_or_default()
+		.any(|path| path == "-")
+	{
+		return Err(miette!(
+			"--ignore-file: reading from stdin is not supported with the tagged filterer"
+		));
+	}
+
 	let ignores = super::common::ignores(args, &vcs_types, &project_origin).await?;
 
 	let filterer = TaggedFilterer::new(project_origin, workdir.clone())?;
@@ -61,6 +71,7 @@ pub async fn tagged(args: &ArgMatches<'static>) -> Result<Arc<TaggedFilterer>> {
 	}
 
 	if !args.is_present("no-default-ignore") {
+		let first_default = filters.len();
 		filters.extend([
 			Filter::from_glob_ignore(None, ".DS_Store/"),
 			Filter::from_glob_ignore(None, "*.py[co]"),
@@ -98,6 +109,11 @@ pub async fn tagged(args: &ArgMatches<'static>) -> Result<Arc<TaggedFilterer>> {
 		if vcs_types.contains(&ProjectType::Pijul) {
 			filters.push(Filter::from_glob_ignore(None, "/.pijul"));
 		}
+
+		debug!(
+			defaults=?&filters[first_default..],
+			"applying default ignores (disable with --no-default-ignore)"
+		);
 	}
 
 	if args.is_present("no-meta") {
@@ -1,7 +1,26 @@
-use std::env::var;
+use std::{
+	env::var,
+	io::ErrorKind,
+	sync::{
+		atomic::{AtomicI32, AtomicU64, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
 
-use miette::{IntoDiagnostic, Result};
-use watchexec::{event::Event, Watchexec};
+use miette::{miette, IntoDiagnostic, Report, Result, WrapErr};
+use tokio::{
+	spawn,
+	sync::mpsc,
+	time::{interval, sleep},
+};
+use tracing::debug;
+use watchexec::{
+	error::{CriticalError, RuntimeError},
+	event::Event,
+	filter::{AndFilterer, Filterer},
+	Watchexec,
+};
 
 mod args;
 mod config;
@@ -11,6 +30,26 @@ mod filterer;
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
+// exit codes for watchexec's own failures, documented in the manpage under EXIT STATUS
+const EXIT_INVALID_OPTIONS: i32 = 2;
+const EXIT_INVALID_FILTERS: i32 = 3;
+const EXIT_CANNOT_START: i32 = 4;
+const EXIT_FAILED: i32 = 5;
+
+trait OrExit<T> {
+	/// Prints the error as returning it from main would, then exits with this code.
+	fn or_exit(self, code: i32) -> T;
+}
+
+impl<T, E: Into<Report>> OrExit<T> for Result<T, E> {
+	fn or_exit(self, code: i32) -> T {
+		self.unwrap_or_else(|err| {
+			eprintln!("Error: {:?}", err.into());
+			std::process::exit(code);
+		})
+	}
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
 	#[cfg(feature = "dev-console")]
@@ -24,9 +63,14 @@ async fn main() -> Result<()> {
 		.map(|v| v == "tagged")
 		.unwrap_or(false);
 
-	let args = args::get_args(tagged_filterer)?;
+	let args = args::get_args(tagged_filterer).or_exit(EXIT_INVALID_OPTIONS);
 
-	{
+	let quiet = args.is_present("quiet");
+	if quiet && args.occurrences_of("verbose") > 0 {
+		eprintln!("[[Warning: --quiet overrides -v, not printing diagnostics]]");
+	}
+
+	if !quiet {
 		let verbosity = args.occurrences_of("verbose");
 		let mut builder = tracing_subscriber::fmt().with_env_filter(match verbosity {
 			0 => "watchexec-cli=warn",
@@ -47,22 +91,150 @@ async fn main() -> Result<()> {
 		}
 	}
 
-	let init = config::init(&args)?;
-	let mut runtime = config::runtime(&args)?;
-	runtime.filterer(if tagged_filterer {
-		eprintln!("!!! EXPERIMENTAL: using tagged filterer !!!");
-		filterer::tagged(&args).await?
+	let init = config::init(&args).or_exit(EXIT_INVALID_OPTIONS);
+	let exit_code = Arc::new(AtomicI32::new(0));
+	let runs = Arc::new(AtomicU64::new(0));
+	let (retries_s, mut retries_r) = mpsc::channel(1);
+	let rules = config::rules(&args).await.or_exit(EXIT_INVALID_OPTIONS);
+	let mut globset = None;
+	let filterer: Arc<dyn Filterer> = if tagged_filterer {
+		if !quiet {
+			eprintln!("!!! EXPERIMENTAL: using tagged filterer !!!");
+		}
+		filterer::tagged(&args).await.or_exit(EXIT_INVALID_FILTERS)
+	} else {
+		let filterer = filterer::globset(&args).await.or_exit(EXIT_INVALID_FILTERS);
+		globset = Some(filterer.clone());
+		filterer
+	};
+	let mut runtime = config::runtime(
+		&args,
+		exit_code.clone(),
+		runs.clone(),
+		retries_s,
+		rules,
+		globset.clone(),
+	)
+	.or_exit(EXIT_INVALID_OPTIONS);
+
+	let delay: Option<u64> = args
+		.value_of("delay")
+		.map(|delay| {
+			delay
+				.parse()
+				.into_diagnostic()
+				.wrap_err("--delay expects a whole number of milliseconds")
+		})
+		.transpose()
+		.or_exit(EXIT_INVALID_OPTIONS);
+
+	let heartbeat: Option<u64> = args
+		.value_of("heartbeat")
+		.map(|secs| match secs.parse() {
+			Ok(0) | Err(_) => Err(miette!(
+				"--heartbeat expects a whole number of seconds, at least 1"
+			)),
+			Ok(secs) => Ok(secs),
+		})
+		.transpose()
+		.or_exit(EXIT_INVALID_OPTIONS);
+
+	if args.is_present("print-config-and-exit") {
+		let summary = config::summary(
+			&args,
+			&runtime,
+			globset.as_ref().map(|filterer| filterer.config()),
+		);
+		println!(
+			"{}",
+			serde_json::to_string_pretty(&summary).into_diagnostic()?
+		);
+		return Ok(());
+	}
+
+	let files = filterer::WatchedFiles::new(&args);
+	runtime.filterer(if files.is_empty() {
+		filterer
 	} else {
-		filterer::globset(&args).await?
+		Arc::new(AndFilterer::new(files, filterer))
 	});
 
-	let wx = Watchexec::new(init, runtime)?;
+	let wx = Watchexec::new(init, runtime).or_exit(EXIT_CANNOT_START);
 
-	if !args.is_present("postpone") {
-		wx.send_event(Event::default()).await?;
+	{
+		let wx = wx.clone();
+		spawn(async move {
+			while let Some(event) = retries_r.recv().await {
+				if wx.send_event(event).await.is_err() {
+					break;
+				}
+			}
+		});
+	}
+
+	if let Some(secs) = heartbeat {
+		let globset = globset.clone();
+		spawn(async move {
+			let mut ticks = interval(Duration::from_secs(secs));
+			ticks.tick().await;
+			loop {
+				ticks.tick().await;
+				let runs = runs.load(Ordering::Relaxed);
+				match &globset {
+					Some(filterer) => {
+						let (seen, rejected) = filterer.counts();
+						eprintln!(
+							"[[Heartbeat: watching, {} events seen, {} filtered out, {} runs]]",
+							seen, rejected, runs
+						);
+					}
+					None => eprintln!("[[Heartbeat: watching, {} runs]]", runs),
+				}
+			}
+		});
+	}
+
+	if args.is_present("postpone") {
+		debug!("postponing the first run until a change is detected");
+	} else if let Some(delay) = delay {
+		debug!("delaying initial run by {}ms", delay);
+
+		// in a task so that changes and signals are still handled during the delay
+		let wx = wx.clone();
+		spawn(async move {
+			sleep(Duration::from_millis(delay)).await;
+			wx.send_event(Event::default()).await.ok();
+		});
+	} else {
+		wx.send_event(Event::default())
+			.await
+			.or_exit(EXIT_CANNOT_START);
+	}
+
+	match wx.main().await.into_diagnostic().or_exit(EXIT_FAILED) {
+		// already printed by the error handler, exit like shells do for these
+		Err(CriticalError::Elevated(RuntimeError::CommandSpawn { err, .. })) => {
+			exit_code.store(
+				if err.kind() == ErrorKind::NotFound {
+					127
+				} else {
+					126
+				},
+				Ordering::SeqCst,
+			);
+		}
+		res => res.or_exit(EXIT_FAILED),
+	}
+	drop(wx);
+
+	if let Some(filterer) = globset.filter(|_| args.is_present("stats")) {
+		eprintln!("[[Filter stats: {}]]", filterer.stats());
 	}
 
-	wx.main().await.into_diagnostic()??;
+	let code = exit_code.load(Ordering::SeqCst);
+	if code != 0 {
+		std::process::exit(code);
+	}
 
 	Ok(())
 }
@@ -0,0 +1,67 @@
+use std::process::Command;
+
+use assert_cmd::prelude::*;
+
+#[test]
+fn clear_combines_with_short_flags() {
+	let output = Command::cargo_bin("watchexec")
+		.unwrap()
+		.args(["-cr", "--print-config-and-exit", "--", "true"])
+		.output()
+		.unwrap();
+
+	assert!(
+		output.status.success(),
+		"-cr parses: {}",
+		String::from_utf8_lossy(&output.stderr)
+	);
+}
+
+#[test]
+fn clear_mode_is_its_own_option() {
+	let output = Command::cargo_bin("watchexec")
+		.unwrap()
+		.args([
+			"-c",
+			"--clear-mode",
+			"success",
+			"--print-config-and-exit",
+			"--",
+			"true",
+		])
+		.output()
+		.unwrap();
+
+	assert!(
+		output.status.success(),
+		"--clear-mode parses: {}",
+		String::from_utf8_lossy(&output.stderr)
+	);
+}
+
+#[test]
+fn shell_operator_is_only_a_warning_without_shell() {
+	let output = Command::cargo_bin("watchexec")
+		.unwrap()
+		.args([
+			"--shell=none",
+			"--print-config-and-exit",
+			"--",
+			"test",
+			"1",
+			"<",
+			"2",
+		])
+		.output()
+		.unwrap();
+
+	assert!(
+		output.status.success(),
+		"an operator-like argument is accepted"
+	);
+	assert!(
+		String::from_utf8_lossy(&output.stderr)
+			.contains("[[Warning: `<` looks like a shell operator"),
+		"but warned about"
+	);
+}
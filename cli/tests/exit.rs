@@ -0,0 +1,29 @@
+use std::process::Command;
+
+use assert_cmd::prelude::*;
+
+fn exit_code(args: &[&str]) -> Option<i32> {
+	Command::cargo_bin("watchexec")
+		.unwrap()
+		.args(args)
+		.output()
+		.unwrap()
+		.status
+		.code()
+}
+
+#[test]
+fn invalid_option() {
+	assert_eq!(
+		exit_code(&["--delay", "soon", "--print-config-and-exit", "--", "true"]),
+		Some(2)
+	);
+}
+
+#[test]
+fn invalid_filter() {
+	assert_eq!(
+		exit_code(&["-f", "[", "--print-config-and-exit", "--", "true"]),
+		Some(3)
+	);
+}
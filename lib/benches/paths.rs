@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use watchexec::{
+	event::{Event, FileType, Tag},
+	paths::{common_path, common_prefix, summarise_events_to_env},
+};
+
+const BATCH: usize = 10_000;
+
+fn paths(diverge_at: usize) -> Vec<PathBuf> {
+	(0..BATCH)
+		.map(|n| {
+			let mut path = PathBuf::from("/home/user/projects/watchexec/lib/src");
+			if n >= diverge_at {
+				path.push("elsewhere");
+			}
+			path.push(format!("module{}", n % 100));
+			path.push(format!("file{}.rs", n));
+			path
+		})
+		.collect()
+}
+
+fn events(paths: &[PathBuf]) -> Vec<Event> {
+	paths
+		.iter()
+		.map(|path| {
+			Event::new(
+				vec![Tag::Path {
+					path: path.clone(),
+					file_type: Some(FileType::File),
+				}],
+				Default::default(),
+			)
+		})
+		.collect()
+}
+
+fn bench_common(c: &mut Criterion) {
+	let shared = paths(BATCH);
+	let divergent = paths(1);
+
+	c.bench_function("common_prefix 10k shared", |b| {
+		b.iter(|| common_prefix(black_box(&shared)))
+	});
+	c.bench_function("common_prefix 10k divergent", |b| {
+		b.iter(|| common_prefix(black_box(&divergent)))
+	});
+
+	let batch = events(&shared);
+	c.bench_function("common_path 10k events", |b| {
+		b.iter(|| common_path(black_box(&batch)))
+	});
+	c.bench_function("summarise_events_to_env 10k events", |b| {
+		b.iter_batched(
+			|| batch.iter().collect::<Vec<_>>(),
+			summarise_events_to_env,
+			BatchSize::LargeInput,
+		)
+	});
+}
+
+criterion_group!(benches, bench_common);
+criterion_main!(benches);
@@ -0,0 +1,73 @@
+use std::{convert::Infallible, sync::Arc, time::Duration};
+
+use miette::{IntoDiagnostic, Result};
+use watchexec::{
+	action::{Action, Outcome},
+	config::{InitConfig, RuntimeConfig},
+	filter::globset::GlobsetFilterer,
+	handler::PrintDebug,
+	signal::{process::SubSignal, source::MainSignal},
+	Watchexec,
+};
+
+// The same watch, filter, and run loop as the CLI, embedded in a program:
+// reruns `cargo check` whenever a Rust file in `src` changes.
+//
+// Run with: `cargo run --example embed`
+#[tokio::main]
+async fn main() -> Result<()> {
+	let mut init = InitConfig::default();
+	init.on_error(PrintDebug(std::io::stderr()));
+
+	let filterer = GlobsetFilterer::builder(".")
+		.extension("rs", None)
+		.build()
+		.await
+		.into_diagnostic()?;
+
+	let mut runtime = RuntimeConfig::default();
+	runtime
+		.pathset(["src"])
+		.command(["cargo", "check"])
+		.action_throttle(Duration::from_millis(100))
+		.filterer(Arc::new(filterer));
+
+	runtime.on_action(|action: Action| async move {
+		let signals = action
+			.events
+			.iter()
+			.flat_map(|event| event.signals())
+			.collect::<Vec<_>>();
+
+		if signals.contains(&MainSignal::Interrupt) || signals.contains(&MainSignal::Terminate) {
+			// pass the interrupt on to the command, then exit once it has stopped
+			action.outcome(Outcome::both(
+				Outcome::StopWith(SubSignal::Interrupt),
+				Outcome::Exit,
+			));
+		} else if action
+			.events
+			.iter()
+			.any(|event| event.completions().next().is_some())
+		{
+			// the command ended by itself: wait for the next change
+			action.outcome(Outcome::DoNothing);
+		} else {
+			// restart the command if it's running, clearing the screen before each run
+			action.outcome(Outcome::if_running(
+				Outcome::both(Outcome::Stop, Outcome::both(Outcome::Clear, Outcome::Start)),
+				Outcome::both(Outcome::Clear, Outcome::Start),
+			));
+		}
+
+		Ok::<(), Infallible>(())
+	});
+
+	let wx = Watchexec::new(init, runtime)?;
+
+	// run once at startup, as the CLI does unless --postpone is given
+	wx.send_event(Default::default()).await?;
+	wx.main().await.into_diagnostic()??;
+
+	Ok(())
+}
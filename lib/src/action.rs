@@ -1,11 +1,14 @@
 //! Processor responsible for receiving events, filtering them, and scheduling actions in response.
 
 use std::{
-	sync::Arc,
-	time::{Duration, Instant},
+	sync::{Arc, Mutex},
+	time::Duration,
 };
 
+use async_stream::stream;
+use atomic_take::AtomicTake;
 use clearscreen::ClearScreen;
+use futures::pin_mut;
 use tokio::{
 	sync::{mpsc, watch},
 	time::timeout,
@@ -17,29 +20,32 @@ use crate::{
 	error::{CriticalError, RuntimeError},
 	event::Event,
 	handler::{rte, Handler},
+	signal::process::SubSignal,
 };
 
 #[doc(inline)]
 pub use outcome::Outcome;
 #[doc(inline)]
+pub use throttle::{accumulate, Throttle};
+#[doc(inline)]
 pub use workingdata::*;
 
+mod coalesce;
 mod outcome;
+mod throttle;
 mod workingdata;
 
 /// The main worker of a Watchexec process.
 ///
 /// This is the main loop of the process. It receives events from the event channel, filters them,
-/// debounces them, obtains the desired outcome of an actioned event, calls the appropriate handlers
-/// and schedules processes as needed.
+/// debounces them, coalesces atomic saves, obtains the desired outcome of an actioned event, calls
+/// the appropriate handlers and schedules processes as needed.
 pub async fn worker(
 	working: watch::Receiver<WorkingData>,
 	errors: mpsc::Sender<RuntimeError>,
 	events_tx: mpsc::Sender<Event>,
 	mut events: mpsc::Receiver<Event>,
 ) -> Result<(), CriticalError> {
-	let mut last = Instant::now();
-	let mut set = Vec::new();
 	let mut process: Option<Supervisor> = None;
 
 	let mut action_handler =
@@ -49,73 +55,59 @@ pub async fn worker(
 	let mut post_spawn_handler =
 		{ working.borrow().post_spawn_handler.take() }.ok_or(CriticalError::MissingHandler)?;
 
-	loop {
-		let maxtime = if set.is_empty() {
-			trace!("nothing in set, waiting forever for next event");
-			Duration::from_secs(u64::MAX)
-		} else {
-			working.borrow().throttle.saturating_sub(last.elapsed())
-		};
-
-		if maxtime.is_zero() {
-			if set.is_empty() {
-				trace!("out of throttle but nothing to do, resetting");
-				last = Instant::now();
-				continue;
-			} else {
-				trace!("out of throttle on recycle");
-			}
-		} else {
-			trace!(?maxtime, "waiting for event");
-			match timeout(maxtime, events.recv()).await {
-				Err(_timeout) => {
-					trace!("timed out, cycling");
-					continue;
-				}
-				Ok(None) => break,
-				Ok(Some(event)) => {
-					trace!(?event, "got event");
-
-					if event.is_empty() {
-						trace!("empty event, by-passing filters");
-					} else {
-						let filtered = working.borrow().filterer.check_event(&event);
-						match filtered {
-							Err(err) => {
-								trace!(%err, "filter errored on event");
-								errors.send(err).await?;
-								continue;
-							}
-							Ok(false) => {
-								trace!("filter rejected event");
-								continue;
-							}
-							Ok(true) => {
-								trace!("filter passed event");
+	// the filter errors go to the error channel from within the stream, so if that fails, the
+	// critical error is kept here and returned once the stream has ended
+	let critical = Mutex::new(None);
+	let filtered = {
+		// the stream takes ownership of what it uses, so give it references
+		let (working, errors, critical) = (&working, &errors, &critical);
+		stream! {
+			while let Some(event) = events.recv().await {
+				trace!(?event, "got event");
+
+				if event.is_empty() {
+					trace!("empty event, by-passing filters");
+				} else {
+					match check_event(working, &event).await {
+						Err(err) => {
+							trace!(%err, "filter errored on event");
+							if let Err(err) = errors.send(err).await {
+								let mut critical = critical.lock().expect("critical error lock poisoned");
+								*critical = Some(err.into());
+								break;
 							}
+							continue;
+						}
+						Ok(false) => {
+							trace!("filter rejected event");
+							continue;
+						}
+						Ok(true) => {
+							trace!("filter passed event");
 						}
-					}
-
-					if set.is_empty() {
-						trace!("event is the first, resetting throttle window");
-						last = Instant::now();
-					}
-
-					set.push(event);
-
-					let elapsed = last.elapsed();
-					if elapsed < working.borrow().throttle {
-						trace!(?elapsed, "still within throttle window, cycling");
-						continue;
 					}
 				}
+
+				yield event;
 			}
 		}
+	};
+	pin_mut!(filtered);
 
+	let mut throttle = Throttle::default();
+	while let Some(mut events) = throttle.accumulate(&working, &mut filtered).await {
 		trace!("out of throttle, starting action process");
-		last = Instant::now();
 
-		let events = Arc::new(set.drain(..).collect());
+		let drop_missing = working.borrow().drop_missing;
+		if drop_missing {
+			events = coalesce::drop_missing(events).await;
+			if events.is_empty() {
+				trace!("all paths are missing, skipping action");
+				continue;
+			}
+		}
+
+		let events = Arc::new(events);
 		let action = Action::new(Arc::clone(&events));
 		debug!(?action, "action constructed");
 
@@ -170,7 +162,28 @@ pub async fn worker(
 	}
 
 	debug!("action worker finished");
-	Ok(())
+	let critical = critical
+		.lock()
+		.expect("critical error lock poisoned")
+		.take();
+	critical.map_or(Ok(()), Err)
+}
+
+/// Check an event against the async filterer if there is one, or else the filterer.
+async fn check_event(
+	working: &watch::Receiver<WorkingData>,
+	event: &Event,
+) -> Result<bool, RuntimeError> {
+	#[cfg(feature = "async-filterer")]
+	{
+		// cloned out so the working data isn't borrowed across the await
+		let filterer = working.borrow().async_filterer.clone();
+		if let Some(filterer) = filterer {
+			return filterer.check_event_async(event).await;
+		}
+	}
+
+	working.borrow().filterer.check_event(event)
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -192,20 +205,30 @@ async fn apply_outcome(
 			return Err(RuntimeError::Exit);
 		}
 		(Some(p), Outcome::Stop) => {
-			p.kill().await;
-			p.wait().await?;
+			stop(p, working.stop_signal, working.stop_timeout).await?;
+			*process = None;
+		}
+		(Some(p), Outcome::StopWith(signal)) => {
+			stop(p, Some(signal), working.stop_timeout).await?;
 			*process = None;
 		}
-		(None, o @ Outcome::Stop) | (None, o @ Outcome::Wait) | (None, o @ Outcome::Signal(_)) => {
+		(None, o @ Outcome::Stop)
+		| (None, o @ Outcome::StopWith(_))
+		| (None, o @ Outcome::Wait)
+		| (None, o @ Outcome::Signal(_)) => {
 			debug!(outcome=?o, "meaningless without a process, not doing anything");
 		}
-		(_, Outcome::Start) => {
-			if working.command.is_empty() {
+		(_, o @ Outcome::Start) | (_, o @ Outcome::StartWith(_)) => {
+			let cmd = match o {
+				Outcome::StartWith(cmd) => cmd,
+				_ => working.command.clone(),
+			};
+
+			if cmd.is_empty() {
 				warn!("tried to start a command without anything to run");
 			} else {
-				let command = working.shell.to_command(&working.command);
-				let (pre_spawn, command) =
-					PreSpawn::new(command, working.command.clone(), events.clone());
+				let command = working.shell.to_command(&cmd);
+				let (pre_spawn, command) = PreSpawn::new(command, cmd.clone(), events.clone());
 
 				debug!("running pre-spawn handler");
 				pre_spawn_handler
@@ -217,7 +240,7 @@ async fn apply_outcome(
 					.into_inner();
 
 				trace!("spawing supervisor for command");
-				let sup = Supervisor::spawn(
+				let mut sup = Supervisor::spawn(
 					errors_c.clone(),
 					events_c.clone(),
 					&mut command,
@@ -226,10 +249,22 @@ async fn apply_outcome(
 
 				debug!("running post-spawn handler");
 				let post_spawn = PostSpawn {
-					command: working.command.clone(),
+					command: cmd,
 					events: events.clone(),
 					id: sup.id(),
 					grouped: working.grouped,
+					stdin: Arc::new(
+						sup.take_stdin()
+							.map_or_else(AtomicTake::empty, AtomicTake::new),
+					),
+					stdout: Arc::new(
+						sup.take_stdout()
+							.map_or_else(AtomicTake::empty, AtomicTake::new),
+					),
+					stderr: Arc::new(
+						sup.take_stderr()
+							.map_or_else(AtomicTake::empty, AtomicTake::new),
+					),
 				};
 				post_spawn_handler
 					.handle(post_spawn)
@@ -252,6 +287,11 @@ async fn apply_outcome(
 			clearscreen::clear()?;
 		}
 
+		(_, Outcome::ClearScrollback) => {
+			clearscreen::clear()?;
+			ClearScreen::WindowsVtClear.clear()?;
+		}
+
 		(_, Outcome::Reset) => {
 			for cs in [
 				ClearScreen::WindowsCooked,
@@ -326,3 +366,25 @@ async fn apply_outcome(
 
 	Ok(())
 }
+
+async fn stop(
+	process: &mut Supervisor,
+	signal: Option<SubSignal>,
+	stop_timeout: Duration,
+) -> Result<(), RuntimeError> {
+	match signal {
+		Some(signal) if cfg!(unix) => {
+			debug!(?signal, timeout=?stop_timeout, "asking process to stop");
+			process.signal(signal).await;
+			if timeout(stop_timeout, process.wait()).await.is_err() {
+				debug!("process did not stop in time, killing it");
+				process.kill().await;
+			}
+		}
+		_ => {
+			process.kill().await;
+		}
+	}
+
+	process.wait().await
+}
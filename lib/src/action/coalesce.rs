@@ -0,0 +1,275 @@
+//! Tidying up a batch of events before it is actioned.
+
+use std::path::Path;
+
+use tokio::fs::symlink_metadata;
+use tracing::trace;
+
+use crate::event::{
+	filekind::{FileEventKind, ModifyKind, RenameMode},
+	Event, Tag,
+};
+
+/// Coalesce atomic saves within a batch of events.
+///
+/// Many editors (vim, VS Code, etc) save by writing a new file and then replacing the old one, so
+/// instead of a modification the watcher sees the original file go away and a file with the same
+/// path appear. When a removal (or rename away) of a path is followed later in the batch by a
+/// creation (or rename into place) of that same path, the removal is dropped and the creation
+/// becomes a modification.
+///
+/// Only events about a single path are considered. Each removal pairs with at most one creation.
+pub(crate) fn coalesce_saves(mut events: Vec<Event>) -> Vec<Event> {
+	let mut dropped = vec![false; events.len()];
+	let mut paired = vec![false; events.len()];
+
+	for i in 0..events.len() {
+		let removed = match single_path(&events[i]).filter(|_| is_removal(&events[i])) {
+			Some(path) => path.to_owned(),
+			None => continue,
+		};
+
+		let creation = (i + 1..events.len()).find(|&j| {
+			!dropped[j]
+				&& !paired[j]
+				&& is_creation(&events[j])
+				&& single_path(&events[j]) == Some(removed.as_path())
+		});
+
+		if let Some(j) = creation {
+			paired[j] = true;
+			trace!(
+				removed=?single_path(&events[i]),
+				created=?single_path(&events[j]),
+				"coalescing atomic save"
+			);
+			dropped[i] = true;
+			for tag in &mut events[j].tags {
+				if let Tag::FileEventKind(kind) = tag {
+					*kind = FileEventKind::Modify(ModifyKind::Any);
+				}
+			}
+		}
+	}
+
+	events
+		.into_iter()
+		.zip(dropped)
+		.filter_map(|(event, dropped)| if dropped { None } else { Some(event) })
+		.collect()
+}
+
+/// Drop paths which don't exist anymore, and events left without any path.
+///
+/// Events which didn't have any paths to begin with are kept.
+pub(crate) async fn drop_missing(events: Vec<Event>) -> Vec<Event> {
+	let mut kept = Vec::with_capacity(events.len());
+	for mut event in events {
+		if event.paths().next().is_none() {
+			kept.push(event);
+			continue;
+		}
+
+		let mut tags = Vec::with_capacity(event.tags.len());
+		for tag in event.tags {
+			if let Tag::Path { path, .. } = &tag {
+				// symlink_metadata so that dangling symlinks count as existing
+				if symlink_metadata(path).await.is_err() {
+					trace!(?path, "dropping missing path");
+					continue;
+				}
+			}
+			tags.push(tag);
+		}
+		event.tags = tags;
+
+		if event.paths().next().is_some() {
+			kept.push(event);
+		}
+	}
+	kept
+}
+
+fn single_path(event: &Event) -> Option<&Path> {
+	let mut paths = event.paths();
+	match (paths.next(), paths.next()) {
+		(Some((path, _)), None) => Some(path),
+		_ => None,
+	}
+}
+
+fn kinds(event: &Event) -> impl Iterator<Item = &FileEventKind> {
+	event.tags.iter().filter_map(|tag| match tag {
+		Tag::FileEventKind(kind) => Some(kind),
+		_ => None,
+	})
+}
+
+fn is_removal(event: &Event) -> bool {
+	kinds(event).any(|kind| {
+		matches!(
+			kind,
+			FileEventKind::Remove(_) | FileEventKind::Modify(ModifyKind::Name(RenameMode::From))
+		)
+	})
+}
+
+fn is_creation(event: &Event) -> bool {
+	kinds(event).any(|kind| {
+		matches!(
+			kind,
+			FileEventKind::Create(_) | FileEventKind::Modify(ModifyKind::Name(RenameMode::To))
+		)
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use crate::event::filekind::{CreateKind, DataChange, RemoveKind};
+
+	use super::*;
+
+	fn event(path: &str, kind: FileEventKind) -> Event {
+		Event {
+			tags: vec![
+				Tag::Path {
+					path: path.into(),
+					file_type: None,
+				},
+				Tag::FileEventKind(kind),
+			],
+			metadata: Default::default(),
+			observed: None,
+		}
+	}
+
+	#[test]
+	fn remove_then_create_is_a_modify() {
+		let events = coalesce_saves(vec![
+			event("/src/main.rs", FileEventKind::Remove(RemoveKind::File)),
+			event("/src/main.rs", FileEventKind::Create(CreateKind::File)),
+		]);
+		assert_eq!(
+			events,
+			vec![event(
+				"/src/main.rs",
+				FileEventKind::Modify(ModifyKind::Any)
+			)]
+		);
+	}
+
+	#[test]
+	fn rename_away_then_create_is_a_modify() {
+		let events = coalesce_saves(vec![
+			event(
+				"/src/main.rs",
+				FileEventKind::Modify(ModifyKind::Name(RenameMode::From)),
+			),
+			event(
+				"/src/main.rs~",
+				FileEventKind::Modify(ModifyKind::Name(RenameMode::To)),
+			),
+			event("/src/main.rs", FileEventKind::Create(CreateKind::File)),
+			event(
+				"/src/main.rs",
+				FileEventKind::Modify(ModifyKind::Data(DataChange::Content)),
+			),
+		]);
+		assert_eq!(
+			events,
+			vec![
+				event(
+					"/src/main.rs~",
+					FileEventKind::Modify(ModifyKind::Name(RenameMode::To)),
+				),
+				event("/src/main.rs", FileEventKind::Modify(ModifyKind::Any)),
+				event(
+					"/src/main.rs",
+					FileEventKind::Modify(ModifyKind::Data(DataChange::Content)),
+				),
+			]
+		);
+	}
+
+	#[test]
+	fn create_then_remove_is_kept() {
+		let original = vec![
+			event("/src/main.rs", FileEventKind::Create(CreateKind::File)),
+			event("/src/main.rs", FileEventKind::Remove(RemoveKind::File)),
+		];
+		assert_eq!(coalesce_saves(original.clone()), original);
+	}
+
+	#[test]
+	fn different_names_are_kept() {
+		let original = vec![
+			event("/src/main.rs", FileEventKind::Remove(RemoveKind::File)),
+			event("/src/lib.rs", FileEventKind::Create(CreateKind::File)),
+		];
+		assert_eq!(coalesce_saves(original.clone()), original);
+	}
+
+	#[test]
+	fn same_name_in_another_directory_is_kept() {
+		let original = vec![
+			event("/a/mod.rs", FileEventKind::Remove(RemoveKind::File)),
+			event("/b/mod.rs", FileEventKind::Create(CreateKind::File)),
+		];
+		assert_eq!(coalesce_saves(original.clone()), original);
+	}
+
+	#[test]
+	fn two_removals_pair_with_one_creation() {
+		let events = coalesce_saves(vec![
+			event("/src/main.rs", FileEventKind::Remove(RemoveKind::File)),
+			event("/src/main.rs", FileEventKind::Remove(RemoveKind::File)),
+			event("/src/main.rs", FileEventKind::Create(CreateKind::File)),
+		]);
+		assert_eq!(
+			events,
+			vec![
+				event("/src/main.rs", FileEventKind::Remove(RemoveKind::File)),
+				event("/src/main.rs", FileEventKind::Modify(ModifyKind::Any)),
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn missing_paths_are_dropped() {
+		let here = std::env::current_dir().expect("current dir");
+		let present = here.join("Cargo.toml");
+		let missing = here.join("missing.rs");
+		let kind = || FileEventKind::Modify(ModifyKind::Any);
+
+		let both = Event {
+			tags: vec![
+				Tag::Path {
+					path: missing.clone(),
+					file_type: None,
+				},
+				Tag::Path {
+					path: present.clone(),
+					file_type: None,
+				},
+				Tag::FileEventKind(kind()),
+			],
+			metadata: Default::default(),
+			observed: None,
+		};
+
+		let events = drop_missing(vec![
+			event(missing.to_str().expect("utf-8 path"), kind()),
+			both,
+			Event::default(),
+		])
+		.await;
+
+		assert_eq!(
+			events,
+			vec![
+				event(present.to_str().expect("utf-8 path"), kind()),
+				Event::default()
+			]
+		);
+	}
+}
@@ -16,11 +16,24 @@ pub enum Outcome {
 	/// This should be used with an `IfRunning`, and will warn if the command is not running.
 	Stop,
 
+	/// If the command is running, stop it, sending this signal instead of the configured one.
+	///
+	/// This otherwise behaves like [`Stop`](Outcome::Stop), waiting up to the
+	/// [`stop_timeout`](crate::action::WorkingData::stop_timeout) before killing the command. It's
+	/// mostly useful to forward a signal received by watchexec to the command before exiting.
+	StopWith(SubSignal),
+
 	/// If the command isn't running, start it.
 	///
 	/// This should be used with an `IfRunning`, and will warn if the command is running.
 	Start,
 
+	/// If the command isn't running, start this one instead of the configured command.
+	///
+	/// This otherwise behaves like [`Start`](Outcome::Start), with the configured shell. It's useful
+	/// to choose between several commands depending on the events in the action.
+	StartWith(Vec<String>),
+
 	/// Wait for command completion.
 	///
 	/// Does nothing if the command isn't running.
@@ -34,6 +47,13 @@ pub enum Outcome {
 	/// Clear the (terminal) screen.
 	Clear,
 
+	/// Clear the (terminal) screen and its scrollback.
+	///
+	/// This does [the default clear][clearscreen::clear()], then prints the erase screen and erase
+	/// scrollback sequences with [`WindowsVtClear`][clearscreen::ClearScreen::WindowsVtClear], as
+	/// the default clear only erases the scrollback if the terminfo database says how to.
+	ClearScrollback,
+
 	/// Reset the (terminal) screen.
 	///
 	/// This invokes (in order): [`WindowsCooked`][clearscreen::ClearScreen::WindowsCooked],
@@ -106,6 +126,14 @@ mod test {
 	fn simple_passthrough() {
 		assert_eq!(Outcome::Wait.resolve(true), Outcome::Wait);
 		assert_eq!(Outcome::Clear.resolve(false), Outcome::Clear);
+		assert_eq!(
+			Outcome::StopWith(SubSignal::Interrupt).resolve(true),
+			Outcome::StopWith(SubSignal::Interrupt)
+		);
+		assert_eq!(
+			Outcome::StartWith(vec!["make".into()]).resolve(false),
+			Outcome::StartWith(vec!["make".into()])
+		);
 	}
 
 	#[test]
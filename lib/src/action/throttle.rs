@@ -0,0 +1,348 @@
+//! Collecting events into batches over the throttle window.
+
+use std::time::Duration;
+
+use futures::{Stream, StreamExt};
+use tokio::{
+	sync::watch,
+	time::{timeout, Instant},
+};
+use tracing::{debug, trace};
+
+use crate::{event::Event, signal::source::MainSignal};
+
+use super::{coalesce::coalesce_saves, ThrottleMode, WorkingData};
+
+/// Collect a batch of events from a source, until the throttle window is over.
+///
+/// This waits as long as needed for a first event, then keeps collecting events until the
+/// [`throttle`](WorkingData::throttle) window is over, as measured according to the
+/// [`throttle_mode`](WorkingData::throttle_mode). The throttle configuration is read from the
+/// working data on every event, so changes to it apply to the window in progress. The batch is
+/// returned with atomic saves coalesced: a file removed and then created again within the window
+/// shows up as a single modification of that file.
+///
+/// The source can be any [`Stream`] of events. The [action worker](super::worker) uses the event
+/// channel, with the filterer applied, but a stream of synthetic events works just as well, which
+/// makes this the piece to test when checking how bursts of events are batched.
+///
+/// An [interrupt](MainSignal::Interrupt) or [terminate](MainSignal::Terminate) signal ends the
+/// window right away, so that stopping watchexec isn't held up by the throttle.
+///
+/// Returns `None` when the source ends. Events collected until then are discarded, as for the
+/// worker that means watchexec is shutting down.
+///
+/// This has no memory of previous batches, so [`ThrottleMode::Adaptive`] behaves as
+/// [`ThrottleMode::Fixed`] here: use a [`Throttle`] to collect successive batches instead.
+pub async fn accumulate<S>(
+	working: &watch::Receiver<WorkingData>,
+	events: &mut S,
+) -> Option<Vec<Event>>
+where
+	S: Stream<Item = Event> + Unpin,
+{
+	Throttle::default().accumulate(working, events).await
+}
+
+/// Collects successive batches of events, keeping track of the adaptive throttle window.
+///
+/// With [`ThrottleMode::Adaptive`], the window depends on the batches before it: this is what the
+/// [action worker](super::worker) uses. With the other modes, [`Throttle::accumulate()`] is the
+/// same as [`accumulate()`].
+#[derive(Clone, Debug, Default)]
+pub struct Throttle {
+	/// The adaptive window of the last batch, and when that batch ended.
+	last: Option<(Duration, Instant)>,
+}
+
+impl Throttle {
+	/// Collect the next batch of events from a source.
+	///
+	/// See [`accumulate()`] for the details.
+	pub async fn accumulate<S>(
+		&mut self,
+		working: &watch::Receiver<WorkingData>,
+		events: &mut S,
+	) -> Option<Vec<Event>>
+	where
+		S: Stream<Item = Event> + Unpin,
+	{
+		let mut set = Vec::new();
+		let mut first = Instant::now();
+		let mut latest = first;
+		let mut window = working.borrow().throttle;
+
+		loop {
+			let maxtime = if set.is_empty() {
+				trace!("nothing in set, waiting forever for next event");
+				Duration::from_secs(u64::MAX)
+			} else {
+				remaining(&working.borrow(), window, first, latest)
+			};
+
+			if maxtime.is_zero() {
+				trace!("out of throttle");
+				break;
+			}
+
+			trace!(?maxtime, "waiting for event");
+			match timeout(maxtime, events.next()).await {
+				Err(_timeout) => {
+					trace!("timed out, cycling");
+				}
+				Ok(None) => return None,
+				Ok(Some(event)) => {
+					if set.is_empty() {
+						trace!("event is the first, resetting throttle window");
+						first = Instant::now();
+						window = self.window(&working.borrow(), first);
+					}
+					latest = Instant::now();
+
+					let stop = event
+						.signals()
+						.any(|sig| matches!(sig, MainSignal::Interrupt | MainSignal::Terminate));
+					set.push(event);
+
+					if stop {
+						trace!("stop signal, cutting throttle window short");
+						break;
+					}
+				}
+			}
+		}
+
+		self.last = Some((window, Instant::now()));
+
+		let drained = set.len();
+		let set = coalesce_saves(set);
+		debug!(
+			drained,
+			batched = set.len(),
+			waited = ?first.elapsed(),
+			quiet_for = ?latest.elapsed(),
+			"debounced events into a batch"
+		);
+		Some(set)
+	}
+
+	/// The throttle window for a batch starting at `first`.
+	///
+	/// In adaptive mode, the window grows when the batch starts within the window of the last one
+	/// after it ended, as that means events are still coming in, and goes back to the throttle
+	/// otherwise.
+	fn window(&self, working: &WorkingData, first: Instant) -> Duration {
+		if working.throttle_mode != ThrottleMode::Adaptive {
+			return working.throttle;
+		}
+
+		match self.last {
+			Some((last, ended)) if first.saturating_duration_since(ended) < last => {
+				let cap = working.throttle_max.max(working.throttle);
+				let grown = Duration::from_secs_f64(
+					(last.as_secs_f64() * working.throttle_growth.max(1.0)).min(cap.as_secs_f64()),
+				);
+				trace!(?grown, "activity is sustained, growing throttle window");
+				grown
+			}
+			_ => working.throttle,
+		}
+	}
+}
+
+/// How long is left in the throttle window.
+///
+/// `window` is the throttle for this cycle, `first` is the time of the first event in the cycle,
+/// and `latest` that of the most recent one.
+fn remaining(working: &WorkingData, window: Duration, first: Instant, latest: Instant) -> Duration {
+	match working.throttle_mode {
+		ThrottleMode::Fixed | ThrottleMode::Adaptive => window.saturating_sub(first.elapsed()),
+		ThrottleMode::Trailing => {
+			let cap = working.throttle_max.max(working.throttle);
+			working
+				.throttle
+				.saturating_sub(latest.elapsed())
+				.min(cap.saturating_sub(first.elapsed()))
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use futures::stream;
+	use tokio::time::sleep;
+
+	use crate::event::{
+		filekind::{CreateKind, FileEventKind, ModifyKind, RemoveKind},
+		Tag,
+	};
+
+	use super::*;
+
+	fn event(path: &str, kind: FileEventKind) -> Event {
+		Event {
+			tags: vec![
+				Tag::Path {
+					path: path.into(),
+					file_type: None,
+				},
+				Tag::FileEventKind(kind),
+			],
+			metadata: Default::default(),
+			observed: None,
+		}
+	}
+
+	fn modify(path: &str) -> Event {
+		event(path, FileEventKind::Modify(ModifyKind::Any))
+	}
+
+	fn working(throttle: u64, mode: ThrottleMode) -> watch::Receiver<WorkingData> {
+		watch::channel(WorkingData {
+			throttle: Duration::from_millis(throttle),
+			throttle_mode: mode,
+			..Default::default()
+		})
+		.1
+	}
+
+	/// Events sent after the given delays in milliseconds, then nothing, without ending.
+	fn timeline(events: Vec<(u64, Event)>) -> impl Stream<Item = Event> + Unpin {
+		Box::pin(
+			stream::iter(events)
+				.then(|(delay, event)| async move {
+					sleep(Duration::from_millis(delay)).await;
+					event
+				})
+				.chain(stream::pending()),
+		)
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn burst_is_one_batch() {
+		let working = working(200, ThrottleMode::Fixed);
+		let mut events = timeline(vec![
+			(0, modify("/a")),
+			(10, modify("/b")),
+			(10, modify("/a")),
+		]);
+
+		assert_eq!(
+			accumulate(&working, &mut events).await,
+			Some(vec![modify("/a"), modify("/b"), modify("/a")])
+		);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn fixed_window_splits_batches() {
+		let working = working(200, ThrottleMode::Fixed);
+		let mut events = timeline(vec![
+			(0, modify("/a")),
+			(100, modify("/b")),
+			(200, modify("/c")),
+		]);
+
+		assert_eq!(
+			accumulate(&working, &mut events).await,
+			Some(vec![modify("/a"), modify("/b")])
+		);
+		assert_eq!(
+			accumulate(&working, &mut events).await,
+			Some(vec![modify("/c")])
+		);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn trailing_window_waits_for_quiet() {
+		let working = working(150, ThrottleMode::Trailing);
+		let mut events = timeline(vec![
+			(0, modify("/a")),
+			(80, modify("/b")),
+			(80, modify("/c")),
+			(80, modify("/d")),
+		]);
+
+		assert_eq!(
+			accumulate(&working, &mut events).await,
+			Some(vec![modify("/a"), modify("/b"), modify("/c"), modify("/d")])
+		);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn atomic_save_is_coalesced() {
+		let working = working(100, ThrottleMode::Fixed);
+		let mut events = timeline(vec![
+			(0, event("/a", FileEventKind::Remove(RemoveKind::File))),
+			(10, event("/a", FileEventKind::Create(CreateKind::File))),
+		]);
+
+		assert_eq!(
+			accumulate(&working, &mut events).await,
+			Some(vec![modify("/a")])
+		);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn adaptive_window_grows_then_resets() {
+		let working = watch::channel(WorkingData {
+			throttle: Duration::from_millis(100),
+			throttle_mode: ThrottleMode::Adaptive,
+			throttle_max: Duration::from_millis(400),
+			..Default::default()
+		})
+		.1;
+
+		// a steady stream of changes for 600ms, then quiet for a second
+		let mut burst: Vec<(u64, Event)> = (0..20).map(|_| (30, modify("/a"))).collect();
+		burst.push((1000, modify("/b")));
+		let mut events = timeline(burst);
+
+		let mut throttle = Throttle::default();
+		let mut windows = Vec::new();
+		for _ in 0..3 {
+			throttle.accumulate(&working, &mut events).await;
+			windows.push(throttle.last.expect("a batch was collected").0);
+		}
+
+		assert_eq!(windows, [100, 200, 400].map(Duration::from_millis).to_vec());
+		assert_eq!(
+			timeout(
+				Duration::from_secs(2),
+				throttle.accumulate(&working, &mut events)
+			)
+			.await
+			.expect("the quiet event should be its own batch"),
+			Some(vec![modify("/b")])
+		);
+		assert_eq!(
+			throttle.last.expect("a batch was collected").0,
+			Duration::from_millis(100)
+		);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn stop_signal_ends_window() {
+		let working = working(10_000, ThrottleMode::Fixed);
+		let interrupt = Event {
+			tags: vec![Tag::Signal(MainSignal::Interrupt)],
+			metadata: Default::default(),
+			observed: None,
+		};
+		let mut events = timeline(vec![(0, modify("/a")), (10, interrupt.clone())]);
+
+		assert_eq!(
+			timeout(Duration::from_secs(1), accumulate(&working, &mut events))
+				.await
+				.expect("window should end on the signal"),
+			Some(vec![modify("/a"), interrupt])
+		);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn ended_source_is_none() {
+		let working = working(100, ThrottleMode::Fixed);
+		let mut events = stream::iter(vec![modify("/a")]);
+
+		assert_eq!(accumulate(&working, &mut events).await, None);
+	}
+}
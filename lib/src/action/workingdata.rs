@@ -7,11 +7,15 @@ use std::{
 use atomic_take::AtomicTake;
 use once_cell::sync::OnceCell;
 use tokio::{
-	process::Command,
+	process::{ChildStderr, ChildStdin, ChildStdout, Command},
 	sync::{Mutex, OwnedMutexGuard},
 };
 
-use crate::{command::Shell, event::Event, filter::Filterer, handler::Handler};
+#[cfg(feature = "async-filterer")]
+use crate::filter::AsyncFilterer;
+use crate::{
+	command::Shell, event::Event, filter::Filterer, handler::Handler, signal::process::SubSignal,
+};
 
 use super::Outcome;
 
@@ -23,11 +27,48 @@ use super::Outcome;
 pub struct WorkingData {
 	/// How long to wait for events to build up before executing an action.
 	///
-	/// This is sometimes called "debouncing." We debounce on the trailing edge: an action is
-	/// triggered only after that amount of time has passed since the first event in the cycle. The
-	/// action is called with all the collected events in the cycle.
+	/// This is sometimes called "debouncing." How the window is measured depends on the
+	/// [`throttle_mode`]: by default, an action is triggered only after that amount of time has
+	/// passed since the first event in the cycle. The action is called with all the collected events
+	/// in the cycle.
+	///
+	/// [`throttle_mode`]: WorkingData::throttle_mode
 	pub throttle: Duration,
 
+	/// How the [`throttle`] window is measured.
+	///
+	/// See [`ThrottleMode`] for the options.
+	///
+	/// [`throttle`]: WorkingData::throttle
+	pub throttle_mode: ThrottleMode,
+
+	/// The longest an action can be postponed in [`ThrottleMode::Trailing`], and the largest the
+	/// window can grow to in [`ThrottleMode::Adaptive`].
+	///
+	/// This is counted from the first event in the cycle, so that a continuous stream of events
+	/// cannot delay the action forever. It is never shorter than the [`throttle`] itself, and has
+	/// no effect in [`ThrottleMode::Fixed`].
+	///
+	/// [`throttle`]: WorkingData::throttle
+	pub throttle_max: Duration,
+
+	/// How much the window grows per batch under sustained activity in [`ThrottleMode::Adaptive`].
+	///
+	/// The window is multiplied by this, up to [`throttle_max`]. Values under 1 count as 1, which
+	/// makes the adaptive mode the same as the fixed one. Defaults to 2.
+	///
+	/// [`throttle_max`]: WorkingData::throttle_max
+	pub throttle_growth: f64,
+
+	/// Whether to drop paths which no longer exist once the throttle window is over.
+	///
+	/// When this is set, every path in the collected events is checked just before the action, and
+	/// those which are gone (e.g. temporary files from an atomic save) are removed from the events.
+	/// Events left without any path are dropped, and if no events are left at all, there's no
+	/// action. Events which never had paths are kept. This is off by default, as deletions are
+	/// often what the action is interested in.
+	pub drop_missing: bool,
+
 	/// The main handler to define: what to do when an action is triggered.
 	///
 	/// This handler is called with the [`Action`] environment, which has a certain way of returning
@@ -87,21 +128,56 @@ pub struct WorkingData {
 	/// See the [`Shell`] enum documentation for more details.
 	pub shell: Shell,
 
+	/// The signal to send to the command to ask it to stop, before killing it.
+	///
+	/// When this is `None`, stopping the command (with [`Outcome::Stop`]) kills it immediately.
+	/// Otherwise, this signal is sent first, and the command is given [`stop_timeout`] to exit by
+	/// itself. If it's still running after that, it is killed.
+	///
+	/// On Windows, signals other than [`SubSignal::ForceStop`] are not supported yet (see
+	/// [#219](https://github.com/watchexec/watchexec/issues/219)), so this is ignored and the
+	/// command is killed immediately.
+	///
+	/// [`stop_timeout`]: WorkingData::stop_timeout
+	pub stop_signal: Option<SubSignal>,
+
+	/// How long to wait for the command to exit after sending it the [`stop_signal`].
+	///
+	/// [`stop_signal`]: WorkingData::stop_signal
+	pub stop_timeout: Duration,
+
 	/// The filterer implementation to use when filtering events.
 	///
 	/// The default is a no-op, which will always pass every event.
 	pub filterer: Arc<dyn Filterer>,
+
+	/// An async filterer implementation to use instead of the [`filterer`].
+	///
+	/// When this is set, the [`filterer`] is not used.
+	///
+	/// [`filterer`]: WorkingData::filterer
+	#[cfg(feature = "async-filterer")]
+	pub async_filterer: Option<Arc<dyn AsyncFilterer>>,
 }
 
 impl fmt::Debug for WorkingData {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-		f.debug_struct("WorkingData")
+		let mut debug = f.debug_struct("WorkingData");
+		debug
 			.field("throttle", &self.throttle)
+			.field("throttle_mode", &self.throttle_mode)
+			.field("throttle_max", &self.throttle_max)
+			.field("throttle_growth", &self.throttle_growth)
+			.field("drop_missing", &self.drop_missing)
 			.field("shell", &self.shell)
 			.field("command", &self.command)
 			.field("grouped", &self.grouped)
-			.field("filterer", &self.filterer)
-			.finish_non_exhaustive()
+			.field("stop_signal", &self.stop_signal)
+			.field("stop_timeout", &self.stop_timeout)
+			.field("filterer", &self.filterer);
+		#[cfg(feature = "async-filterer")]
+		debug.field("async_filterer", &self.async_filterer);
+		debug.finish_non_exhaustive()
 	}
 }
 
@@ -110,17 +186,58 @@ impl Default for WorkingData {
 		Self {
 			// set to 50ms here, but will remain 100ms on cli until 2022
 			throttle: Duration::from_millis(50),
+			throttle_mode: ThrottleMode::default(),
+			throttle_max: Duration::from_secs(5),
+			throttle_growth: 2.0,
+			drop_missing: false,
 			action_handler: Arc::new(AtomicTake::new(Box::new(()) as _)),
 			pre_spawn_handler: Arc::new(AtomicTake::new(Box::new(()) as _)),
 			post_spawn_handler: Arc::new(AtomicTake::new(Box::new(()) as _)),
 			command: Vec::new(),
 			shell: Shell::default(),
 			grouped: true,
+			stop_signal: None,
+			stop_timeout: Duration::from_millis(250),
 			filterer: Arc::new(()),
+			#[cfg(feature = "async-filterer")]
+			async_filterer: None,
 		}
 	}
 }
 
+/// How the action [throttle](WorkingData::throttle) window is measured.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ThrottleMode {
+	/// The window starts at the first event in the cycle, and lasts for the throttle duration,
+	/// regardless of any later events.
+	Fixed,
+
+	/// The window is extended by every new event, so the action only happens once events have
+	/// stopped coming in for the throttle duration, or after [`throttle_max`] at the most.
+	///
+	/// [`throttle_max`]: WorkingData::throttle_max
+	Trailing,
+
+	/// The window is measured as for [`Fixed`](ThrottleMode::Fixed), but grows while events keep
+	/// coming in.
+	///
+	/// It starts at the throttle duration. When a cycle starts within the window of the previous
+	/// one after it ended, the window is multiplied by [`throttle_growth`], up to
+	/// [`throttle_max`]. Once a whole window goes by without events, it goes back to the throttle
+	/// duration. A single change is acted on quickly, while a long burst of changes (like a large
+	/// checkout) results in ever fewer actions.
+	///
+	/// [`throttle_growth`]: WorkingData::throttle_growth
+	/// [`throttle_max`]: WorkingData::throttle_max
+	Adaptive,
+}
+
+impl Default for ThrottleMode {
+	fn default() -> Self {
+		Self::Fixed
+	}
+}
+
 /// The environment given to the action handler.
 ///
 /// This deliberately does not implement Clone to make it hard to move it out of the handler, which
@@ -228,4 +345,26 @@ pub struct PostSpawn {
 
 	/// Whether the command was run in a process group.
 	pub grouped: bool,
+
+	/// The standard input of the process, if it was piped.
+	///
+	/// To write to the command, set its stdin to [`Stdio::piped()`] in the pre-spawn handler, then
+	/// take it from here. If it's not taken, it's closed once the handler returns, which the
+	/// command sees as the end of its input.
+	///
+	/// [`Stdio::piped()`]: std::process::Stdio::piped()
+	pub stdin: Arc<AtomicTake<ChildStdin>>,
+
+	/// The standard output of the process, if it was piped.
+	///
+	/// To read the output of the command, set its stdout to [`Stdio::piped()`] in the pre-spawn
+	/// handler, then take it from here. If it's not taken, it's closed once the handler returns.
+	///
+	/// [`Stdio::piped()`]: std::process::Stdio::piped()
+	pub stdout: Arc<AtomicTake<ChildStdout>>,
+
+	/// The standard error of the process, if it was piped.
+	///
+	/// As for [`stdout`](PostSpawn::stdout), set it to be piped in the pre-spawn handler.
+	pub stderr: Arc<AtomicTake<ChildStderr>>,
 }
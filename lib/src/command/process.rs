@@ -34,6 +34,7 @@ impl Process {
 	///
 	/// Does nothing if the process is not running.
 	#[cfg(unix)]
+	#[allow(clippy::result_large_err)]
 	pub fn signal(&mut self, sig: command_group::Signal) -> Result<(), RuntimeError> {
 		use command_group::UnixChildExt;
 
@@ -81,6 +82,7 @@ impl Process {
 	/// would be lost.
 	///
 	/// Does nothing and returns `false` immediately if the `Process` is `Done` or `None`.
+	#[allow(clippy::result_large_err)]
 	pub fn is_running(&mut self) -> Result<bool, RuntimeError> {
 		match self {
 			Self::None | Self::Done(_) => Ok(false),
@@ -118,10 +120,41 @@ impl Process {
 	///
 	/// Returns immediately with the cached exit status if the `Process` is `Done`, and with `None`
 	/// if the `Process` is `None`.
+	///
+	/// This is cancel-safe: if the future is dropped before completion, no exit status is lost.
 	pub async fn wait(&mut self) -> Result<Option<ExitStatus>, RuntimeError> {
 		match self {
 			Self::None => Ok(None),
 			Self::Done(status) => Ok(Some(*status)),
+			#[cfg(unix)]
+			Self::Grouped(c) => {
+				// The group wait blocks in a thread, which keeps going (and reaps the process) even
+				// if this future is dropped, so instead check the group every time a child ends.
+				use tokio::signal::unix::{signal, SignalKind};
+
+				trace!("waiting on process group");
+				let mut sigchld =
+					signal(SignalKind::child()).map_err(|err| RuntimeError::IoError {
+						about: "listening for SIGCHLD",
+						err,
+					})?;
+
+				let status = loop {
+					if let Some(status) = c.try_wait().map_err(|err| RuntimeError::IoError {
+						about: "waiting on process group",
+						err,
+					})? {
+						break status;
+					}
+
+					sigchld.recv().await;
+				};
+
+				trace!(?status, "converting to ::Done");
+				*self = Self::Done(status);
+				Ok(Some(status))
+			}
+			#[cfg(not(unix))]
 			Self::Grouped(c) => {
 				trace!("waiting on process group");
 				let status = c.wait().await.map_err(|err| RuntimeError::IoError {
@@ -19,7 +19,8 @@ pub enum Shell {
 	/// Use the given string as a unix shell invocation.
 	///
 	/// This means two things:
-	/// - the program is invoked with `-c` followed by the command, and
+	/// - the program is invoked with `-c` followed by the command (the `-c` may also be given at
+	///   the end of the string, as in `bash -o pipefail -c`, in which case it's not added again), and
 	/// - the string will be split on space, and the resulting vec used as execvp(3) arguments:
 	///   first is the shell program, rest are additional arguments (which come before the `-c`
 	///   mentioned above). This is a very simplistic approach deliberately: it will not support
@@ -99,7 +100,10 @@ impl Shell {
 
 				let mut c = Command::new(shprog);
 				c.args(shopts);
-				c.arg("-c").arg(cmd.join(" "));
+				if shopts.last() != Some(&"-c") {
+					c.arg("-c");
+				}
+				c.arg(cmd.join(" "));
 				c
 			}
 		}
@@ -133,6 +137,22 @@ mod test {
 		Ok(())
 	}
 
+	#[tokio::test]
+	#[cfg(unix)]
+	async fn unix_shell_none_literal_arguments() -> Result<(), std::io::Error> {
+		assert!(Shell::None
+			.to_command(&[
+				"test".into(),
+				"a 'b' $c".into(),
+				"=".into(),
+				"a 'b' $c".into()
+			])
+			.group_status()
+			.await?
+			.success());
+		Ok(())
+	}
+
 	#[tokio::test]
 	#[cfg(unix)]
 	async fn unix_shell_alternate() -> Result<(), std::io::Error> {
@@ -155,6 +175,17 @@ mod test {
 		Ok(())
 	}
 
+	#[tokio::test]
+	#[cfg(unix)]
+	async fn unix_shell_trailing_c() -> Result<(), std::io::Error> {
+		let status = Shell::Unix("bash -o pipefail -c".into())
+			.to_command(&["false | true".into()])
+			.group_status()
+			.await?;
+		assert_eq!(status.code(), Some(1));
+		Ok(())
+	}
+
 	#[tokio::test]
 	#[cfg(windows)]
 	async fn windows_shell_default() -> Result<(), std::io::Error> {
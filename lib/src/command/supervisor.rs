@@ -5,7 +5,7 @@ use std::sync::{
 
 use command_group::AsyncCommandGroup;
 use tokio::{
-	process::Command,
+	process::{ChildStderr, ChildStdin, ChildStdout, Command},
 	select, spawn,
 	sync::{
 		mpsc::{self, Sender},
@@ -47,10 +47,15 @@ pub struct Supervisor {
 	// 2. This way it's typed-enforced that I send only once
 	waiter: Option<oneshot::Receiver<()>>,
 	ongoing: Arc<AtomicBool>,
+
+	stdin: Option<ChildStdin>,
+	stdout: Option<ChildStdout>,
+	stderr: Option<ChildStderr>,
 }
 
 impl Supervisor {
 	/// Spawns the command, the supervision task, and returns a new control object.
+	#[allow(clippy::result_large_err)]
 	pub fn spawn(
 		errors: Sender<RuntimeError>,
 		events: Sender<Event>,
@@ -58,22 +63,21 @@ impl Supervisor {
 		grouped: bool,
 	) -> Result<Self, RuntimeError> {
 		debug!(%grouped, ?command, "spawning command");
-		let (process, id) = if grouped {
-			let proc = command.group_spawn().map_err(|err| RuntimeError::IoError {
-				about: "spawing process group",
-				err,
-			})?;
+		let (process, id, (stdin, stdout, stderr)) = if grouped {
+			let mut proc = command
+				.group_spawn()
+				.map_err(|err| spawn_error(command, err))?;
 			let id = proc.id().ok_or(RuntimeError::ProcessDeadOnArrival)?;
 			debug!(pgid=%id, "process group spawned");
-			(Process::Grouped(proc), id)
+			let inner = proc.inner();
+			let stdio = (inner.stdin.take(), inner.stdout.take(), inner.stderr.take());
+			(Process::Grouped(proc), id, stdio)
 		} else {
-			let proc = command.spawn().map_err(|err| RuntimeError::IoError {
-				about: "spawning process (ungrouped)",
-				err,
-			})?;
+			let mut proc = command.spawn().map_err(|err| spawn_error(command, err))?;
 			let id = proc.id().ok_or(RuntimeError::ProcessDeadOnArrival)?;
 			debug!(pid=%id, "process spawned");
-			(Process::Ungrouped(proc), id)
+			let stdio = (proc.stdin.take(), proc.stdout.take(), proc.stderr.take());
+			(Process::Ungrouped(proc), id, stdio)
 		};
 
 		let ongoing = Arc::new(AtomicBool::new(true));
@@ -148,13 +152,13 @@ impl Supervisor {
 					errors.send(err).await.ok();
 				}
 				Ok(status) => {
-					let event = Event {
-						tags: vec![
+					let event = Event::new(
+						vec![
 							Tag::Source(Source::Internal),
 							Tag::ProcessCompletion(status.map(|s| s.into())),
 						],
-						metadata: Default::default(),
-					};
+						Default::default(),
+					);
 
 					debug!(?event, "creating synthetic process completion event");
 					if let Err(err) = events.send(event).await {
@@ -182,6 +186,9 @@ impl Supervisor {
 			ongoing,
 			intervene: int_s,
 			handle, // TODO: is there anything useful to do with this? do we need to keep it?
+			stdin,
+			stdout,
+			stderr,
 		})
 	}
 
@@ -194,6 +201,36 @@ impl Supervisor {
 		self.id
 	}
 
+	/// Takes the standard input of the process, if it was piped.
+	///
+	/// This is only `Some` if the command was configured with [`Stdio::piped()`] for its stdin,
+	/// and only the first time it's called.
+	///
+	/// [`Stdio::piped()`]: std::process::Stdio::piped()
+	pub fn take_stdin(&mut self) -> Option<ChildStdin> {
+		self.stdin.take()
+	}
+
+	/// Takes the standard output of the process, if it was piped.
+	///
+	/// This is only `Some` if the command was configured with [`Stdio::piped()`] for its stdout,
+	/// and only the first time it's called.
+	///
+	/// [`Stdio::piped()`]: std::process::Stdio::piped()
+	pub fn take_stdout(&mut self) -> Option<ChildStdout> {
+		self.stdout.take()
+	}
+
+	/// Takes the standard error of the process, if it was piped.
+	///
+	/// This is only `Some` if the command was configured with [`Stdio::piped()`] for its stderr,
+	/// and only the first time it's called.
+	///
+	/// [`Stdio::piped()`]: std::process::Stdio::piped()
+	pub fn take_stderr(&mut self) -> Option<ChildStderr> {
+		self.stderr.take()
+	}
+
 	/// Issues a signal to the process.
 	///
 	/// On Windows, this currently only supports [`SubSignal::ForceStop`].
@@ -243,11 +280,14 @@ impl Supervisor {
 			return Ok(());
 		}
 
-		if let Some(waiter) = self.waiter.take() {
+		// borrow rather than take the waiter, so that this future can be cancelled (e.g. with a
+		// timeout) and waited on again later
+		if let Some(waiter) = self.waiter.as_mut() {
 			debug!("waiting on supervisor completion");
 			waiter
 				.await
 				.map_err(|err| RuntimeError::InternalSupervisor(err.to_string()))?;
+			self.waiter = None;
 			debug!("supervisor completed");
 
 			if self.ongoing.swap(false, Ordering::SeqCst) {
@@ -269,3 +309,79 @@ impl Supervisor {
 		Ok(())
 	}
 }
+
+fn spawn_error(command: &Command, err: std::io::Error) -> RuntimeError {
+	RuntimeError::CommandSpawn {
+		program: command
+			.as_std()
+			.get_program()
+			.to_string_lossy()
+			.into_owned(),
+		err,
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::time::Duration;
+
+	use tokio::{fs::read_to_string, process::Command, sync::mpsc, time::sleep};
+
+	use super::Supervisor;
+
+	#[cfg(unix)]
+	async fn is_alive(pid: &str) -> bool {
+		// a killed grandchild may linger as a zombie if nothing reaps it, which counts as gone
+		let output = Command::new("ps")
+			.args(["-o", "stat=", "-p", pid])
+			.output()
+			.await
+			.expect("ps");
+		output.status.success()
+			&& !String::from_utf8_lossy(&output.stdout)
+				.trim()
+				.starts_with('Z')
+	}
+
+	#[tokio::test]
+	#[cfg(unix)]
+	async fn unix_kill_reaches_grandchildren() {
+		let pidfile = std::env::temp_dir().join(format!(
+			"watchexec-test-grandchild-{}.pid",
+			std::process::id()
+		));
+		let mut command = Command::new("sh");
+		command
+			.arg("-c")
+			.arg(format!("sleep 30 & echo $! > {}; wait", pidfile.display()));
+
+		let (errors, _errors_r) = mpsc::channel(8);
+		let (events, _events_r) = mpsc::channel(8);
+		let mut supervisor = Supervisor::spawn(errors, events, &mut command, true).expect("spawn");
+
+		let mut grandchild = String::new();
+		for _ in 0..100 {
+			if let Ok(pid) = read_to_string(&pidfile).await {
+				grandchild = pid.trim().to_owned();
+				if !grandchild.is_empty() {
+					break;
+				}
+			}
+			sleep(Duration::from_millis(20)).await;
+		}
+		std::fs::remove_file(&pidfile).ok();
+		assert!(!grandchild.is_empty(), "grandchild never started");
+		assert!(is_alive(&grandchild).await, "grandchild should be running");
+
+		supervisor.kill().await;
+		supervisor.wait().await.expect("wait");
+
+		for _ in 0..50 {
+			if !is_alive(&grandchild).await {
+				return;
+			}
+			sleep(Duration::from_millis(20)).await;
+		}
+		panic!("grandchild {} survived the kill", grandchild);
+	}
+}
@@ -4,13 +4,16 @@ use std::{fmt, path::Path, sync::Arc, time::Duration};
 
 use atomic_take::AtomicTake;
 
+#[cfg(feature = "async-filterer")]
+use crate::filter::AsyncFilterer;
 use crate::{
-	action::{Action, PostSpawn, PreSpawn},
+	action::{Action, PostSpawn, PreSpawn, ThrottleMode},
 	command::Shell,
 	error::RuntimeError,
 	filter::Filterer,
-	fs::Watcher,
+	fs::{WatchedPath, Watcher},
 	handler::Handler,
+	signal::process::SubSignal,
 };
 
 /// Runtime configuration for [`Watchexec`][crate::Watchexec].
@@ -51,6 +54,32 @@ impl RuntimeConfig {
 		self
 	}
 
+	/// Add paths to watch without descending into them.
+	///
+	/// These are added to the pathset, so call this after [`pathset()`](RuntimeConfig::pathset),
+	/// which replaces it. See [`WatchedPath::non_recursive()`] for details.
+	pub fn pathset_non_recursive<I, P>(&mut self, paths: I) -> &mut Self
+	where
+		I: IntoIterator<Item = P>,
+		P: AsRef<Path>,
+	{
+		self.fs.pathset.extend(
+			paths
+				.into_iter()
+				.map(|p| WatchedPath::non_recursive(p.as_ref())),
+		);
+		self
+	}
+
+	/// Set how deep to watch into the pathset.
+	///
+	/// See the [documentation on the field](crate::fs::WorkingData#structfield.max_depth) for more
+	/// details.
+	pub fn pathset_max_depth(&mut self, depth: Option<usize>) -> &mut Self {
+		self.fs.max_depth = depth;
+		self
+	}
+
 	/// Set the file watcher type to use.
 	pub fn file_watcher(&mut self, watcher: Watcher) -> &mut Self {
 		self.fs.watcher = watcher;
@@ -63,6 +92,40 @@ impl RuntimeConfig {
 		self
 	}
 
+	/// Set how the action throttle window is measured.
+	///
+	/// See the [documentation on the field](crate::action::WorkingData#structfield.throttle_mode)
+	/// for more details.
+	pub fn action_throttle_mode(&mut self, mode: ThrottleMode) -> &mut Self {
+		self.action.throttle_mode = mode;
+		self
+	}
+
+	/// Set the longest an action can be postponed with the trailing throttle, and the largest the
+	/// adaptive throttle can grow to.
+	pub fn action_throttle_max(&mut self, max: impl Into<Duration>) -> &mut Self {
+		self.action.throttle_max = max.into();
+		self
+	}
+
+	/// Set how much the adaptive throttle grows under sustained activity.
+	///
+	/// See the [documentation on the field](crate::action::WorkingData#structfield.throttle_growth)
+	/// for more details.
+	pub fn action_throttle_growth(&mut self, growth: f64) -> &mut Self {
+		self.action.throttle_growth = growth;
+		self
+	}
+
+	/// Set whether to drop paths which no longer exist before an action.
+	///
+	/// See the [documentation on the field](crate::action::WorkingData#structfield.drop_missing)
+	/// for more details.
+	pub fn action_drop_missing(&mut self, drop: bool) -> &mut Self {
+		self.action.drop_missing = drop;
+		self
+	}
+
 	/// Set the shell to use to invoke commands.
 	pub fn command_shell(&mut self, shell: Shell) -> &mut Self {
 		self.action.shell = shell;
@@ -75,6 +138,21 @@ impl RuntimeConfig {
 		self
 	}
 
+	/// Set the signal to send to ask the command to stop, before killing it.
+	///
+	/// See the [documentation on the field](crate::action::WorkingData#structfield.stop_signal) for
+	/// more details.
+	pub fn command_stop_signal(&mut self, signal: SubSignal) -> &mut Self {
+		self.action.stop_signal = Some(signal);
+		self
+	}
+
+	/// Set how long to wait for the command to stop after sending the stop signal.
+	pub fn command_stop_timeout(&mut self, timeout: impl Into<Duration>) -> &mut Self {
+		self.action.stop_timeout = timeout.into();
+		self
+	}
+
 	/// Set the command to run on action.
 	pub fn command<I, S>(&mut self, command: I) -> &mut Self
 	where
@@ -91,6 +169,13 @@ impl RuntimeConfig {
 		self
 	}
 
+	/// Set an async filterer implementation to use, instead of the [`filterer`](Self::filterer).
+	#[cfg(feature = "async-filterer")]
+	pub fn async_filterer(&mut self, filterer: Arc<dyn AsyncFilterer>) -> &mut Self {
+		self.action.async_filterer = Some(filterer);
+		self
+	}
+
 	/// Set the action handler.
 	pub fn on_action(&mut self, handler: impl Handler<Action> + Send + 'static) -> &mut Self {
 		self.action.action_handler = Arc::new(AtomicTake::new(Box::new(handler) as _));
@@ -162,6 +247,10 @@ pub struct InitConfig {
 	/// If the handler errors, [_that_ error][crate::error::RuntimeError::Handler] is immediately
 	/// given to the handler. If this second handler call errors as well, its error is ignored.
 	///
+	/// If the handler returns a [`CriticalError`][crate::error::CriticalError] instead, such as
+	/// [`Elevated`][crate::error::CriticalError::Elevated] wrapping the runtime error, watchexec
+	/// stops and [`Watchexec::main()`][crate::Watchexec::main()] resolves to that error.
+	///
 	/// # Examples
 	///
 	/// ```
@@ -24,6 +24,13 @@ pub enum CriticalError {
 	#[diagnostic(code(watchexec::critical::external))]
 	External(#[from] Box<dyn std::error::Error + Send + Sync>),
 
+	/// A runtime error which the error handler decided was fatal.
+	///
+	/// See [`InitConfig::error_handler`][crate::config::InitConfig#structfield.error_handler].
+	#[error("fatal runtime error")]
+	#[diagnostic(code(watchexec::critical::elevated))]
+	Elevated(#[source] RuntimeError),
+
 	/// A critical I/O error occurred.
 	#[error("io({about}): {err}")]
 	#[diagnostic(code(watchexec::critical::io_error))]
@@ -79,6 +79,27 @@ pub enum RuntimeError {
 		err: notify::Error,
 	},
 
+	/// Error received when adding to the pathset for the filesystem watcher fails because the OS
+	/// limit on watches has been reached.
+	///
+	/// On Linux, this is inotify's `ENOSPC`. The path is then partially watched at best.
+	#[error("while adding {path:?} to the {kind:?} watcher: {err}")]
+	#[diagnostic(
+		code(watchexec::runtime::fs_watcher_too_many_watches),
+		help("on Linux, raise the fs.inotify.max_user_watches sysctl, or use the poll watcher")
+	)]
+	FsWatcherTooManyWatches {
+		/// The path that was attempted to be added.
+		path: PathBuf,
+
+		/// The kind of watcher that failed to add a path.
+		kind: Watcher,
+
+		/// The underlying error.
+		#[source]
+		err: notify::Error,
+	},
+
 	/// Error received when removing from the pathset for the filesystem watcher fails.
 	#[error("while removing {path:?} from the {kind:?} watcher: {err}")]
 	#[diagnostic(code(watchexec::runtime::fs_watcher_path_remove))]
@@ -152,6 +173,21 @@ pub enum RuntimeError {
 	#[diagnostic(code(watchexec::runtime::process))]
 	Process(#[source] std::io::Error),
 
+	/// Error received when the command cannot be spawned, e.g. because the program doesn't exist.
+	///
+	/// This names the program, which is the shell if the command is run through one. The shell
+	/// then reports a missing command itself, with the command's exit status.
+	#[error("{}", spawn_message(.program, .err))]
+	#[diagnostic(code(watchexec::runtime::command_spawn))]
+	CommandSpawn {
+		/// The program which was run.
+		program: String,
+
+		/// The underlying error.
+		#[source]
+		err: std::io::Error,
+	},
+
 	/// Error received when a process did not start correctly, or finished before we could even tell.
 	#[error("process was dead on arrival")]
 	#[diagnostic(code(watchexec::runtime::process_doa))]
@@ -171,10 +207,13 @@ pub enum RuntimeError {
 	Clearscreen(#[from] clearscreen::Error),
 
 	/// Error received when parsing a glob (possibly from an [`IgnoreFile`](crate::ignore::files::IgnoreFile)) fails.
-	#[error("cannot parse glob from ignore '{file:?}': {err}")]
+	#[error(
+		"invalid glob pattern{}: {err}",
+		.file.as_ref().map(|file| format!(" in '{}'", file.display())).unwrap_or_default()
+	)]
 	#[diagnostic(code(watchexec::runtime::ignore_glob))]
 	GlobsetGlob {
-		/// The path to the erroring ignore file.
+		/// The path to the erroring ignore file, or the origin the glob applies in.
 		file: Option<PathBuf>,
 
 		/// The underlying error.
@@ -195,6 +234,33 @@ pub enum RuntimeError {
 		err: std::io::Error,
 	},
 
+	/// Error when a path can't be checked against a glob of a
+	/// [`GlobsetFilterer`](crate::filter::globset::GlobsetFilterer).
+	///
+	/// Rather than guess whether the path passes, the filterer reports this, and the event is
+	/// dropped.
+	#[error(
+		"cannot check '{}' against '{glob}'{}: {reason}",
+		.path.display(),
+		.glob_source.as_ref().map(|source| format!(" (from {})", source.display())).unwrap_or_default()
+	)]
+	#[diagnostic(code(watchexec::runtime::filter_check))]
+	FilterCheck {
+		/// The path that couldn't be checked.
+		path: PathBuf,
+
+		/// The glob it couldn't be checked against, as it was written. Extensions are given as the
+		/// equivalent glob, like `*.tar.gz`.
+		glob: String,
+
+		/// Where the glob came from, if known: the file it was read from or the origin it was given
+		/// for.
+		glob_source: Option<PathBuf>,
+
+		/// Why the path couldn't be checked.
+		reason: &'static str,
+	},
+
 	/// Error emitted by a [`Filterer`](crate::filter::Filterer).
 	///
 	/// With built-in filterers this will probably be a dynbox of
@@ -220,3 +286,11 @@ pub enum RuntimeError {
 	#[diagnostic(code(watchexec::runtime::set))]
 	Set(#[related] Vec<RuntimeError>),
 }
+
+fn spawn_message(program: &str, err: &std::io::Error) -> String {
+	if err.kind() == std::io::ErrorKind::NotFound {
+		format!("command not found: {}", program)
+	} else {
+		format!("cannot run {}: {}", program, err)
+	}
+}
@@ -12,6 +12,7 @@ use std::{
 	num::{NonZeroI32, NonZeroI64},
 	path::{Path, PathBuf},
 	process::ExitStatus,
+	time::{Instant, SystemTime},
 };
 
 use filekind::FileEventKind;
@@ -27,13 +28,66 @@ pub mod filekind {
 }
 
 /// An event, as far as watchexec cares about.
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+///
+/// Create events with [`Event::new()`] or [`Event::default()`], as fields may be added. Events are
+/// compared by their tags and metadata only: the same change observed twice is the same event.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
 pub struct Event {
 	/// Structured, classified information which can be used to filter or classify the event.
 	pub tags: Vec<Tag>,
 
 	/// Arbitrary other information, cannot be used for filtering.
 	pub metadata: HashMap<String, Vec<String>>,
+
+	/// When the event was observed by watchexec, if known.
+	///
+	/// Event sources set this as they receive events, before any filtering. Synthetic events
+	/// created elsewhere may not have it.
+	pub observed: Option<Timestamp>,
+}
+
+impl Event {
+	/// A new event with these tags and metadata, observed now.
+	pub fn new(tags: Vec<Tag>, metadata: HashMap<String, Vec<String>>) -> Self {
+		Self {
+			tags,
+			metadata,
+			observed: Some(Timestamp::now()),
+		}
+	}
+}
+
+impl PartialEq for Event {
+	fn eq(&self, other: &Self) -> bool {
+		self.tags == other.tags && self.metadata == other.metadata
+	}
+}
+
+impl Eq for Event {}
+
+/// When an event was observed.
+///
+/// This records both clocks: the monotonic [`Instant`] should be used to order events and to
+/// measure the time between them, and the [`SystemTime`] to display or record when they happened,
+/// as it may jump around.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Timestamp {
+	/// Monotonic clock reading.
+	pub instant: Instant,
+
+	/// Wall clock reading.
+	pub system: SystemTime,
+}
+
+impl Timestamp {
+	/// The current time, on both clocks.
+	pub fn now() -> Self {
+		Self {
+			instant: Instant::now(),
+			system: SystemTime::now(),
+		}
+	}
 }
 
 /// Something which can be used to filter or qualify an event.
@@ -112,6 +166,54 @@ impl From<std::fs::FileType> for FileType {
 	}
 }
 
+impl FileType {
+	/// Whether this is a directory.
+	pub fn is_dir(self) -> bool {
+		matches!(self, Self::Dir)
+	}
+}
+
+/// Accessors for the `(path, file type)` pairs returned by [`Event::paths()`].
+///
+/// This is mostly useful to implement [`Filterer`](crate::filter::Filterer)s.
+///
+/// # Examples
+///
+/// ```
+/// use watchexec::event::{Event, EventPath, FileType, Tag};
+///
+/// let event = Event::new(
+///     vec![
+///         Tag::Path { path: "/src".into(), file_type: Some(FileType::Dir) },
+///         Tag::Path { path: "/src/lib.rs".into(), file_type: None },
+///     ],
+///     Default::default(),
+/// );
+///
+/// let dirs: Vec<Option<bool>> = event.paths().map(|path| path.is_dir()).collect();
+/// assert_eq!(dirs, vec![Some(true), None]);
+/// assert!(!event.paths().all(|path| path.is_known_dir()));
+/// ```
+pub trait EventPath {
+	/// Whether the path is a directory, or `None` if its file type is unknown.
+	fn is_dir(&self) -> Option<bool>;
+
+	/// Whether the path is known to be a directory.
+	///
+	/// An unknown file type counts as not a directory. The
+	/// [`GlobsetFilterer`](crate::filter::globset::GlobsetFilterer) instead looks at the filesystem
+	/// in that case, if it has directory-only patterns like `build/`, to match them as git would.
+	fn is_known_dir(&self) -> bool {
+		self.is_dir().unwrap_or(false)
+	}
+}
+
+impl EventPath for (&Path, Option<&FileType>) {
+	fn is_dir(&self) -> Option<bool> {
+		self.1.map(|ft| ft.is_dir())
+	}
+}
+
 impl fmt::Display for FileType {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		match self {
@@ -1,7 +1,9 @@
-//! The `Filterer` trait, two implementations, and some helper functions.
+//! The `Filterer` trait, its implementations, combinators, and some helper functions.
 
 use std::sync::Arc;
 
+#[cfg(feature = "async-filterer")]
+use futures::future::BoxFuture;
 use ignore::gitignore::GitignoreBuilder;
 
 use crate::{
@@ -9,7 +11,12 @@ use crate::{
 	event::Event,
 };
 
+#[doc(inline)]
+pub use combinators::*;
+
+mod combinators;
 pub mod globset;
+pub mod size;
 pub mod tagged;
 
 /// An interface for filtering events.
@@ -24,16 +31,101 @@ pub trait Filterer: std::fmt::Debug + Send + Sync {
 	/// the watchexec error handler. While the type signature supports any [`RuntimeError`], it's
 	/// preferred that you create your own error type and return it wrapped in the
 	/// [`RuntimeError::Filterer`] variant with the name of your filterer as `kind`.
+	#[allow(clippy::result_large_err)]
 	fn check_event(&self, event: &Event) -> Result<bool, RuntimeError>;
 }
 
+/// An interface for filtering events which may need to wait on I/O to decide.
+///
+/// This is only available with the `async-filterer` feature. Where a [`Filterer`] must answer
+/// right away, an async filterer can read file contents or ask another service about an event
+/// without blocking the thread. The action worker awaits the answer before going on with the next
+/// event, so it should still be reasonably quick: events queue up in the meantime.
+///
+/// Every [`Filterer`] is also an `AsyncFilterer`, which answers with the result of the synchronous
+/// check straight away. So the [`GlobsetFilterer`](globset::GlobsetFilterer), the combinators, and
+/// any other synchronous filterer can be used wherever an async filterer is wanted. The other way
+/// around isn't possible without blocking, so to combine an async check with synchronous ones,
+/// call the latter from the async filterer's
+/// [`check_event_async()`](AsyncFilterer::check_event_async).
+///
+/// Set one with [`RuntimeConfig::async_filterer()`](crate::config::RuntimeConfig::async_filterer).
+///
+/// ```
+/// use futures::future::BoxFuture;
+/// use watchexec::{error::RuntimeError, event::Event, filter::AsyncFilterer};
+///
+/// #[derive(Debug)]
+/// struct NotEmpty;
+///
+/// impl AsyncFilterer for NotEmpty {
+///     fn check_event_async<'a>(
+///         &'a self,
+///         event: &'a Event,
+///     ) -> BoxFuture<'a, Result<bool, RuntimeError>> {
+///         Box::pin(async move {
+///             for (path, _) in event.paths() {
+///                 if tokio::fs::metadata(path).await.map_or(true, |meta| meta.len() == 0) {
+///                     return Ok(false);
+///                 }
+///             }
+///             Ok(true)
+///         })
+///     }
+/// }
+/// ```
+#[cfg(feature = "async-filterer")]
+pub trait AsyncFilterer: std::fmt::Debug + Send + Sync {
+	/// Called on (almost) every event, and should resolve to `false` if the event is to be
+	/// discarded.
+	///
+	/// Errors are handled as for [`Filterer::check_event()`].
+	fn check_event_async<'a>(
+		&'a self,
+		event: &'a Event,
+	) -> BoxFuture<'a, Result<bool, RuntimeError>>;
+}
+
+#[cfg(feature = "async-filterer")]
+impl<T: Filterer + ?Sized> AsyncFilterer for T {
+	fn check_event_async<'a>(
+		&'a self,
+		event: &'a Event,
+	) -> BoxFuture<'a, Result<bool, RuntimeError>> {
+		Box::pin(std::future::ready(self.check_event(event)))
+	}
+}
+
 impl Filterer for () {
 	fn check_event(&self, _event: &Event) -> Result<bool, RuntimeError> {
 		Ok(true)
 	}
 }
 
-impl<T: Filterer> Filterer for Arc<T> {
+/// A filterer which passes every event.
+///
+/// This behaves the same as the `()` filterer, but is more explicit when used as a default or in
+/// combination with [`AndFilterer`], [`OrFilterer`], and [`NotFilterer`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct AlwaysFilterer;
+
+impl Filterer for AlwaysFilterer {
+	fn check_event(&self, _event: &Event) -> Result<bool, RuntimeError> {
+		Ok(true)
+	}
+}
+
+/// A filterer which rejects every event.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct NeverFilterer;
+
+impl Filterer for NeverFilterer {
+	fn check_event(&self, _event: &Event) -> Result<bool, RuntimeError> {
+		Ok(false)
+	}
+}
+
+impl<T: Filterer + ?Sized> Filterer for Arc<T> {
 	fn check_event(&self, event: &Event) -> Result<bool, RuntimeError> {
 		Arc::as_ref(self).check_event(event)
 	}
@@ -0,0 +1,60 @@
+//! Filterers which combine other filterers.
+
+use crate::{error::RuntimeError, event::Event, filter::Filterer};
+
+/// A filterer which passes an event only if both inner filterers pass it.
+///
+/// The second filterer is not checked if the first rejects the event (or errors).
+#[derive(Debug)]
+pub struct AndFilterer(pub Box<dyn Filterer>, pub Box<dyn Filterer>);
+
+impl AndFilterer {
+	/// Combine two filterers with a logical AND.
+	pub fn new(left: impl Filterer + 'static, right: impl Filterer + 'static) -> Self {
+		Self(Box::new(left), Box::new(right))
+	}
+}
+
+impl Filterer for AndFilterer {
+	fn check_event(&self, event: &Event) -> Result<bool, RuntimeError> {
+		Ok(self.0.check_event(event)? && self.1.check_event(event)?)
+	}
+}
+
+/// A filterer which passes an event if either inner filterer passes it.
+///
+/// The second filterer is not checked if the first passes the event (or errors).
+#[derive(Debug)]
+pub struct OrFilterer(pub Box<dyn Filterer>, pub Box<dyn Filterer>);
+
+impl OrFilterer {
+	/// Combine two filterers with a logical OR.
+	pub fn new(left: impl Filterer + 'static, right: impl Filterer + 'static) -> Self {
+		Self(Box::new(left), Box::new(right))
+	}
+}
+
+impl Filterer for OrFilterer {
+	fn check_event(&self, event: &Event) -> Result<bool, RuntimeError> {
+		Ok(self.0.check_event(event)? || self.1.check_event(event)?)
+	}
+}
+
+/// A filterer which passes exactly the events its inner filterer rejects.
+///
+/// Errors from the inner filterer are passed through unchanged.
+#[derive(Debug)]
+pub struct NotFilterer(pub Box<dyn Filterer>);
+
+impl NotFilterer {
+	/// Invert a filterer.
+	pub fn new(inner: impl Filterer + 'static) -> Self {
+		Self(Box::new(inner))
+	}
+}
+
+impl Filterer for NotFilterer {
+	fn check_event(&self, event: &Event) -> Result<bool, RuntimeError> {
+		self.0.check_event(event).map(|pass| !pass)
+	}
+}
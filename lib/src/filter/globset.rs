@@ -1,28 +1,76 @@
 //! A simple filterer in the style of the watchexec v1 filter.
 
-use std::ffi::OsString;
-use std::path::{Path, PathBuf};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs::symlink_metadata;
+use std::iter::once;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::{
+	gitignore::{Gitignore, GitignoreBuilder},
+	Match,
+};
 use tracing::{debug, trace, trace_span};
 
 use crate::error::RuntimeError;
-use crate::event::{Event, FileType};
-use crate::filter::Filterer;
-use crate::ignore::{IgnoreFile, IgnoreFilterer};
+use crate::event::{Event, EventPath, FileType};
+use crate::filter::{tagged::swaplock::SwapLock, Filterer};
+use crate::ignore::{is_dir_only, IgnoreFile, IgnoreFilterer};
 
 /// A path-only filterer based on globsets.
 ///
 /// This filterer mimics the behavior of the `watchexec` v1 filter, but does not match it exactly,
 /// due to differing internals. It is intended to be used as a stopgap until the tagged filterer
-/// or another advanced filterer, reaches a stable state or becomes the default. As such it does not
-/// have an updatable configuration.
+/// or another advanced filterer, reaches a stable state or becomes the default.
+///
+/// Globs follow gitignore rules: a glob without a slash, like `*.log`, matches the file name at any
+/// depth, while a glob with a slash, like `logs/*.log`, is anchored to the directory it applies in.
+/// Prefix the latter with `**/` to match it at any depth.
+///
+/// Its configuration can be replaced wholesale with [`reconfigure()`](GlobsetFilterer::reconfigure).
+///
+/// It keeps a count of the events it passed and rejected, see [`stats()`](GlobsetFilterer::stats).
 #[derive(Debug)]
 pub struct GlobsetFilterer {
+	origin: PathBuf,
+	inner: SwapLock<Globsets>,
+	counters: Counters,
+}
+
+/// The compiled configuration of a [`GlobsetFilterer`], swapped in as a whole.
+#[derive(Clone, Debug)]
+struct Globsets {
+	origin: PathBuf,
 	filters: Gitignore,
 	ignores: Gitignore,
+	roots: Vec<RootGlobs>,
 	ignore_files: IgnoreFilterer,
 	extensions: Vec<OsString>,
+	denied_extensions: Vec<OsString>,
+	scoped_extensions: Vec<ScopedExtensions>,
+	extensions_case_insensitive: bool,
+	case_insensitive: bool,
+	ignore_hidden: bool,
+	dir_only: bool,
+}
+
+/// The extensions which only apply to paths within a directory.
+#[derive(Clone, Debug)]
+struct ScopedExtensions {
+	scope: PathBuf,
+	allowed: Vec<OsString>,
+	denied: Vec<OsString>,
+}
+
+/// The filter and ignore globs compiled again relative to a watched root.
+#[derive(Clone, Debug)]
+struct RootGlobs {
+	root: PathBuf,
+	filters: Gitignore,
+	ignores: Gitignore,
 }
 
 impl GlobsetFilterer {
@@ -32,7 +80,11 @@ impl GlobsetFilterer {
 	/// second is used to ignore paths (matching paths will fail the pattern). If the filter list is
 	/// empty, only the ignore list will be used. If both lists are empty, the filter always passes.
 	///
-	/// The extensions list is used to filter files by extension.
+	/// The extensions list is used to filter files by extension, given as `js`, `.js`, or `*.js`
+	/// alike. Extensions prefixed with `!` are denied instead: files with them fail the filter,
+	/// whatever the other rules say. If there are only denied extensions, every other file passes;
+	/// if there are also allowed extensions, a file must have one of those and none of the denied
+	/// ones.
 	///
 	/// Non-path events are always passed.
 	pub async fn new(
@@ -42,111 +94,938 @@ impl GlobsetFilterer {
 		ignore_files: impl IntoIterator<Item = IgnoreFile>,
 		extensions: impl IntoIterator<Item = OsString>,
 	) -> Result<Self, RuntimeError> {
+		let mut builder = Self::builder(origin);
+		for (glob, in_path) in filters {
+			builder.filter(glob, in_path);
+		}
+		for (glob, in_path) in ignores {
+			builder.ignore(glob, in_path);
+		}
+		for file in ignore_files {
+			builder.ignore_file(file);
+		}
+		for ext in extensions {
+			builder.extension(ext, None);
+		}
+		builder.build().await
+	}
+
+	/// Create a new `GlobsetFilterer` from already-compiled filter and ignore matchers.
+	///
+	/// This is the same as [`new()`](GlobsetFilterer::new) without ignore files, but skips building
+	/// the globs: as [`Gitignore`] is cheap to clone, a large set of ignores can be compiled once
+	/// and shared between several filterers. The matchers should be rooted at the `origin`. Whether
+	/// globs are matched case-insensitively is up to how they were built.
+	pub fn from_gitignores(
+		origin: impl AsRef<Path>,
+		filters: Gitignore,
+		ignores: Gitignore,
+		extensions: impl IntoIterator<Item = OsString>,
+	) -> Self {
 		let origin = origin.as_ref();
-		let mut filters_builder = GitignoreBuilder::new(&origin);
-		let mut ignores_builder = GitignoreBuilder::new(&origin);
+		let (extensions, denied_extensions) = split_extensions(extensions, false);
+
+		let mut ignore_files = IgnoreFilterer::empty(origin);
+		ignore_files.finish();
 
-		for (filter, in_path) in filters {
-			trace!(filter=?&filter, "add filter to globset filterer");
-			filters_builder
-				.add_line(in_path.clone(), &filter)
-				.map_err(|err| RuntimeError::GlobsetGlob { file: in_path, err })?;
+		debug!(
+			num_filters=%filters.num_ignores(),
+			num_ignores=%ignores.num_ignores(),
+			num_extensions=%extensions.len(),
+			num_denied_extensions=%denied_extensions.len(),
+		"globset filterer made from compiled globs");
+
+		Self {
+			origin: origin.to_owned(),
+			inner: SwapLock::new(Globsets {
+				origin: origin.to_owned(),
+				filters,
+				ignores,
+				roots: Vec::new(),
+				ignore_files,
+				extensions,
+				denied_extensions,
+				scoped_extensions: Vec::new(),
+				extensions_case_insensitive: false,
+				case_insensitive: false,
+				ignore_hidden: false,
+				// the globs were compiled elsewhere, so there's no telling
+				dir_only: true,
+			}),
+			counters: Counters::default(),
 		}
+	}
 
-		for (ignore, in_path) in ignores {
-			trace!(ignore=?&ignore, "add ignore to globset filterer");
-			ignores_builder
-				.add_line(in_path.clone(), &ignore)
-				.map_err(|err| RuntimeError::GlobsetGlob { file: in_path, err })?;
+	/// Start building a `GlobsetFilterer` for a project origin.
+	///
+	/// This is an alternative to [`new()`](GlobsetFilterer::new) which lets rules be added one at a
+	/// time, e.g. conditionally or in a loop. See [`GlobsetFiltererBuilder`].
+	pub fn builder(origin: impl AsRef<Path>) -> GlobsetFiltererBuilder {
+		GlobsetFiltererBuilder {
+			origin: origin.as_ref().to_owned(),
+			..Default::default()
 		}
+	}
 
-		let filters = filters_builder
-			.build()
-			.map_err(|err| RuntimeError::GlobsetGlob { file: None, err })?;
-		let ignores = ignores_builder
-			.build()
-			.map_err(|err| RuntimeError::GlobsetGlob { file: None, err })?;
+	/// Replace the filters, ignores, ignore files, and extensions of this filterer.
+	///
+	/// This takes the same arguments as [`new()`](GlobsetFilterer::new), except for the origin,
+	/// which stays the same. As with `new()`, the extensions apply everywhere: any [scoped
+	/// ones](GlobsetFiltererBuilder::extension) are replaced too. Whether globs and extensions are
+	/// matched case-insensitively, whether hidden paths are ignored, and the watched
+	/// [roots](GlobsetFiltererBuilder::root), are kept from the current configuration. The new
+	/// configuration is built entirely before being swapped in, so events are always checked
+	/// against either the old or the new configuration, never a mix. If building fails, the old
+	/// configuration stays in place.
+	pub async fn reconfigure(
+		&self,
+		filters: impl IntoIterator<Item = (String, Option<PathBuf>)>,
+		ignores: impl IntoIterator<Item = (String, Option<PathBuf>)>,
+		ignore_files: impl IntoIterator<Item = IgnoreFile>,
+		extensions: impl IntoIterator<Item = OsString>,
+	) -> Result<(), RuntimeError> {
+		let builder = {
+			let inner = self.inner.borrow();
+			GlobsetFiltererBuilder {
+				origin: self.origin.clone(),
+				filters: filters.into_iter().collect(),
+				ignores: ignores.into_iter().collect(),
+				ignore_files: ignore_files.into_iter().collect(),
+				extensions: extensions.into_iter().map(|ext| (ext, None)).collect(),
+				roots: inner.roots.iter().map(|root| root.root.clone()).collect(),
+				extensions_case_insensitive: inner.extensions_case_insensitive,
+				case_insensitive: inner.case_insensitive,
+				ignore_hidden: inner.ignore_hidden,
+			}
+		};
+		let inner = Globsets::new(&builder).await?;
+		self.inner
+			.replace(inner)
+			.await
+			.map_err(|err| RuntimeError::Filterer {
+				kind: "globset",
+				err: Box::new(err) as _,
+			})
+	}
+}
+
+/// Builder for a [`GlobsetFilterer`].
+///
+/// Obtain one with [`GlobsetFilterer::builder()`]. The methods are chainable, and the rules have the
+/// same meaning as the arguments to [`GlobsetFilterer::new()`].
+#[derive(Clone, Debug, Default)]
+pub struct GlobsetFiltererBuilder {
+	origin: PathBuf,
+	filters: Vec<(String, Option<PathBuf>)>,
+	ignores: Vec<(String, Option<PathBuf>)>,
+	ignore_files: Vec<IgnoreFile>,
+	extensions: Vec<(OsString, Option<PathBuf>)>,
+	roots: Vec<PathBuf>,
+	extensions_case_insensitive: bool,
+	case_insensitive: bool,
+	ignore_hidden: bool,
+}
+
+impl GlobsetFiltererBuilder {
+	/// Add a filter glob: if there are any, only paths matching at least one will pass.
+	///
+	/// The `origin` is the directory the glob applies in, or `None` for the project origin.
+	pub fn filter(&mut self, glob: impl Into<String>, origin: Option<PathBuf>) -> &mut Self {
+		self.filters.push((glob.into(), origin));
+		self
+	}
+
+	/// Add an ignore glob: paths matching it will fail the filter.
+	///
+	/// The `origin` is the directory the glob applies in, or `None` for the project origin.
+	pub fn ignore(&mut self, glob: impl Into<String>, origin: Option<PathBuf>) -> &mut Self {
+		self.ignores.push((glob.into(), origin));
+		self
+	}
+
+	/// Add an ignore file, which will be read when building.
+	pub fn ignore_file(&mut self, file: IgnoreFile) -> &mut Self {
+		self.ignore_files.push(file);
+		self
+	}
+
+	/// Add an allowed extension, like `js`. A leading `.` or `*.` is ignored.
+	///
+	/// Prefix it with `!` to deny the extension instead. See [`GlobsetFilterer::new()`] for how
+	/// allowed and denied extensions interact.
+	///
+	/// The `origin` is the directory the extension applies in, relative to the project origin, or
+	/// `None` for everywhere. Within a directory, the extensions scoped to it (and to the
+	/// directories it's in) are checked together with those that apply everywhere, as if they had
+	/// all been given without a scope. Outside of it, they don't apply at all: so in a monorepo with
+	/// `rs` scoped to `server` and `ts` to `web`, `server/main.rs` and `web/index.ts` pass,
+	/// `server/index.ts` doesn't, and `docs/readme.md` does, as no extensions apply there.
+	pub fn extension(&mut self, ext: impl Into<OsString>, origin: Option<PathBuf>) -> &mut Self {
+		self.extensions.push((ext.into(), origin));
+		self
+	}
+
+	/// Add a watched root, which globs are also matched relative to.
+	///
+	/// Globs are matched relative to the origin, so that with an origin of `/`, `src/**` only matches
+	/// `/src`. When watching several roots, like `/a` and `/b`, add each of them: a path within one
+	/// of them is then also matched relative to it (the deepest one, if they're nested), so `src/**`
+	/// matches `/a/src` and `/b/src` too. Paths are ignored or filtered in if either match says so.
+	pub fn root(&mut self, root: impl AsRef<Path>) -> &mut Self {
+		self.roots.push(root.as_ref().to_owned());
+		self
+	}
 
-		let extensions: Vec<OsString> = extensions.into_iter().collect();
+	/// Match extensions without regard to (ASCII) case.
+	///
+	/// When enabled, `js` will match `main.js`, `Main.JS`, and `main.Js`. This is off by default.
+	pub fn extensions_case_insensitive(&mut self, enabled: bool) -> &mut Self {
+		self.extensions_case_insensitive = enabled;
+		self
+	}
+
+	/// Match filter and ignore globs without regard to case.
+	///
+	/// When enabled, `*.PNG` will match `image.png`. This is useful where the filesystem itself is
+	/// case-insensitive, like by default on Windows and macOS. It does not apply to ignore files,
+	/// nor to extensions: see [`extensions_case_insensitive()`] for those. This is off by default.
+	///
+	/// [`extensions_case_insensitive()`]: GlobsetFiltererBuilder::extensions_case_insensitive
+	pub fn case_insensitive(&mut self, enabled: bool) -> &mut Self {
+		self.case_insensitive = enabled;
+		self
+	}
+
+	/// Ignore hidden paths: those with a file or directory name starting with a `.`.
+	///
+	/// Only the part of the path within the closest watched [root](GlobsetFiltererBuilder::root),
+	/// or else the origin, is checked, so that watching within a hidden directory like
+	/// `~/.config` still works. For a path outside of those, only the file name is checked. Unlike
+	/// an ignore glob like `.*`, this also applies to everything within a hidden directory. This is
+	/// off by default.
+	pub fn ignore_hidden(&mut self, enabled: bool) -> &mut Self {
+		self.ignore_hidden = enabled;
+		self
+	}
+
+	/// Build the filterer.
+	///
+	/// This fails if any of the globs is invalid, or if an ignore file cannot be read.
+	pub async fn build(&self) -> Result<GlobsetFilterer, RuntimeError> {
+		let inner = Globsets::new(self).await?;
+
+		Ok(GlobsetFilterer {
+			origin: self.origin.clone(),
+			inner: SwapLock::new(inner),
+			counters: Counters::default(),
+		})
+	}
+}
+
+impl Globsets {
+	async fn new(config: &GlobsetFiltererBuilder) -> Result<Self, RuntimeError> {
+		let origin = &config.origin;
+		for (filter, _) in &config.filters {
+			trace!(?filter, "add filter to globset filterer");
+		}
+		for (ignore, _) in &config.ignores {
+			trace!(?ignore, "add ignore to globset filterer");
+		}
+
+		let filters = compile(origin, &config.filters, config.case_insensitive)?;
+		let ignores = compile(origin, &config.ignores, config.case_insensitive)?;
+
+		let mut roots = Vec::new();
+		for root in &config.roots {
+			if root == origin || roots.iter().any(|r: &RootGlobs| &r.root == root) {
+				continue;
+			}
+
+			roots.push(RootGlobs {
+				root: root.clone(),
+				filters: compile(root, &config.filters, config.case_insensitive)?,
+				ignores: compile(root, &config.ignores, config.case_insensitive)?,
+			});
+		}
+		// deepest first, so the first root containing a path is the closest one
+		roots.sort_by_key(|r| Reverse(r.root.components().count()));
+
+		let (allowed_extensions, denied_extensions) = split_extensions(
+			config
+				.extensions
+				.iter()
+				.filter(|(_, scope)| scope.is_none())
+				.map(|(ext, _)| ext.clone()),
+			config.extensions_case_insensitive,
+		);
+
+		let mut scoped_extensions: Vec<ScopedExtensions> = Vec::new();
+		for scope in config
+			.extensions
+			.iter()
+			.filter_map(|(_, scope)| scope.as_ref())
+		{
+			let scope = origin.join(scope);
+			if scoped_extensions.iter().any(|s| s.scope == scope) {
+				continue;
+			}
+
+			let (allowed, denied) = split_extensions(
+				config
+					.extensions
+					.iter()
+					.filter(|(_, s)| s.as_ref().map(|s| origin.join(s)).as_ref() == Some(&scope))
+					.map(|(ext, _)| ext.clone()),
+				config.extensions_case_insensitive,
+			);
+			scoped_extensions.push(ScopedExtensions {
+				scope,
+				allowed,
+				denied,
+			});
+		}
 
-		let mut ignore_files =
-			IgnoreFilterer::new(origin, &ignore_files.into_iter().collect::<Vec<_>>()).await?;
+		let mut ignore_files = IgnoreFilterer::new(origin, &config.ignore_files).await?;
 		ignore_files.finish();
 
+		let dir_only = ignore_files.has_dir_only()
+			|| config
+				.filters
+				.iter()
+				.chain(&config.ignores)
+				.any(|(glob, _)| is_dir_only(glob));
+
 		debug!(
 			num_filters=%filters.num_ignores(),
 			num_neg_filters=%filters.num_whitelists(),
 			num_ignores=%ignores.num_ignores(),
 			num_in_ignore_files=?ignore_files.num_ignores(),
 			num_neg_ignores=%ignores.num_whitelists(),
-			num_extensions=%extensions.len(),
+			num_roots=%roots.len(),
+			num_extensions=%allowed_extensions.len(),
+			num_denied_extensions=%denied_extensions.len(),
+			num_extension_scopes=%scoped_extensions.len(),
 		"globset filterer built");
 
 		Ok(Self {
+			origin: origin.clone(),
 			filters,
 			ignores,
+			roots,
 			ignore_files,
-			extensions,
+			extensions: allowed_extensions,
+			denied_extensions,
+			scoped_extensions,
+			extensions_case_insensitive: config.extensions_case_insensitive,
+			case_insensitive: config.case_insensitive,
+			ignore_hidden: config.ignore_hidden,
+			dir_only,
 		})
 	}
+
+	/// Whether a path is hidden within the closest root, or the origin. See
+	/// [`GlobsetFiltererBuilder::ignore_hidden()`].
+	fn is_hidden(&self, path: &Path) -> bool {
+		let within = self
+			.roots
+			.iter()
+			.map(|r| r.root.as_path())
+			.chain(once(self.origin.as_path()))
+			.find_map(|root| path.strip_prefix(root).ok());
+
+		let is_dotted = |name: &OsStr| name.to_string_lossy().starts_with('.');
+		match within {
+			Some(within) => within.components().any(|c| match c {
+				Component::Normal(name) => is_dotted(name),
+				_ => false,
+			}),
+			None => path.file_name().map_or(false, is_dotted),
+		}
+	}
+
+	/// The allowed and denied extensions which apply to a path: those that apply everywhere, and
+	/// those scoped to a directory the path is in.
+	fn extensions_for(&self, path: &Path) -> (Cow<'_, [OsString]>, Cow<'_, [OsString]>) {
+		let mut scopes = self
+			.scoped_extensions
+			.iter()
+			.filter(|s| path.starts_with(&s.scope))
+			.peekable();
+		if scopes.peek().is_none() {
+			return (
+				Cow::Borrowed(&self.extensions),
+				Cow::Borrowed(&self.denied_extensions),
+			);
+		}
+
+		let mut allowed = self.extensions.clone();
+		let mut denied = self.denied_extensions.clone();
+		for scope in scopes {
+			allowed.extend(scope.allowed.iter().cloned());
+			denied.extend(scope.denied.iter().cloned());
+		}
+		(Cow::Owned(allowed), Cow::Owned(denied))
+	}
+
+	/// The matchers to check a path against: the origin's, and the closest root's if it's in one.
+	fn matchers(&self, path: &Path) -> impl Iterator<Item = (&Gitignore, &Gitignore)> {
+		let root = self
+			.roots
+			.iter()
+			.find(|r| path.starts_with(&r.root))
+			.map(|r| (&r.filters, &r.ignores));
+		once((&self.filters, &self.ignores)).chain(root)
+	}
 }
 
-impl Filterer for GlobsetFilterer {
-	/// Filter an event.
+/// Compile globs relative to a root.
+#[allow(clippy::result_large_err)]
+fn compile(
+	root: &Path,
+	globs: &[(String, Option<PathBuf>)],
+	case_insensitive: bool,
+) -> Result<Gitignore, RuntimeError> {
+	let mut builder = GitignoreBuilder::new(root);
+	if case_insensitive {
+		builder
+			.case_insensitive(true)
+			.map_err(|err| RuntimeError::GlobsetGlob { file: None, err })?;
+	}
+
+	for (glob, in_path) in globs {
+		builder
+			.add_line(in_path.clone(), glob)
+			.map_err(|err| RuntimeError::GlobsetGlob {
+				file: in_path.clone(),
+				err,
+			})?;
+	}
+
+	builder
+		.build()
+		.map_err(|err| RuntimeError::GlobsetGlob { file: None, err })
+}
+
+/// Sort extensions into allowed and denied (prefixed with `!`) ones.
+///
+/// A leading `.` or `*.` is stripped, so that `.js` and `*.js` are the same as `js`.
+fn split_extensions(
+	extensions: impl IntoIterator<Item = OsString>,
+	case_insensitive: bool,
+) -> (Vec<OsString>, Vec<OsString>) {
+	let mut allowed = Vec::new();
+	let mut denied = Vec::new();
+	for ext in extensions {
+		let ext = if case_insensitive {
+			ext.to_ascii_lowercase()
+		} else {
+			ext
+		};
+
+		match ext.to_str() {
+			Some(ext) => match ext.strip_prefix('!') {
+				Some(ext) => denied.push(OsString::from(bare_extension(ext))),
+				None => allowed.push(OsString::from(bare_extension(ext))),
+			},
+			None => allowed.push(ext),
+		}
+	}
+	(allowed, denied)
+}
+
+fn bare_extension(ext: &str) -> &str {
+	ext.strip_prefix("*.")
+		.or_else(|| ext.strip_prefix('.'))
+		.unwrap_or(ext)
+}
+
+impl GlobsetFilterer {
+	/// Filter an event, and report why it passed or failed.
 	///
-	/// This implementation never errors.
-	fn check_event(&self, event: &Event) -> Result<bool, RuntimeError> {
+	/// This is the same check as [`Filterer::check_event()`], but instead of a boolean it returns
+	/// the rule which rejected the event, if any.
+	///
+	/// An event passes if any of its paths passes, so that a batch mixing ignored and wanted paths
+	/// (like a rename from an ignored name to a watched one) still goes through. When every path
+	/// fails, the reason reported is the first path's. Events without paths always pass.
+	///
+	/// If a path can't be checked, and no other path passes, this returns a
+	/// [`RuntimeError::FilterCheck`] for it. That happens when a file name which isn't valid
+	/// Unicode is checked against a compound extension like `tar.gz`.
+	#[allow(clippy::result_large_err)]
+	pub fn check_event_verbose(&self, event: &Event) -> Result<FilterReason, RuntimeError> {
 		let _span = trace_span!("filterer_check").entered();
+		let inner = self.inner.borrow();
 
-		{
-			trace!("checking internal ignore filterer");
-			if !self
-				.ignore_files
-				.check_event(event)
-				.expect("IgnoreFilterer never errors")
-			{
-				trace!("internal ignore filterer matched (fail)");
-				return Ok(false);
+		let mut first_failure = None;
+		let mut first_error = None;
+		for (path, file_type) in unique_paths(event) {
+			match inner.check_path(path, file_type) {
+				Ok(FilterReason::Passed) => return Ok(FilterReason::Passed),
+				Ok(reason) => {
+					first_failure.get_or_insert(reason);
+				}
+				Err(err) => {
+					trace!(%err, "path could not be checked");
+					first_error.get_or_insert(err);
+				}
 			}
 		}
 
-		for (path, file_type) in event.paths() {
-			let _span = trace_span!("path", ?path).entered();
-			let is_dir = file_type
-				.map(|t| matches!(t, FileType::Dir))
-				.unwrap_or(false);
+		match first_error {
+			Some(err) => Err(err),
+			None => Ok(first_failure.unwrap_or(FilterReason::Passed)),
+		}
+	}
+
+	/// How many events this filterer has passed and rejected so far, and why.
+	///
+	/// Only events checked through [`Filterer::check_event()`] are counted, so calling
+	/// [`check_event_verbose()`](GlobsetFilterer::check_event_verbose) or
+	/// [`passing_paths()`](GlobsetFilterer::passing_paths) directly doesn't change the stats. The
+	/// counts are kept across [`reconfigure()`](GlobsetFilterer::reconfigure).
+	pub fn stats(&self) -> FilterStats {
+		self.counters.snapshot()
+	}
+
+	/// How many filter globs are configured, including negated (`!`) ones.
+	///
+	/// Like the other accessors, this reflects the current configuration, so it changes after a
+	/// [`reconfigure()`](GlobsetFilterer::reconfigure).
+	pub fn filter_count(&self) -> usize {
+		let inner = self.inner.borrow();
+		inner.filters.num_ignores() as usize + inner.filters.num_whitelists() as usize
+	}
+
+	/// How many ignore globs are configured, including negated (`!`) ones.
+	///
+	/// This doesn't count the patterns from ignore files.
+	pub fn ignore_count(&self) -> usize {
+		let inner = self.inner.borrow();
+		inner.ignores.num_ignores() as usize + inner.ignores.num_whitelists() as usize
+	}
+
+	/// The allowed extensions which apply everywhere, without any leading `.` or `*.`.
+	///
+	/// With [case-insensitive extensions](GlobsetFiltererBuilder::extensions_case_insensitive),
+	/// these are lowercased.
+	pub fn extensions(&self) -> Vec<OsString> {
+		self.inner.borrow().extensions.clone()
+	}
+
+	/// The denied extensions which apply everywhere, without the leading `!` and any `.` or `*.`.
+	pub fn denied_extensions(&self) -> Vec<OsString> {
+		self.inner.borrow().denied_extensions.clone()
+	}
+
+	/// Each path of an event, and whether it passes the filterer.
+	///
+	/// This is the per-path form of [`Filterer::check_event()`], which passes an event if any of
+	/// these does. Duplicate paths are only returned once, in the order they first appear in the
+	/// event, and paths which can't be checked don't pass. Use
+	/// [`check_event_verbose()`](GlobsetFilterer::check_event_verbose) to find out why a path was
+	/// rejected, or [`passing_paths()`](GlobsetFilterer::passing_paths) for only those that pass.
+	/// Like those, this doesn't change the [stats](GlobsetFilterer::stats).
+	pub fn filter_paths<'e>(&self, event: &'e Event) -> Vec<(&'e Path, bool)> {
+		let _span = trace_span!("filterer_paths").entered();
+		let inner = self.inner.borrow();
+
+		unique_paths(event)
+			.into_iter()
+			.map(|(path, file_type)| {
+				let passed = inner
+					.check_path(path, file_type)
+					.map_or(false, |reason| reason.passed());
+				(path, passed)
+			})
+			.collect()
+	}
+
+	/// The paths of an event which pass the filterer.
+	///
+	/// Duplicate paths are only returned once, in the order they first appear in the event. This
+	/// lets consumers act on only the paths that are wanted from an event that passed overall.
+	/// Paths which can't be checked are left out.
+	pub fn passing_paths<'e>(&self, event: &'e Event) -> Vec<(&'e Path, Option<&'e FileType>)> {
+		let _span = trace_span!("filterer_paths").entered();
+		let inner = self.inner.borrow();
+
+		unique_paths(event)
+			.into_iter()
+			.filter(|(path, file_type)| {
+				inner
+					.check_path(path, *file_type)
+					.map_or(false, |reason| reason.passed())
+			})
+			.collect()
+	}
+}
 
-			if self.ignores.matched(path, is_dir).is_ignore() {
-				trace!("ignored by globset ignore");
-				return Ok(false);
+impl Globsets {
+	#[allow(clippy::result_large_err)]
+	fn check_path(
+		&self,
+		path: &Path,
+		file_type: Option<&FileType>,
+	) -> Result<FilterReason, RuntimeError> {
+		let _span = trace_span!("path", ?path).entered();
+
+		// Directory-only patterns like `build/` need to know whether the path is a directory. When
+		// the event doesn't say and there are such patterns, look at the filesystem the way git
+		// does: without following symlinks, and counting paths that don't exist (anymore) as not
+		// directories. Otherwise, an unknown file type counts as not a directory.
+		let is_dir = match (path, file_type).is_dir() {
+			Some(is_dir) => is_dir,
+			None if self.dir_only => {
+				let is_dir = symlink_metadata(path).map_or(false, |meta| meta.is_dir());
+				trace!(?is_dir, "file type unknown, checked filesystem");
+				is_dir
 			}
+			None => false,
+		};
+
+		trace!("checking internal ignore filterer");
+		if !self.ignore_files.check_path(path, is_dir) {
+			trace!("internal ignore filterer matched (fail)");
+			return Ok(FilterReason::IgnoreFile {
+				path: path.to_owned(),
+			});
+		}
 
-			if self.filters.num_ignores() > 0 && !self.filters.matched(path, is_dir).is_ignore() {
-				trace!("ignored by globset filters");
-				return Ok(false);
+		if self.ignore_hidden && self.is_hidden(path) {
+			trace!("hidden path (fail)");
+			return Ok(FilterReason::Hidden {
+				path: path.to_owned(),
+			});
+		}
+
+		for (_, ignores) in self.matchers(path) {
+			if let Match::Ignore(glob) = ignores.matched(path, is_dir) {
+				trace!(glob=%glob.original(), "ignored by globset ignore");
+				return Ok(FilterReason::Ignored {
+					path: path.to_owned(),
+					glob: glob.original().to_owned(),
+					source: glob.from().map(ToOwned::to_owned),
+				});
 			}
+		}
 
-			if !self.extensions.is_empty() {
-				if is_dir {
-					trace!("omitted from extension check due to being a dir");
-					continue;
-				}
+		if self.filters.num_ignores() > 0
+			&& !self
+				.matchers(path)
+				.any(|(filters, _)| filters.matched(path, is_dir).is_ignore())
+		{
+			trace!("ignored by globset filters");
+			return Ok(FilterReason::NotFiltered {
+				path: path.to_owned(),
+			});
+		}
+
+		let (extensions, denied_extensions) = self.extensions_for(path);
+		if extensions.is_empty() && denied_extensions.is_empty() {
+			return Ok(FilterReason::Passed);
+		}
 
-				if let Some(ext) = path.extension() {
-					if !self.extensions.iter().any(|e| e == ext) {
-						trace!("ignored by extension filter");
-						return Ok(false);
-					}
-				} else {
-					trace!(
-						?path,
-						"failed on extension check due to having no extension"
-					);
-					return Ok(false);
+		if is_dir {
+			trace!("omitted from extension check due to being a dir");
+			return Ok(FilterReason::Passed);
+		}
+
+		if let (Some(name), Some(ext)) = (path.file_name(), path.extension()) {
+			let (name, ext) = if self.extensions_case_insensitive {
+				(
+					Cow::Owned(name.to_ascii_lowercase()),
+					Cow::Owned(ext.to_ascii_lowercase()),
+				)
+			} else {
+				(Cow::Borrowed(name), Cow::Borrowed(ext))
+			};
+
+			if self.any_extension(path, &name, &ext, &denied_extensions)? {
+				trace!("ignored by denied extension");
+				Ok(FilterReason::DeniedExtension {
+					path: path.to_owned(),
+				})
+			} else if extensions.is_empty() || self.any_extension(path, &name, &ext, &extensions)? {
+				Ok(FilterReason::Passed)
+			} else {
+				trace!("ignored by extension filter");
+				Ok(FilterReason::Extension {
+					path: path.to_owned(),
+				})
+			}
+		} else if extensions.is_empty() {
+			Ok(FilterReason::Passed)
+		} else {
+			trace!(
+				?path,
+				"failed on extension check due to having no extension"
+			);
+			Ok(FilterReason::Extension {
+				path: path.to_owned(),
+			})
+		}
+	}
+
+	/// Whether the file has any of the extensions in the list.
+	#[allow(clippy::result_large_err)]
+	fn any_extension(
+		&self,
+		path: &Path,
+		name: &OsStr,
+		ext: &OsStr,
+		list: &[OsString],
+	) -> Result<bool, RuntimeError> {
+		for wanted in list {
+			match has_extension(name, ext, wanted) {
+				Some(true) => return Ok(true),
+				Some(false) => {}
+				None => {
+					return Err(RuntimeError::FilterCheck {
+						path: path.to_owned(),
+						glob: format!("*.{}", wanted.to_string_lossy()),
+						glob_source: None,
+						reason: "the file name is not valid Unicode",
+					})
 				}
 			}
 		}
 
-		Ok(true)
+		Ok(false)
 	}
 }
+
+impl Filterer for GlobsetFilterer {
+	/// Filter an event.
+	///
+	/// This errors with [`RuntimeError::FilterCheck`] when the event can't be checked. Use
+	/// [`check_event_verbose()`](GlobsetFilterer::check_event_verbose) to find out why an event was
+	/// rejected.
+	fn check_event(&self, event: &Event) -> Result<bool, RuntimeError> {
+		match self.check_event_verbose(event) {
+			Ok(reason) => {
+				self.counters.count(&reason);
+				Ok(reason.passed())
+			}
+			Err(err) => {
+				self.counters.errored.fetch_add(1, Ordering::Relaxed);
+				Err(err)
+			}
+		}
+	}
+}
+
+/// Counts of the events passed and rejected by a [`GlobsetFilterer`], by reason.
+///
+/// Returned by [`GlobsetFilterer::stats()`]. Each field but the last matches a [`FilterReason`]
+/// variant.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FilterStats {
+	/// Events which passed.
+	pub passed: u64,
+
+	/// Events rejected by an ignore file.
+	pub ignore_file: u64,
+
+	/// Events rejected by an ignore glob.
+	pub ignored: u64,
+
+	/// Events rejected for being hidden.
+	pub hidden: u64,
+
+	/// Events rejected for not matching any filter glob.
+	pub not_filtered: u64,
+
+	/// Events rejected for not having an allowed extension.
+	pub extension: u64,
+
+	/// Events rejected for having a denied extension.
+	pub denied_extension: u64,
+
+	/// Events which couldn't be checked, and were dropped with an error.
+	pub errored: u64,
+}
+
+impl FilterStats {
+	/// How many events were rejected, for any reason, including those which couldn't be checked.
+	pub fn rejected(&self) -> u64 {
+		self.ignore_file
+			+ self.ignored
+			+ self.hidden
+			+ self.not_filtered
+			+ self.extension
+			+ self.denied_extension
+			+ self.errored
+	}
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+	passed: AtomicU64,
+	ignore_file: AtomicU64,
+	ignored: AtomicU64,
+	hidden: AtomicU64,
+	not_filtered: AtomicU64,
+	extension: AtomicU64,
+	denied_extension: AtomicU64,
+	errored: AtomicU64,
+}
+
+impl Counters {
+	fn count(&self, reason: &FilterReason) {
+		let counter = match reason {
+			FilterReason::Passed => &self.passed,
+			FilterReason::IgnoreFile { .. } => &self.ignore_file,
+			FilterReason::Ignored { .. } => &self.ignored,
+			FilterReason::Hidden { .. } => &self.hidden,
+			FilterReason::NotFiltered { .. } => &self.not_filtered,
+			FilterReason::Extension { .. } => &self.extension,
+			FilterReason::DeniedExtension { .. } => &self.denied_extension,
+		};
+		counter.fetch_add(1, Ordering::Relaxed);
+	}
+
+	fn snapshot(&self) -> FilterStats {
+		FilterStats {
+			passed: self.passed.load(Ordering::Relaxed),
+			ignore_file: self.ignore_file.load(Ordering::Relaxed),
+			ignored: self.ignored.load(Ordering::Relaxed),
+			hidden: self.hidden.load(Ordering::Relaxed),
+			not_filtered: self.not_filtered.load(Ordering::Relaxed),
+			extension: self.extension.load(Ordering::Relaxed),
+			denied_extension: self.denied_extension.load(Ordering::Relaxed),
+			errored: self.errored.load(Ordering::Relaxed),
+		}
+	}
+}
+
+/// Why an event passed or failed a [`GlobsetFilterer`].
+///
+/// Returned by [`GlobsetFilterer::check_event_verbose()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FilterReason {
+	/// The event passed every rule.
+	Passed,
+
+	/// A path was ignored by one of the ignore files.
+	IgnoreFile {
+		/// The path that was ignored.
+		path: PathBuf,
+	},
+
+	/// A path matched an ignore glob.
+	Ignored {
+		/// The path that was ignored.
+		path: PathBuf,
+
+		/// The glob that matched, as it was written.
+		glob: String,
+
+		/// Where the glob came from: the file it was read from or the origin it was given for.
+		source: Option<PathBuf>,
+	},
+
+	/// A path was hidden, and hidden paths are ignored.
+	Hidden {
+		/// The path that was ignored.
+		path: PathBuf,
+	},
+
+	/// A path did not match any of the filter globs.
+	NotFiltered {
+		/// The path that was not matched.
+		path: PathBuf,
+	},
+
+	/// A file did not have any of the allowed extensions.
+	Extension {
+		/// The file that was rejected.
+		path: PathBuf,
+	},
+
+	/// A file had one of the denied extensions.
+	DeniedExtension {
+		/// The file that was rejected.
+		path: PathBuf,
+	},
+}
+
+impl FilterReason {
+	/// Whether the event passed the filterer.
+	pub fn passed(&self) -> bool {
+		matches!(self, Self::Passed)
+	}
+}
+
+impl fmt::Display for FilterReason {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Passed => write!(f, "passed"),
+			Self::IgnoreFile { path } => {
+				write!(f, "{} ignored by an ignore file", path.display())
+			}
+			Self::Ignored {
+				path,
+				glob,
+				source: Some(source),
+			} => write!(
+				f,
+				"{} ignored by '{}' (from {})",
+				path.display(),
+				glob,
+				source.display()
+			),
+			Self::Ignored {
+				path,
+				glob,
+				source: None,
+			} => write!(f, "{} ignored by '{}'", path.display(), glob),
+			Self::Hidden { path } => write!(f, "{} is hidden", path.display()),
+			Self::NotFiltered { path } => {
+				write!(f, "{} did not match any filter", path.display())
+			}
+			Self::Extension { path } => {
+				write!(f, "{} does not have an allowed extension", path.display())
+			}
+			Self::DeniedExtension { path } => {
+				write!(f, "{} has a denied extension", path.display())
+			}
+		}
+	}
+}
+
+/// The paths of an event, without duplicates.
+fn unique_paths(event: &Event) -> Vec<(&Path, Option<&FileType>)> {
+	let mut paths: Vec<(&Path, Option<&FileType>)> = Vec::new();
+	for (path, file_type) in event.paths() {
+		if !paths.iter().any(|(seen, _)| *seen == path) {
+			paths.push((path, file_type));
+		}
+	}
+	paths
+}
+
+/// Whether a file name has the wanted extension.
+///
+/// `ext` is the final extension of the file name. If `wanted` is a compound extension (like
+/// `tar.gz`) the file name is checked for the whole chain instead, and must have a non-empty stem.
+/// That needs the file name as text, so this returns `None` if it isn't valid Unicode.
+fn has_extension(name: &OsStr, ext: &OsStr, wanted: &OsStr) -> Option<bool> {
+	if ext == wanted {
+		return Some(true);
+	}
+
+	let wanted = wanted.to_string_lossy();
+	if !wanted.contains('.') {
+		return Some(false);
+	}
+
+	Some(
+		name.to_str()?
+			.strip_suffix(&*wanted)
+			.and_then(|stem| stem.strip_suffix('.'))
+			.map_or(false, |stem| !stem.is_empty()),
+	)
+}
@@ -0,0 +1,82 @@
+//! A filterer which looks at the size of changed files.
+
+use std::{fs::metadata, ops::RangeInclusive};
+
+use tracing::trace;
+
+use crate::{error::RuntimeError, event::Event, filter::Filterer};
+
+/// A path filterer which passes events for files with a size in a range.
+///
+/// Each path in an event is looked up on the filesystem when the event is checked, and the event
+/// is rejected if any of its files is smaller or larger than the range. Symlinks are followed.
+/// Events without paths are passed.
+///
+/// By the time an event is checked the path may have become a directory, or may not exist anymore
+/// (or can't be read), in which case there's no size to go by: such paths are passed by default,
+/// see [`pass_dirs()`](SizeFilterer::pass_dirs) and
+/// [`pass_missing()`](SizeFilterer::pass_missing).
+///
+/// This filterer only looks at sizes, so it's usually combined with a filterer that looks at names
+/// using an [`AndFilterer`](crate::filter::AndFilterer):
+///
+/// ```
+/// use watchexec::filter::{size::SizeFilterer, AlwaysFilterer, AndFilterer};
+///
+/// // Ignore changes to files of more than a megabyte
+/// let filterer = AndFilterer::new(AlwaysFilterer, SizeFilterer::new(0..=1024 * 1024));
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SizeFilterer {
+	range: RangeInclusive<u64>,
+	pass_dirs: bool,
+	pass_missing: bool,
+}
+
+impl SizeFilterer {
+	/// Create a filterer passing files with a size in bytes within `range`.
+	///
+	/// Use `0..=max` or `min..=u64::MAX` for a one-sided threshold.
+	pub fn new(range: RangeInclusive<u64>) -> Self {
+		Self {
+			range,
+			pass_dirs: true,
+			pass_missing: true,
+		}
+	}
+
+	/// Whether events for directories are passed (the default) or rejected.
+	pub fn pass_dirs(mut self, pass: bool) -> Self {
+		self.pass_dirs = pass;
+		self
+	}
+
+	/// Whether events for paths that don't exist or can't be read are passed (the default) or
+	/// rejected.
+	pub fn pass_missing(mut self, pass: bool) -> Self {
+		self.pass_missing = pass;
+		self
+	}
+}
+
+impl Filterer for SizeFilterer {
+	fn check_event(&self, event: &Event) -> Result<bool, RuntimeError> {
+		for (path, _) in event.paths() {
+			let pass = match metadata(path) {
+				Ok(meta) if meta.is_dir() => self.pass_dirs,
+				Ok(meta) => self.range.contains(&meta.len()),
+				Err(err) => {
+					trace!(?path, %err, "can't read file size");
+					self.pass_missing
+				}
+			};
+
+			if !pass {
+				trace!(?path, range=?self.range, "rejected by size");
+				return Ok(false);
+			}
+		}
+
+		Ok(true)
+	}
+}
@@ -13,7 +13,7 @@ use unicase::UniCase;
 
 use crate::error::RuntimeError;
 use crate::error::TaggedFiltererError;
-use crate::event::{Event, FileType, ProcessEnd, Tag};
+use crate::event::{Event, EventPath, ProcessEnd, Tag};
 use crate::filter::Filterer;
 use crate::ignore::{IgnoreFile, IgnoreFilterer};
 use crate::signal::process::SubSignal;
@@ -131,6 +131,7 @@ impl Filterer for TaggedFilterer {
 }
 
 impl TaggedFilterer {
+	#[allow(clippy::result_large_err)]
 	fn check(&self, event: &Event) -> Result<bool, TaggedFiltererError> {
 		let _span = trace_span!("filterer_check").entered();
 		trace!(?event, "checking event");
@@ -169,7 +170,7 @@ impl TaggedFilterer {
 					let mut tag_match = true;
 
 					if let (Matcher::Path, Tag::Path { path, file_type }) = (matcher, tag) {
-						let is_dir = file_type.map_or(false, |ft| matches!(ft, FileType::Dir));
+						let is_dir = (path.as_path(), file_type.as_ref()).is_known_dir();
 
 						{
 							let gc = self.glob_compiled.borrow();
@@ -324,6 +325,7 @@ impl TaggedFilterer {
 	/// So, if origin is `/path/to/project` and workdir is `/path/to/project/subtree`:
 	/// - `path=foo.bar` is resolved to `/path/to/project/subtree/foo.bar`
 	/// - `path=/foo.bar` is resolved to `/path/to/project/foo.bar`
+	#[allow(clippy::result_large_err)]
 	pub fn new(
 		origin: impl Into<PathBuf>,
 		workdir: impl Into<PathBuf>,
@@ -354,6 +356,7 @@ impl TaggedFilterer {
 
 	// Ok(Some(bool)) => the match was applied, bool is the result
 	// Ok(None) => for some precondition, the match was not done (mismatched tag, out of context, …)
+	#[allow(clippy::result_large_err)]
 	fn match_tag(&self, filter: &Filter, tag: &Tag) -> Result<Option<bool>, TaggedFiltererError> {
 		trace!(matcher=?filter.on, "matching filter to tag");
 		match (tag, filter.on) {
@@ -507,8 +510,8 @@ impl TaggedFilterer {
 				trace!(?op_filter, "pulling filters from swaplock");
 				// we want to hold the lock as little as possible, so we clone the filters
 				fs.iter()
-					.cloned()
 					.filter(|f| f.op == op_filter)
+					.cloned()
 					.collect::<Vec<_>>()
 			} else {
 				trace!(?op_filter, "no filters, erasing compiled glob");
@@ -603,6 +606,7 @@ impl Filter {
 	/// This is really an internal method to the tagged filterer machinery, exposed so you can build
 	/// your own filterer using the same types or the textual syntax. As such its behaviour is not
 	/// guaranteed to be stable (its signature is, though).
+	#[allow(clippy::result_large_err)]
 	pub fn matches(&self, subject: impl AsRef<str>) -> Result<bool, TaggedFiltererError> {
 		let subject = subject.as_ref();
 
@@ -666,6 +670,7 @@ impl Filter {
 	}
 
 	/// Returns the filter with its `in_path` canonicalised.
+	#[allow(clippy::result_large_err)]
 	pub fn canonicalised(mut self) -> Result<Self, TaggedFiltererError> {
 		if let Some(ctx) = self.in_path {
 			self.in_path =
@@ -2,15 +2,22 @@
 
 use std::{
 	collections::{HashMap, HashSet},
-	fs::metadata,
+	fs::{metadata, read_dir},
 	mem::take,
 	path::{Path, PathBuf},
 	sync::{Arc, Mutex},
 	time::Duration,
 };
 
-use notify::Watcher as _;
-use tokio::sync::{mpsc, watch};
+use notify::{
+	event::{CreateKind, EventKind, Flag},
+	Watcher as _,
+};
+use tokio::{
+	select, spawn,
+	sync::{mpsc, watch},
+	time::sleep,
+};
 use tracing::{debug, error, trace, warn};
 
 use crate::{
@@ -42,6 +49,7 @@ impl Default for Watcher {
 }
 
 impl Watcher {
+	#[allow(clippy::result_large_err)]
 	fn create(
 		self,
 		f: impl notify::EventHandler,
@@ -53,6 +61,18 @@ impl Watcher {
 		}
 		.map_err(|err| RuntimeError::FsWatcherCreate { kind: self, err })
 	}
+
+	/// Whether this watcher sees the contents of new directories without further help.
+	///
+	/// The poll watcher rescans the whole tree, and FSEvents and ReadDirectoryChangesW watch
+	/// recursively at the OS level. The inotify and kqueue backends instead add a watch on each
+	/// new directory after it's created, so anything created inside it before then is missed.
+	fn recurses_natively(self) -> bool {
+		match self {
+			Self::Poll(_) => true,
+			Self::Native => cfg!(any(target_os = "macos", target_os = "windows")),
+		}
+	}
 }
 
 /// The configuration of the [fs][self] worker.
@@ -66,41 +86,83 @@ pub struct WorkingData {
 
 	/// The kind of watcher to be used.
 	pub watcher: Watcher,
+
+	/// How deep to watch into the pathset, if not all the way.
+	///
+	/// When this is `None`, each path is watched recursively. Otherwise, the tree is walked up to
+	/// this depth when a path is added, and each directory found is watched on its own, so only
+	/// changes at most this many levels below a watched path are seen: at depth 1, only the entries
+	/// directly in the watched directories. A depth of 0 is treated as 1.
+	///
+	/// This keeps the number of watches down on large trees, at the cost of completeness:
+	/// directories created after the walk are not watched, and symlinks are not followed.
+	pub max_depth: Option<usize>,
 }
 
 /// A path to watch.
 ///
-/// This is currently only a wrapper around a [`PathBuf`], but may be augmented in the future.
-#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct WatchedPath(PathBuf);
+/// This is a [`PathBuf`], and whether to watch it recursively. Paths converted from a `PathBuf` or
+/// a `&str` are recursive; use [`non_recursive()`](WatchedPath::non_recursive) otherwise.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WatchedPath {
+	path: PathBuf,
+	recursive: bool,
+}
+
+impl WatchedPath {
+	/// A path to watch without descending into it.
+	///
+	/// Only changes to the path itself, and to the entries directly within it if it's a directory,
+	/// are seen. This is not affected by [`max_depth`](WorkingData::max_depth).
+	pub fn non_recursive(path: impl Into<PathBuf>) -> Self {
+		Self {
+			path: path.into(),
+			recursive: false,
+		}
+	}
+
+	/// Whether this path is watched recursively.
+	pub fn is_recursive(&self) -> bool {
+		self.recursive
+	}
+}
+
+impl Default for WatchedPath {
+	fn default() -> Self {
+		PathBuf::default().into()
+	}
+}
 
 impl From<PathBuf> for WatchedPath {
 	fn from(path: PathBuf) -> Self {
-		Self(path)
+		Self {
+			path,
+			recursive: true,
+		}
 	}
 }
 
 impl From<&str> for WatchedPath {
 	fn from(path: &str) -> Self {
-		Self(path.into())
+		PathBuf::from(path).into()
 	}
 }
 
 impl From<&Path> for WatchedPath {
 	fn from(path: &Path) -> Self {
-		Self(path.into())
+		path.to_owned().into()
 	}
 }
 
 impl From<WatchedPath> for PathBuf {
 	fn from(path: WatchedPath) -> Self {
-		path.0
+		path.path
 	}
 }
 
 impl AsRef<Path> for WatchedPath {
 	fn as_ref(&self) -> &Path {
-		self.0.as_ref()
+		self.path.as_ref()
 	}
 }
 
@@ -116,6 +178,10 @@ impl AsRef<Path> for WatchedPath {
 /// Note that the paths emitted by the watcher are canonicalised. No guarantee is made about the
 /// implementation or output of that canonicalisation (i.e. it might not be `std`'s).
 ///
+/// If a watched path is removed, the worker waits for it to be created again and then watches it
+/// anew, emitting a creation event for it. If the watcher reports it has dropped events, all the
+/// watched paths are added again.
+///
 /// # Examples
 ///
 /// Direct usage:
@@ -146,12 +212,67 @@ pub async fn worker(
 	debug!("launching filesystem worker");
 
 	let mut watcher_type = Watcher::default();
+	let mut watcher_depth = None;
 	let mut watcher = None;
 	let mut pathset = HashSet::new();
+	let mut subdirs: HashMap<WatchedPath, Vec<PathBuf>> = HashMap::new();
+	let mut waiting = HashSet::new();
+	let (recovery_s, mut recovery_r) = mpsc::unbounded_channel();
+
+	loop {
+		let recovery = select! {
+			changed = working.changed() => {
+				if changed.is_err() {
+					break;
+				}
 
-	while working.changed().await.is_ok() {
-		// In separate scope so we drop the working read lock as early as we can
-		let (new_watcher, to_watch, to_drop) = {
+				None
+			}
+			Some(recovery) = recovery_r.recv() => Some(recovery),
+		};
+
+		let (new_watcher, to_watch, to_drop) = if let Some(recovery) = recovery {
+			match recovery {
+				Recovery::Lost(path) => {
+					if pathset.contains(&path) && waiting.insert(path.clone()) {
+						warn!(
+							?path,
+							"watched path was removed, waiting for it to come back"
+						);
+						spawn(wait_for_path(path, recovery_s.clone()));
+					}
+					continue;
+				}
+				Recovery::Found(path) => {
+					waiting.remove(&path);
+					if !pathset.remove(&path) {
+						continue;
+					}
+
+					debug!(?path, "watched path is back, watching it again");
+					subdirs.remove(&path);
+					let scan_new_dirs =
+						!watcher_type.recurses_natively() && watcher_depth.is_none();
+					if let Err(e) = process_event(
+						Ok(notify::Event::new(EventKind::Create(CreateKind::Folder))
+							.add_path(path.clone().into())),
+						watcher_type,
+						scan_new_dirs,
+						events.clone(),
+					) {
+						errors.send(e).await?;
+					}
+
+					(None, vec![path], Vec::new())
+				}
+				Recovery::Rescan => {
+					warn!("the watcher missed some events, re-adding all watched paths");
+					subdirs.drain();
+					(None, pathset.drain().collect(), Vec::new())
+				}
+			}
+		} else {
+			// In separate scope so we drop the working read lock as early as we can
 			let data = working.borrow();
 			trace!(?data, "filesystem worker got a working data change");
 
@@ -159,11 +280,15 @@ pub async fn worker(
 				trace!("no more watched paths, dropping watcher");
 				watcher.take();
 				pathset.drain();
+				subdirs.drain();
 				continue;
 			}
 
-			if watcher.is_none() || watcher_type != data.watcher {
+			if watcher.is_none() || watcher_type != data.watcher || watcher_depth != data.max_depth
+			{
 				pathset.drain();
+				subdirs.drain();
+				watcher_depth = data.max_depth;
 
 				(Some(data.watcher), data.pathset.clone(), Vec::new())
 			} else {
@@ -189,9 +314,16 @@ pub async fn worker(
 			debug!(?kind, "creating new watcher");
 			let n_errors = errors.clone();
 			let n_events = events.clone();
+			// new directories aren't watched when the depth is limited, so don't look into them
+			let scan_new_dirs = !kind.recurses_natively() && watcher_depth.is_none();
+			let n_recovery = recovery_s.clone();
 			match kind.create(move |nev: Result<notify::Event, notify::Error>| {
 				trace!(event = ?nev, "receiving possible event from watcher");
-				if let Err(e) = process_event(nev, kind, n_events.clone()) {
+				if let Ok(nev) = &nev {
+					check_recovery(nev, kind, &n_recovery);
+				}
+
+				if let Err(e) = process_event(nev, kind, scan_new_dirs, n_events.clone()) {
 					n_errors.try_send(e).ok();
 				}
 			}) {
@@ -209,6 +341,16 @@ pub async fn worker(
 			debug!(?to_watch, ?to_drop, "applying changes to the watcher");
 
 			for path in to_drop {
+				for dir in subdirs.remove(&path).unwrap_or_default() {
+					trace!(?dir, "removing subdirectory from the watcher");
+					if let Err(err) = w.unwatch(&dir) {
+						error!(?err, "notify unwatch() error");
+						for e in notify_multi_path_errors(watcher_type, dir.into(), err, true) {
+							errors.send(e).await?;
+						}
+					}
+				}
+
 				trace!(?path, "removing path from the watcher");
 				if let Err(err) = w.unwatch(path.as_ref()) {
 					error!(?err, "notify unwatch() error");
@@ -221,8 +363,36 @@ pub async fn worker(
 			}
 
 			for path in to_watch {
-				trace!(?path, "adding path to the watcher");
-				if let Err(err) = w.watch(path.as_ref(), notify::RecursiveMode::Recursive) {
+				let mode = if !path.is_recursive() {
+					notify::RecursiveMode::NonRecursive
+				} else if let Some(depth) = watcher_depth {
+					let dirs = dirs_within(path.as_ref(), depth);
+					trace!(
+						?path,
+						?depth,
+						n = dirs.len(),
+						"adding subdirectories to the watcher"
+					);
+					let mut watched = Vec::with_capacity(dirs.len());
+					for dir in dirs {
+						if let Err(err) = w.watch(&dir, notify::RecursiveMode::NonRecursive) {
+							error!(?err, "notify watch() error");
+							for e in notify_multi_path_errors(watcher_type, dir.into(), err, false)
+							{
+								errors.send(e).await?;
+							}
+						} else {
+							watched.push(dir);
+						}
+					}
+					subdirs.insert(path.clone(), watched);
+					notify::RecursiveMode::NonRecursive
+				} else {
+					notify::RecursiveMode::Recursive
+				};
+
+				trace!(?path, ?mode, "adding path to the watcher");
+				if let Err(err) = w.watch(path.as_ref(), mode) {
 					error!(?err, "notify watch() error");
 					for e in notify_multi_path_errors(watcher_type, path, err, false) {
 						errors.send(e).await?;
@@ -263,6 +433,8 @@ fn notify_multi_path_errors(
 
 		errs.push(if rm {
 			RuntimeError::FsWatcherPathRemove { path, kind, err: e }
+		} else if matches!(e.kind, notify::ErrorKind::MaxFilesWatch) {
+			RuntimeError::FsWatcherTooManyWatches { path, kind, err: e }
 		} else {
 			RuntimeError::FsWatcherPathAdd { path, kind, err: e }
 		});
@@ -271,13 +443,89 @@ fn notify_multi_path_errors(
 	errs
 }
 
+/// Something that happened to the watcher which it needs to recover from.
+#[derive(Debug)]
+enum Recovery {
+	/// A path may have been removed, and its watch along with it.
+	Lost(WatchedPath),
+
+	/// A path that was removed exists again.
+	Found(WatchedPath),
+
+	/// The watcher's event queue overflowed, and events were dropped.
+	Rescan,
+}
+
+/// Look at an event from the watcher for signs that watches need to be re-established.
+///
+/// The native watcher forgets about a path when it's deleted, so if it's created again, it won't be
+/// watched anymore. The poll watcher looks at the path again on every poll, so it doesn't need this.
+fn check_recovery(nev: &notify::Event, kind: Watcher, recovery: &mpsc::UnboundedSender<Recovery>) {
+	if nev.flag() == Some(Flag::Rescan) {
+		recovery.send(Recovery::Rescan).ok();
+	} else if kind == Watcher::Native && matches!(nev.kind, EventKind::Remove(_)) {
+		for path in &nev.paths {
+			if !path.exists() {
+				recovery.send(Recovery::Lost(path.as_path().into())).ok();
+			}
+		}
+	}
+}
+
+/// Wait for a removed path to exist again, then report it as found.
+///
+/// Gives up once the worker is gone.
+async fn wait_for_path(path: WatchedPath, recovery: mpsc::UnboundedSender<Recovery>) {
+	while !recovery.is_closed() {
+		sleep(Duration::from_millis(500)).await;
+		if path.as_ref().exists() {
+			recovery.send(Recovery::Found(path)).ok();
+			return;
+		}
+	}
+}
+
+/// The directories under `root`, not including itself, that are less than `depth` levels down.
+///
+/// Symlinks are not followed, and directories that can't be read are skipped.
+fn dirs_within(root: &Path, depth: usize) -> Vec<PathBuf> {
+	let mut dirs = Vec::new();
+	let mut level = vec![root.to_owned()];
+	for _ in 1..depth {
+		level = level
+			.iter()
+			.filter_map(|dir| read_dir(dir).ok())
+			.flatten()
+			.flatten()
+			.filter(|entry| entry.file_type().map_or(false, |ft| ft.is_dir()))
+			.map(|entry| entry.path())
+			.collect();
+
+		if level.is_empty() {
+			break;
+		}
+
+		dirs.extend(level.iter().cloned());
+	}
+
+	dirs
+}
+
+#[allow(clippy::result_large_err)]
 fn process_event(
 	nev: Result<notify::Event, notify::Error>,
 	kind: Watcher,
+	scan_new_dirs: bool,
 	n_events: mpsc::Sender<Event>,
 ) -> Result<(), RuntimeError> {
 	let nev = nev.map_err(|err| RuntimeError::FsWatcherEvent { kind, err })?;
 
+	let new_dirs = if scan_new_dirs && matches!(nev.kind, EventKind::Create(CreateKind::Folder)) {
+		nev.paths.clone()
+	} else {
+		Vec::new()
+	};
+
 	let mut tags = Vec::with_capacity(4);
 	tags.push(Tag::Source(Source::Filesystem));
 	tags.push(Tag::FileEventKind(nev.kind));
@@ -307,7 +555,7 @@ fn process_event(
 		metadata.insert("notify-backend".to_string(), vec![src.to_string()]);
 	}
 
-	let ev = Event { tags, metadata };
+	let ev = Event::new(tags, metadata);
 
 	trace!(event = ?ev, "processed notify event into watchexec event");
 	n_events
@@ -317,5 +565,95 @@ fn process_event(
 			err,
 		})?;
 
+	for dir in new_dirs {
+		scan_new_dir(&dir, kind, &n_events)?;
+	}
+
 	Ok(())
 }
+
+/// Emit create events for the contents of a directory that was just created.
+///
+/// Files may be written into a new directory before the watcher has had a chance to add a watch
+/// on it, and those would otherwise go unseen. Subdirectories are reported as folder creations
+/// and go through [`process_event`] again, which scans them in turn. Symlinks are not followed.
+#[allow(clippy::result_large_err)]
+fn scan_new_dir(
+	dir: &Path,
+	kind: Watcher,
+	n_events: &mpsc::Sender<Event>,
+) -> Result<(), RuntimeError> {
+	let entries = match read_dir(dir) {
+		Ok(entries) => entries,
+		Err(err) => {
+			// it may well have been removed again already
+			trace!(?err, ?dir, "could not scan new directory");
+			return Ok(());
+		}
+	};
+
+	for entry in entries.flatten() {
+		let create = if entry.file_type().map_or(false, |ft| ft.is_dir()) {
+			CreateKind::Folder
+		} else {
+			CreateKind::File
+		};
+
+		trace!(path = ?entry.path(), ?create, "found entry in new directory");
+		process_event(
+			Ok(notify::Event::new(EventKind::Create(create)).add_path(entry.path())),
+			kind,
+			true,
+			n_events.clone(),
+		)?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use std::path::{Path, PathBuf};
+
+	use notify::event::{EventKind, Flag, RemoveKind};
+	use tokio::sync::mpsc;
+
+	use super::{check_recovery, dirs_within, Recovery, Watcher};
+
+	#[test]
+	fn dirs_within_depth_one_is_empty() {
+		assert!(dirs_within(Path::new("src"), 0).is_empty());
+		assert!(dirs_within(Path::new("src"), 1).is_empty());
+	}
+
+	#[test]
+	fn dirs_within_stops_at_depth() {
+		let dirs = dirs_within(Path::new("src"), 2);
+		assert!(dirs.contains(&PathBuf::from("src/filter")));
+		assert!(!dirs.contains(&PathBuf::from("src/filter/tagged")));
+
+		let dirs = dirs_within(Path::new("src"), 3);
+		assert!(dirs.contains(&PathBuf::from("src/filter/tagged")));
+	}
+
+	#[test]
+	fn overflow_asks_for_rescan() {
+		let (s, mut r) = mpsc::unbounded_channel();
+		let nev = notify::Event::new(EventKind::Other).set_flag(Flag::Rescan);
+		check_recovery(&nev, Watcher::Native, &s);
+		assert!(matches!(r.try_recv(), Ok(Recovery::Rescan)));
+	}
+
+	#[test]
+	fn removal_of_missing_path_is_lost() {
+		let (s, mut r) = mpsc::unbounded_channel();
+		let nev = notify::Event::new(EventKind::Remove(RemoveKind::Folder))
+			.add_path(PathBuf::from("src"))
+			.add_path(PathBuf::from("does-not-exist"));
+		check_recovery(&nev, Watcher::Native, &s);
+		assert!(
+			matches!(r.try_recv(), Ok(Recovery::Lost(path)) if path.as_ref() == Path::new("does-not-exist"))
+		);
+		assert!(r.try_recv().is_err());
+	}
+}
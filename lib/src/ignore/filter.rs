@@ -6,11 +6,11 @@ use ignore::{
 	Match,
 };
 use tokio::fs::read_to_string;
-use tracing::{trace, trace_span};
+use tracing::{debug, trace, trace_span};
 
 use crate::{
 	error::RuntimeError,
-	event::{Event, FileType},
+	event::{Event, EventPath},
 	filter::Filterer,
 };
 
@@ -29,6 +29,7 @@ pub struct IgnoreFilterer {
 	origin: PathBuf,
 	builder: Option<GitignoreBuilder>,
 	compiled: Gitignore,
+	dir_only: bool,
 }
 
 impl IgnoreFilterer {
@@ -41,6 +42,7 @@ impl IgnoreFilterer {
 			builder: Some(GitignoreBuilder::new(origin)),
 			origin: origin.to_owned(),
 			compiled: Gitignore::empty(),
+			dir_only: false,
 		}
 	}
 
@@ -85,21 +87,26 @@ impl IgnoreFilterer {
 
 		trace!(files=%files_contents.len(), "building ignore list");
 		let mut builder = GitignoreBuilder::new(origin);
+		let mut dir_only = false;
 		for (file, content) in files_contents.into_iter().flatten() {
 			let _span = trace_span!("loading ignore file", ?file).entered();
+			let mut patterns = 0_usize;
 			for line in content.lines() {
 				if line.is_empty() || line.starts_with('#') {
 					continue;
 				}
 
 				trace!(?line, "adding ignore line");
+				dir_only |= is_dir_only(line);
 				builder
 					.add_line(file.applies_in.clone(), line)
 					.map_err(|err| RuntimeError::GlobsetGlob {
 						file: Some(file.path.clone()),
 						err,
 					})?;
+				patterns += 1;
 			}
+			debug!(path=?file.path, applies_in=?file.applies_in, %patterns, "loaded ignore file");
 		}
 
 		trace!("compiling globset");
@@ -118,6 +125,7 @@ impl IgnoreFilterer {
 			origin: origin.to_owned(),
 			builder: Some(builder),
 			compiled,
+			dir_only,
 		})
 	}
 
@@ -126,6 +134,11 @@ impl IgnoreFilterer {
 		(self.compiled.num_ignores(), self.compiled.num_whitelists())
 	}
 
+	/// Whether any of the patterns loaded only matches directories, like `build/`.
+	pub(crate) fn has_dir_only(&self) -> bool {
+		self.dir_only
+	}
+
 	/// Deletes the internal builder, to save memory.
 	///
 	/// This makes it impossible to add new ignore files without re-compiling the whole set.
@@ -148,19 +161,23 @@ impl IgnoreFilterer {
 					})?;
 
 			let _span = trace_span!("loading ignore file", ?file).entered();
+			let mut patterns = 0_usize;
 			for line in content.lines() {
 				if line.is_empty() || line.starts_with('#') {
 					continue;
 				}
 
 				trace!(?line, "adding ignore line");
+				self.dir_only |= is_dir_only(line);
 				builder
 					.add_line(file.applies_in.clone(), line)
 					.map_err(|err| RuntimeError::GlobsetGlob {
 						file: Some(file.path.clone()),
 						err,
 					})?;
+				patterns += 1;
 			}
+			debug!(path=?file.path, applies_in=?file.applies_in, %patterns, "loaded ignore file");
 
 			self.recompile(file.path.clone())?;
 		}
@@ -168,6 +185,7 @@ impl IgnoreFilterer {
 		Ok(())
 	}
 
+	#[allow(clippy::result_large_err)]
 	fn recompile(&mut self, file: PathBuf) -> Result<(), RuntimeError> {
 		if let Some(builder) = &mut self.builder {
 			let pre_ignores = self.compiled.num_ignores();
@@ -206,6 +224,7 @@ impl IgnoreFilterer {
 				}
 
 				trace!(?line, "adding ignore line");
+				self.dir_only |= is_dir_only(line);
 				builder
 					.add_line(applies_in.clone(), line)
 					.map_err(|err| RuntimeError::GlobsetGlob { file: None, err })?;
@@ -224,15 +243,23 @@ impl IgnoreFilterer {
 	/// Note that this is a slightly different implementation than the [`Filterer`] trait, as the
 	/// latter handles events with multiple associated paths.
 	pub fn check_dir(&self, path: &Path) -> bool {
-		let _span = trace_span!("check_dir", ?path).entered();
+		self.check_path(path, true)
+	}
+
+	/// Check a single path against the ignore set.
+	///
+	/// Returns `false` if the path should be ignored. Unlike the [`Filterer`] implementation, this
+	/// looks at one path alone, so filterers can make their own decisions for events with several.
+	pub fn check_path(&self, path: &Path, is_dir: bool) -> bool {
+		let _span = trace_span!("check_path", ?path, ?is_dir).entered();
 
 		trace!("checking against compiled ignore files");
 		match if path.strip_prefix(&self.origin).is_ok() {
 			trace!("checking against path or parents");
-			self.compiled.matched_path_or_any_parents(path, true)
+			self.compiled.matched_path_or_any_parents(path, is_dir)
 		} else {
 			trace!("checking against path only");
-			self.compiled.matched(path, true)
+			self.compiled.matched(path, is_dir)
 		} {
 			Match::None => {
 				trace!("no match (pass)");
@@ -266,9 +293,7 @@ impl Filterer for IgnoreFilterer {
 
 		for (path, file_type) in event.paths() {
 			let _span = trace_span!("checking_against_compiled", ?path, ?file_type).entered();
-			let is_dir = file_type
-				.map(|t| matches!(t, FileType::Dir))
-				.unwrap_or(false);
+			let is_dir = (path, file_type).is_known_dir();
 
 			match if path.strip_prefix(&self.origin).is_ok() {
 				trace!("checking against path or parents");
@@ -300,3 +325,8 @@ impl Filterer for IgnoreFilterer {
 		Ok(pass)
 	}
 }
+
+/// Whether a gitignore pattern only matches directories, which is when it ends with a slash.
+pub(crate) fn is_dir_only(line: &str) -> bool {
+	line.trim_end().ends_with('/')
+}
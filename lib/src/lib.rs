@@ -78,6 +78,9 @@
 //! # struct MietteStub;
 //! ```
 //!
+//! The `embed` example in the examples folder shows the whole watch, filter, and run loop the CLI
+//! is built on, with a globset filterer, restarting, and clearing the screen, in a few lines.
+//!
 //! Alternatively, one can use the modules exposed by the crate and the external crates such as
 //! [ClearScreen][clearscreen] and [Command Group][command_group] to build something more advanced,
 //! at the cost of reimplementing the glue code. See the examples folder for some basic/demo tools
@@ -1,17 +1,26 @@
 //! Utilities for paths and sets of paths.
 
 use std::{
-	collections::HashMap,
+	collections::{HashMap, HashSet},
 	ffi::OsString,
+	fs::read_dir,
 	path::{Path, PathBuf},
 };
 
+use tracing::trace;
+
 use crate::event::{Event, FileType, Tag};
 
 /// The separator for paths used in environment variables.
+///
+/// This is the same separator as used in the `PATH` environment variable on the platform, such that
+/// the usual tools for splitting `PATH` also work on these variables.
 #[cfg(unix)]
 pub const PATH_SEPARATOR: &str = ":";
 /// The separator for paths used in environment variables.
+///
+/// This is the same separator as used in the `PATH` environment variable on the platform, such that
+/// the usual tools for splitting `PATH` also work on these variables.
 #[cfg(not(unix))]
 pub const PATH_SEPARATOR: &str = ";";
 
@@ -19,6 +28,10 @@ pub const PATH_SEPARATOR: &str = ";";
 ///
 /// This is a utility function which is useful for finding the common root of a set of origins.
 ///
+/// Paths are compared component by component against the first one, looking no further than the
+/// prefix found so far, so each path costs at most as many comparisons as the prefix is long, and
+/// nothing is allocated until the end. It returns as soon as the paths share nothing.
+///
 /// Returns `None` if zero paths are given or paths share no common prefix.
 pub fn common_prefix<I, P>(paths: I) -> Option<PathBuf>
 where
@@ -26,37 +39,130 @@ where
 	P: AsRef<Path>,
 {
 	let mut paths = paths.into_iter();
-	let first_path = paths.next().map(|p| p.as_ref().to_owned());
-	let mut longest_path = if let Some(ref p) = first_path {
-		p.components().collect::<Vec<_>>()
-	} else {
-		return None;
-	};
+	let first = paths.next()?;
+	let first = first.as_ref();
 
+	let mut len = first.components().count();
 	for path in paths {
-		let mut greatest_distance = 0;
-		for component_pair in path.as_ref().components().zip(longest_path.iter()) {
-			if component_pair.0 != *component_pair.1 {
-				break;
-			}
+		len = first
+			.components()
+			.zip(path.as_ref().components())
+			.take(len)
+			.take_while(|(a, b)| a == b)
+			.count();
 
-			greatest_distance += 1;
-		}
-
-		if greatest_distance != longest_path.len() {
-			longest_path.truncate(greatest_distance);
+		if len == 0 {
+			return None;
 		}
 	}
 
-	if longest_path.is_empty() {
+	if len == 0 {
 		None
 	} else {
-		let mut result = PathBuf::new();
-		for component in longest_path {
-			result.push(component.as_os_str());
+		Some(first.components().take(len).collect())
+	}
+}
+
+/// Finds the targets of symlinked directories found within the given roots, recursively.
+///
+/// Targets which are already within one of the roots or another target are not returned, as they
+/// will be watched anyway. Every directory is only walked once, such that symlink loops terminate.
+/// Broken links and unreadable directories are skipped.
+///
+/// The returned paths are canonical, in the order they were found.
+pub fn symlink_targets<I, P>(roots: I) -> Vec<PathBuf>
+where
+	I: IntoIterator<Item = P>,
+	P: AsRef<Path>,
+{
+	let mut visited = HashSet::new();
+	let mut queue: Vec<PathBuf> = roots
+		.into_iter()
+		.filter_map(|root| dunce::canonicalize(root).ok())
+		.filter(|root| visited.insert(root.clone()))
+		.collect();
+	let roots = queue.clone();
+	let mut targets: Vec<PathBuf> = Vec::new();
+
+	while let Some(dir) = queue.pop() {
+		let entries = match read_dir(&dir) {
+			Ok(entries) => entries,
+			Err(err) => {
+				trace!(?dir, %err, "skipping unreadable directory");
+				continue;
+			}
+		};
+
+		for entry in entries.flatten() {
+			let file_type = match entry.file_type() {
+				Ok(ft) => ft,
+				Err(_) => continue,
+			};
+
+			if file_type.is_dir() {
+				queue.push(entry.path());
+			} else if file_type.is_symlink() {
+				let target = match dunce::canonicalize(entry.path()) {
+					Ok(target) if target.is_dir() => target,
+					_ => continue,
+				};
+
+				if !visited.insert(target.clone())
+					|| roots
+						.iter()
+						.chain(targets.iter())
+						.any(|p| target.starts_with(p))
+				{
+					continue;
+				}
+
+				trace!(link=?entry.path(), ?target, "found symlinked directory");
+				targets.push(target.clone());
+				queue.push(target);
+			}
 		}
-		Some(result)
 	}
+
+	targets
+}
+
+/// Collects the paths of all given [`Event`]s, deduplicated.
+///
+/// Paths are returned in the order they were first seen. Non-path events are ignored.
+pub fn changed_paths<'events>(
+	events: impl IntoIterator<Item = &'events Event>,
+) -> Vec<&'events Path> {
+	let mut seen = HashSet::new();
+	events
+		.into_iter()
+		.flat_map(|event| event.paths())
+		.map(|(path, _)| path)
+		.filter(|path| seen.insert(*path))
+		.collect()
+}
+
+/// Returns the deepest directory containing all the paths of the given [`Event`]s.
+///
+/// For files this is their parent directory, and directories count as themselves, such that the
+/// common path of a single changed file is the directory it's in. This is the `COMMON` value of
+/// [`summarise_events_to_env()`].
+///
+/// Returns `None` if there are no paths, or they share no common prefix.
+pub fn common_path<'events>(events: impl IntoIterator<Item = &'events Event>) -> Option<PathBuf> {
+	common_prefix(
+		events
+			.into_iter()
+			.flat_map(|event| event.paths())
+			.map(trunk),
+	)
+}
+
+fn trunk<'p>((path, file_type): (&'p Path, Option<&FileType>)) -> &'p Path {
+	match file_type {
+		Some(FileType::Dir) => None,
+		_ => path.parent(),
+	}
+	.unwrap_or(path)
 }
 
 /// Summarise [`Event`]s as a set of environment variables by category.
@@ -71,34 +177,33 @@ where
 ///
 /// It ignores non-path events and pathed events without event kind. Multiple events are sorted in
 /// byte order and joined with the platform-specific path separator (`:` for unix, `;` for Windows).
+/// Use [`summarise_events_to_env_with_separator`] for the same separator on every platform.
 pub fn summarise_events_to_env<'events>(
 	events: impl IntoIterator<Item = &'events Event>,
 ) -> HashMap<&'static str, OsString> {
-	let mut all_trunks = Vec::new();
+	summarise_events_to_env_with_separator(events, PATH_SEPARATOR)
+}
+
+/// Summarise [`Event`]s as a set of environment variables by category, joining paths with the given
+/// separator.
+///
+/// This is otherwise the same as [`summarise_events_to_env`].
+pub fn summarise_events_to_env_with_separator<'events>(
+	events: impl IntoIterator<Item = &'events Event>,
+	separator: &str,
+) -> HashMap<&'static str, OsString> {
+	let events: Vec<&Event> = events.into_iter().collect();
+	let common_path = common_path(events.iter().copied());
+
 	let mut kind_buckets = HashMap::new();
 	for event in events {
-		let (paths, trunks): (Vec<_>, Vec<_>) = event
-			.paths()
-			.map(|(p, ft)| {
-				(
-					p.to_owned(),
-					match ft {
-						Some(FileType::Dir) => None,
-						_ => p.parent(),
-					}
-					.unwrap_or(p)
-					.to_owned(),
-				)
-			})
-			.unzip();
-		tracing::trace!(?paths, ?trunks, "event paths");
+		let paths: Vec<PathBuf> = event.paths().map(|(p, _)| p.to_owned()).collect();
+		tracing::trace!(?paths, "event paths");
 
 		if paths.is_empty() {
 			continue;
 		}
 
-		all_trunks.extend(trunks.clone());
-
 		// usually there's only one but just in case
 		for kind in event.tags.iter().filter_map(|t| {
 			if let Tag::FileEventKind(kind) = t {
@@ -114,8 +219,6 @@ pub fn summarise_events_to_env<'events>(
 		}
 	}
 
-	let common_path = common_prefix(all_trunks);
-
 	let mut grouped_buckets = HashMap::new();
 	for (kind, paths) in kind_buckets {
 		use notify::event::{AccessKind::*, AccessMode::*, EventKind::*, ModifyKind::*};
@@ -150,7 +253,7 @@ pub fn summarise_events_to_env<'events>(
 			paths.sort();
 			paths.into_iter().enumerate().for_each(|(i, path)| {
 				if i > 0 {
-					joined.push(PATH_SEPARATOR);
+					joined.push(separator);
 				}
 				joined.push(path);
 			});
@@ -181,10 +181,7 @@ async fn send_event(
 		Tag::Signal(sig),
 	];
 
-	let event = Event {
-		tags,
-		metadata: Default::default(),
-	};
+	let event = Event::new(tags, Default::default());
 
 	trace!(?event, "processed signal into event");
 	if let Err(err) = events.send(event).await {
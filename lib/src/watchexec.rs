@@ -52,6 +52,7 @@ impl Watchexec {
 	///
 	/// Returns an [`Arc`] for convenience; use [`try_unwrap`][Arc::try_unwrap()] to get the value
 	/// directly if needed.
+	#[allow(clippy::result_large_err)]
 	pub fn new(
 		mut init: InitConfig,
 		mut runtime: RuntimeConfig,
@@ -125,6 +126,7 @@ impl Watchexec {
 	}
 
 	/// Applies a new [`RuntimeConfig`] to the runtime.
+	#[allow(clippy::result_large_err)]
 	pub fn reconfigure(&self, config: RuntimeConfig) -> Result<(), ReconfigError> {
 		debug!(?config, "reconfiguring");
 		self.action_watch.send(config.action)?;
@@ -161,6 +163,7 @@ impl Watchexec {
 }
 
 #[inline]
+#[allow(clippy::result_large_err)]
 fn flatten(join_res: Result<Result<(), CriticalError>, JoinError>) -> Result<(), CriticalError> {
 	join_res
 		.map_err(CriticalError::MainTaskJoin)
@@ -179,6 +182,14 @@ async fn error_hook(
 
 		error!(%err, "runtime error");
 		if let Err(err) = handler.handle(err) {
+			let err = match err.downcast::<CriticalError>() {
+				Ok(crit) => {
+					debug!(%crit, "error handler elevated error to critical");
+					return Err(*crit);
+				}
+				Err(err) => err,
+			};
+
 			error!(%err, "error while handling error");
 			handler
 				.handle(rte("error hook", err))
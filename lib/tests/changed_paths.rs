@@ -0,0 +1,58 @@
+use std::path::{Path, PathBuf};
+
+use watchexec::{
+	event::{filekind::*, Event, Source, Tag},
+	paths::changed_paths,
+};
+
+fn event(paths: &[&str]) -> Event {
+	let mut tags = vec![
+		Tag::Source(Source::Filesystem),
+		Tag::FileEventKind(FileEventKind::Modify(ModifyKind::Any)),
+	];
+	tags.extend(paths.iter().map(|path| Tag::Path {
+		path: PathBuf::from(path),
+		file_type: None,
+	}));
+
+	Event::new(tags, Default::default())
+}
+
+#[test]
+fn no_events_no_paths() {
+	let events = Vec::<Event>::new();
+	assert_eq!(changed_paths(&events), Vec::<&Path>::new());
+}
+
+#[test]
+fn non_path_events_are_ignored() {
+	let events = vec![Event::default(), event(&[])];
+	assert_eq!(changed_paths(&events), Vec::<&Path>::new());
+}
+
+#[test]
+fn paths_across_events() {
+	let events = vec![event(&["/a/one"]), event(&["/a/two", "/b/three"])];
+	assert_eq!(
+		changed_paths(&events),
+		vec![
+			Path::new("/a/one"),
+			Path::new("/a/two"),
+			Path::new("/b/three")
+		]
+	);
+}
+
+#[test]
+fn paths_are_deduplicated_in_first_seen_order() {
+	let events = vec![
+		event(&["/a/two"]),
+		event(&["/a/one", "/a/two"]),
+		event(&["/a/two"]),
+		event(&["/a/one"]),
+	];
+	assert_eq!(
+		changed_paths(&events),
+		vec![Path::new("/a/two"), Path::new("/a/one")]
+	);
+}
@@ -1,9 +1,11 @@
-use std::{collections::HashMap, ffi::OsString};
+use std::{collections::HashMap, ffi::OsString, path::PathBuf};
 
 use notify::event::CreateKind;
 use watchexec::{
 	event::{filekind::*, Event, Tag},
-	paths::summarise_events_to_env,
+	paths::{
+		common_path, common_prefix, summarise_events_to_env, summarise_events_to_env_with_separator,
+	},
 };
 
 #[cfg(unix)]
@@ -22,16 +24,16 @@ fn ospath(path: &str) -> OsString {
 }
 
 fn event(path: &str, kind: FileEventKind) -> Event {
-	Event {
-		tags: vec![
+	Event::new(
+		vec![
 			Tag::Path {
 				path: ospath(path).into(),
 				file_type: None,
 			},
 			Tag::FileEventKind(kind),
 		],
-		metadata: Default::default(),
-	}
+		Default::default(),
+	)
 }
 
 #[test]
@@ -174,8 +176,8 @@ fn single_type_multipath() {
 					"".to_string()
 						+ "deeper/sub/folder.txt"
 						+ ENV_SEP + "dom/folder.txt"
-						+ ENV_SEP + "root.txt" + ENV_SEP
-						+ "sub/folder.txt"
+						+ ENV_SEP + "root.txt"
+						+ ENV_SEP + "sub/folder.txt"
 				)
 			),
 			("COMMON", ospath("")),
@@ -201,6 +203,21 @@ fn single_type_divergent_paths() {
 	);
 }
 
+#[test]
+fn single_type_with_separator() {
+	let events = vec![
+		event("sub/folder.txt", FileEventKind::Create(CreateKind::File)),
+		event("dom/folder.txt", FileEventKind::Create(CreateKind::File)),
+	];
+	assert_eq!(
+		summarise_events_to_env_with_separator(&events, "\n"),
+		HashMap::from([
+			("CREATED", OsString::from("dom/folder.txt\nsub/folder.txt")),
+			("COMMON", ospath("")),
+		])
+	);
+}
+
 #[test]
 fn multitype_multipath() {
 	let events = vec![
@@ -230,8 +247,8 @@ fn multitype_multipath() {
 
 #[test]
 fn multiple_paths_in_one_event() {
-	let events = vec![Event {
-		tags: vec![
+	let events = vec![Event::new(
+		vec![
 			Tag::Path {
 				path: ospath("one.txt").into(),
 				file_type: None,
@@ -242,8 +259,8 @@ fn multiple_paths_in_one_event() {
 			},
 			Tag::FileEventKind(FileEventKind::Any),
 		],
-		metadata: Default::default(),
-	}];
+		Default::default(),
+	)];
 	assert_eq!(
 		summarise_events_to_env(&events),
 		HashMap::from([
@@ -260,15 +277,12 @@ fn multiple_paths_in_one_event() {
 fn mixed_non_paths_events() {
 	let events = vec![
 		event("one.txt", FileEventKind::Any),
-		Event {
-			tags: vec![Tag::Process(1234)],
-			metadata: Default::default(),
-		},
+		Event::new(vec![Tag::Process(1234)], Default::default()),
 		event("two.txt", FileEventKind::Any),
-		Event {
-			tags: vec![Tag::FileEventKind(FileEventKind::Any)],
-			metadata: Default::default(),
-		},
+		Event::new(
+			vec![Tag::FileEventKind(FileEventKind::Any)],
+			Default::default(),
+		),
 	];
 	assert_eq!(
 		summarise_events_to_env(&events),
@@ -285,14 +299,11 @@ fn mixed_non_paths_events() {
 #[test]
 fn only_non_paths_events() {
 	let events = vec![
-		Event {
-			tags: vec![Tag::Process(1234)],
-			metadata: Default::default(),
-		},
-		Event {
-			tags: vec![Tag::FileEventKind(FileEventKind::Any)],
-			metadata: Default::default(),
-		},
+		Event::new(vec![Tag::Process(1234)], Default::default()),
+		Event::new(
+			vec![Tag::FileEventKind(FileEventKind::Any)],
+			Default::default(),
+		),
 	];
 	assert_eq!(summarise_events_to_env(&events), HashMap::new());
 }
@@ -313,12 +324,50 @@ fn multipath_is_sorted() {
 				"OTHERWISE_CHANGED",
 				OsString::from(
 					"".to_string()
-						+ "0123.txt" + ENV_SEP + "a.txt"
-						+ ENV_SEP + "b.txt" + ENV_SEP
-						+ "c.txt" + ENV_SEP + "ᄁ.txt"
+						+ "0123.txt" + ENV_SEP
+						+ "a.txt" + ENV_SEP
+						+ "b.txt" + ENV_SEP
+						+ "c.txt" + ENV_SEP
+						+ "ᄁ.txt"
 				)
 			),
 			("COMMON", ospath("")),
 		])
 	);
 }
+
+#[test]
+fn common_path_matches_env() {
+	let events = vec![
+		event("sub/a.txt", FileEventKind::Any),
+		event("sub/deeper/b.txt", FileEventKind::Any),
+	];
+	assert_eq!(
+		common_path(&events).map(OsString::from),
+		summarise_events_to_env(&events).remove("COMMON")
+	);
+	assert_eq!(
+		common_path(&events).map(OsString::from),
+		Some(ospath("sub"))
+	);
+}
+
+#[test]
+fn common_path_of_nothing() {
+	assert_eq!(common_path(&Vec::<Event>::new()), None);
+}
+
+#[test]
+fn common_prefix_stops_at_divergence() {
+	assert_eq!(
+		common_prefix(["/a/b/c/d", "/a/b/e", "/a/b/c"]),
+		Some(PathBuf::from("/a/b"))
+	);
+	assert_eq!(
+		common_prefix(["/a/b/c", "/a/b/c"]),
+		Some(PathBuf::from("/a/b/c"))
+	);
+	assert_eq!(common_prefix(["/a/b", "/c/d"]), Some(PathBuf::from("/")));
+	assert_eq!(common_prefix(["a/b", "c/d", "a/b"]), None);
+	assert_eq!(common_prefix(Vec::<PathBuf>::new()), None);
+}
@@ -0,0 +1,77 @@
+#![cfg(feature = "async-filterer")]
+
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use watchexec::{
+	error::RuntimeError,
+	event::{Event, Tag},
+	filter::{globset::GlobsetFilterer, AsyncFilterer, NeverFilterer},
+};
+
+fn event(path: &str) -> Event {
+	Event::new(
+		vec![Tag::Path {
+			path: path.into(),
+			file_type: None,
+		}],
+		Default::default(),
+	)
+}
+
+#[derive(Debug)]
+struct Exists;
+
+impl AsyncFilterer for Exists {
+	fn check_event_async<'a>(
+		&'a self,
+		event: &'a Event,
+	) -> BoxFuture<'a, Result<bool, RuntimeError>> {
+		Box::pin(async move {
+			for (path, _) in event.paths() {
+				if tokio::fs::metadata(path).await.is_err() {
+					return Ok(false);
+				}
+			}
+			Ok(true)
+		})
+	}
+}
+
+#[tokio::test]
+async fn sync_filterers_are_async_filterers() {
+	let globset: Arc<dyn AsyncFilterer> = Arc::new(
+		GlobsetFilterer::builder("/")
+			.extension("rs", None)
+			.build()
+			.await
+			.expect("building filterer"),
+	);
+	assert!(globset
+		.check_event_async(&event("/src/main.rs"))
+		.await
+		.expect("checking event"));
+	assert!(!globset
+		.check_event_async(&event("/src/main.ts"))
+		.await
+		.expect("checking event"));
+
+	let never: Arc<dyn AsyncFilterer> = Arc::new(NeverFilterer);
+	assert!(!never
+		.check_event_async(&event("/src/main.rs"))
+		.await
+		.expect("checking event"));
+}
+
+#[tokio::test]
+async fn async_filterers_can_do_io() {
+	let here = std::env::current_dir().expect("current dir");
+	assert!(Exists
+		.check_event_async(&event(here.join("Cargo.toml").to_str().unwrap()))
+		.await
+		.expect("checking event"));
+	assert!(!Exists
+		.check_event_async(&event(here.join("nonexistent").to_str().unwrap()))
+		.await
+		.expect("checking event"));
+}
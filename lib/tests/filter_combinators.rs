@@ -0,0 +1,108 @@
+use std::sync::{
+	atomic::{AtomicUsize, Ordering},
+	Arc,
+};
+
+use watchexec::{
+	error::RuntimeError,
+	event::Event,
+	filter::{AlwaysFilterer, AndFilterer, Filterer, NeverFilterer, NotFilterer, OrFilterer},
+};
+
+#[derive(Debug)]
+struct Fixed(bool, Arc<AtomicUsize>);
+
+impl Fixed {
+	fn new(pass: bool) -> (Self, Arc<AtomicUsize>) {
+		let calls = Arc::new(AtomicUsize::new(0));
+		(Self(pass, calls.clone()), calls)
+	}
+}
+
+impl Filterer for Fixed {
+	fn check_event(&self, _event: &Event) -> Result<bool, RuntimeError> {
+		self.1.fetch_add(1, Ordering::SeqCst);
+		Ok(self.0)
+	}
+}
+
+#[derive(Debug)]
+struct Failing;
+
+impl Filterer for Failing {
+	fn check_event(&self, _event: &Event) -> Result<bool, RuntimeError> {
+		Err(RuntimeError::Exit)
+	}
+}
+
+fn check(filterer: &dyn Filterer) -> bool {
+	filterer.check_event(&Event::default()).unwrap()
+}
+
+#[test]
+fn and_truth_table() {
+	for (a, b) in [(false, false), (false, true), (true, false), (true, true)] {
+		let filterer = AndFilterer::new(Fixed::new(a).0, Fixed::new(b).0);
+		assert_eq!(check(&filterer), a && b, "{a} && {b}");
+	}
+}
+
+#[test]
+fn or_truth_table() {
+	for (a, b) in [(false, false), (false, true), (true, false), (true, true)] {
+		let filterer = OrFilterer::new(Fixed::new(a).0, Fixed::new(b).0);
+		assert_eq!(check(&filterer), a || b, "{a} || {b}");
+	}
+}
+
+#[test]
+fn not_inverts() {
+	assert!(!check(&NotFilterer::new(Fixed::new(true).0)));
+	assert!(check(&NotFilterer::new(Fixed::new(false).0)));
+}
+
+#[test]
+fn and_short_circuits() {
+	let (right, calls) = Fixed::new(true);
+	assert!(!check(&AndFilterer::new(Fixed::new(false).0, right)));
+	assert_eq!(calls.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn or_short_circuits() {
+	let (right, calls) = Fixed::new(false);
+	assert!(check(&OrFilterer::new(Fixed::new(true).0, right)));
+	assert_eq!(calls.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn errors_propagate() {
+	let event = Event::default();
+	assert!(AndFilterer::new(Fixed::new(true).0, Failing)
+		.check_event(&event)
+		.is_err());
+	assert!(OrFilterer::new(Failing, Fixed::new(true).0)
+		.check_event(&event)
+		.is_err());
+	assert!(NotFilterer::new(Failing).check_event(&event).is_err());
+}
+
+#[test]
+fn nests() {
+	let filterer = AndFilterer::new(
+		NotFilterer::new(Fixed::new(false).0),
+		OrFilterer::new(Fixed::new(false).0, Fixed::new(true).0),
+	);
+	assert!(check(&filterer));
+}
+
+#[test]
+fn always_and_never() {
+	assert_eq!(std::mem::size_of::<AlwaysFilterer>(), 0);
+	assert_eq!(std::mem::size_of::<NeverFilterer>(), 0);
+	assert!(check(&AlwaysFilterer));
+	assert!(!check(&NeverFilterer));
+	assert!(check(&NotFilterer::new(NeverFilterer)));
+	assert!(!check(&AndFilterer::new(AlwaysFilterer, NeverFilterer)));
+	assert!(check(&OrFilterer::new(NeverFilterer, AlwaysFilterer)));
+}
@@ -1,5 +1,17 @@
+use std::ffi::OsString;
+
+use ignore::gitignore::GitignoreBuilder;
+
 mod helpers;
 use helpers::globset::*;
+use watchexec::{
+	error::RuntimeError,
+	event::{Event, FileType, Tag},
+	filter::{
+		globset::{FilterReason, GlobsetFilterer},
+		Filterer,
+	},
+};
 
 #[tokio::test]
 async fn empty_filter_passes_everything() {
@@ -63,6 +75,26 @@ async fn glob_single_final_ext_star() {
 	filterer.dir_does_pass("Cargo.toml");
 }
 
+#[tokio::test]
+async fn glob_without_slash_matches_at_any_depth() {
+	let filterer = filt(&["*.log"], &["debug-*.log"], &[]).await;
+
+	filterer.file_does_pass("app.log");
+	filterer.file_does_pass("deep/nested/app.log");
+	filterer.file_does_pass("/test/deep/nested/app.log");
+	filterer.file_doesnt_pass("deep/nested/debug-app.log");
+	filterer.file_doesnt_pass("deep/nested/app.txt");
+}
+
+#[tokio::test]
+async fn glob_with_slash_is_anchored() {
+	let filterer = filt(&["logs/*.log"], &[], &[]).await;
+
+	filterer.file_does_pass("logs/app.log");
+	filterer.file_doesnt_pass("deep/logs/app.log");
+	filterer.file_doesnt_pass("app.log");
+}
+
 #[tokio::test]
 async fn glob_star_trailing_slash() {
 	let filterer = filt(&["Cargo.*/"], &[], &[]).await;
@@ -392,6 +424,42 @@ async fn ignore_folder_incorrectly_with_bare_and_trailing_slash() {
 	filterer.dir_does_pass("prunes/carrots/cauliflowers/artichokes/oranges");
 }
 
+#[tokio::test]
+async fn ignore_directory_only_pattern() {
+	let filterer = filt(&[], &["build/"], &[]).await;
+
+	filterer.dir_doesnt_pass("build");
+	filterer.dir_doesnt_pass("/test/build");
+	filterer.dir_doesnt_pass("apples/build");
+	filterer.file_does_pass("build");
+	filterer.file_does_pass("apples/build");
+	filterer.dir_does_pass("builds");
+	filterer.dir_does_pass("rebuild");
+
+	// no such path on disk, so like git, not a directory
+	filterer.unk_does_pass("build");
+}
+
+#[tokio::test]
+async fn ignore_directory_only_pattern_checks_disk_for_unknown_types() {
+	// the tests run from the crate root, where src/ is a directory and Cargo.toml is a file
+	let filterer = filt(&[], &["src/", "Cargo.toml/"], &[]).await;
+
+	filterer.unk_doesnt_pass("src");
+	filterer.unk_does_pass("Cargo.toml");
+	filterer.file_does_pass("src");
+	filterer.dir_doesnt_pass("Cargo.toml");
+}
+
+#[tokio::test]
+async fn unknown_types_are_not_looked_up_without_directory_only_patterns() {
+	// src/ is a directory on disk, which would be omitted from the extension check
+	let filterer = filt(&[], &["build"], &["rs"]).await;
+
+	filterer.unk_doesnt_pass("src");
+	filterer.dir_does_pass("src");
+}
+
 #[tokio::test]
 async fn ignore_folder_incorrectly_with_only_double_double_glob() {
 	let filterer = filt(&[], &["**/prunes/**"], &[]).await;
@@ -451,3 +519,611 @@ async fn ignore_folder_correctly_with_double_and_double_double_globs() {
 	filterer.dir_doesnt_pass("prunes/carrots/cauliflowers/oranges");
 	filterer.dir_doesnt_pass("prunes/carrots/cauliflowers/artichokes/oranges");
 }
+
+#[tokio::test]
+async fn reconfigure_replaces_everything() {
+	let filterer = filt(&["Cargo.toml"], &["target"], &["toml"]).await;
+
+	filterer.file_does_pass("Cargo.toml");
+	filterer.file_doesnt_pass("package.json");
+
+	filterer
+		.reconfigure(
+			vec![("package.json".to_string(), None)],
+			vec![],
+			vec![],
+			vec!["json".into()],
+		)
+		.await
+		.expect("reconfiguring filterer");
+
+	filterer.file_doesnt_pass("Cargo.toml");
+	filterer.file_does_pass("package.json");
+	filterer.file_does_pass("target/package.json");
+}
+
+#[tokio::test]
+async fn reconfigure_failure_keeps_old_config() {
+	let filterer = filt(&["Cargo.toml"], &[], &[]).await;
+
+	assert!(filterer
+		.reconfigure(vec![("[".to_string(), None)], vec![], vec![], vec![])
+		.await
+		.is_err());
+
+	filterer.file_does_pass("Cargo.toml");
+	filterer.file_doesnt_pass("package.json");
+}
+
+#[tokio::test]
+async fn builder_adds_rules_incrementally() {
+	let origin = dunce::canonicalize(".").unwrap();
+	let mut builder = GlobsetFilterer::builder(&origin);
+	for ext in ["toml", "json"] {
+		builder.extension(ext, None);
+	}
+	builder.filter("Cargo.*", None).ignore("Cargo.json", None);
+	let filterer = builder.build().await.expect("building filterer");
+
+	filterer.file_does_pass("Cargo.toml");
+	filterer.file_doesnt_pass("Cargo.json");
+	filterer.file_doesnt_pass("Cargo.lock");
+	filterer.file_doesnt_pass("package.json");
+}
+
+#[tokio::test]
+async fn extensions_case_insensitive() {
+	let origin = dunce::canonicalize(".").unwrap();
+	let filterer = GlobsetFilterer::builder(&origin)
+		.extension("Js", None)
+		.extensions_case_insensitive(true)
+		.build()
+		.await
+		.expect("building filterer");
+
+	filterer.file_does_pass("main.js");
+	filterer.file_does_pass("Main.JS");
+	filterer.file_does_pass("main.Js");
+	filterer.file_doesnt_pass("main.ts");
+	filterer.file_doesnt_pass("main.JSON");
+}
+
+#[tokio::test]
+async fn extensions_case_sensitive_by_default() {
+	let filterer = filt(&[], &[], &["js"]).await;
+
+	filterer.file_does_pass("main.js");
+	filterer.file_doesnt_pass("Main.JS");
+	filterer.file_doesnt_pass("main.Js");
+	filterer.file_doesnt_pass("main.ts");
+}
+
+#[tokio::test]
+async fn extensions_scoped_to_subtrees() {
+	let origin = dunce::canonicalize(".").unwrap();
+	let filterer = GlobsetFilterer::builder(&origin)
+		.extension("rs", Some("server".into()))
+		.extension("ts", Some("web".into()))
+		.build()
+		.await
+		.expect("building filterer");
+
+	filterer.file_does_pass("server/main.rs");
+	filterer.file_does_pass("server/src/lib.rs");
+	filterer.file_doesnt_pass("server/index.ts");
+	filterer.file_does_pass("web/index.ts");
+	filterer.file_doesnt_pass("web/build.rs");
+	filterer.file_does_pass("docs/readme.md");
+	filterer.file_does_pass("Cargo.toml");
+	filterer.file_does_pass("servers/main.ts");
+}
+
+#[tokio::test]
+async fn extensions_scoped_add_to_unscoped() {
+	let origin = dunce::canonicalize(".").unwrap();
+	let filterer = GlobsetFilterer::builder(&origin)
+		.extension("md", None)
+		.extension("rs", Some("server".into()))
+		.extension("!bak", Some("server".into()))
+		.build()
+		.await
+		.expect("building filterer");
+
+	filterer.file_does_pass("server/main.rs");
+	filterer.file_does_pass("server/readme.md");
+	filterer.file_doesnt_pass("server/main.rs.bak");
+	filterer.file_doesnt_pass("server/index.ts");
+	filterer.file_does_pass("docs/readme.md");
+	filterer.file_doesnt_pass("docs/main.rs");
+	filterer.dir_does_pass("docs");
+}
+
+#[tokio::test]
+async fn globs_case_insensitive() {
+	let origin = dunce::canonicalize(".").unwrap();
+	let filterer = GlobsetFilterer::builder(&origin)
+		.filter("*.PNG", None)
+		.filter("Assets/**", None)
+		.ignore("*.Thumb.png", None)
+		.case_insensitive(true)
+		.build()
+		.await
+		.expect("building filterer");
+
+	filterer.file_does_pass("image.png");
+	filterer.file_does_pass("IMAGE.Png");
+	filterer.file_does_pass("assets/style.css");
+	filterer.file_does_pass("ASSETS/style.css");
+	filterer.file_doesnt_pass("image.thumb.png");
+	filterer.file_doesnt_pass("IMAGE.THUMB.PNG");
+	filterer.file_doesnt_pass("image.jpg");
+}
+
+#[tokio::test]
+async fn globs_case_sensitive_by_default() {
+	let filterer = filt(&["*.PNG"], &["*.Thumb.PNG"], &[]).await;
+
+	filterer.file_does_pass("image.PNG");
+	filterer.file_doesnt_pass("image.png");
+	filterer.file_doesnt_pass("image.Thumb.PNG");
+	filterer.file_does_pass("image.thumb.PNG");
+}
+
+#[tokio::test]
+async fn extensions_compound() {
+	let filterer = filt(&[], &[], &["tar.gz", "d.ts"]).await;
+
+	filterer.file_does_pass("archive.tar.gz");
+	filterer.file_does_pass("/test/dist/index.d.ts");
+	filterer.file_doesnt_pass("archive.gz");
+	filterer.file_doesnt_pass("archive.tgz");
+	filterer.file_doesnt_pass("index.ts");
+	filterer.file_doesnt_pass(".tar.gz");
+}
+
+#[tokio::test]
+async fn extensions_single_segment_matches_final_extension() {
+	let filterer = filt(&[], &[], &["gz", "js"]).await;
+
+	filterer.file_does_pass("archive.tar.gz");
+	filterer.file_does_pass("foo.js");
+	filterer.file_does_pass("foo.test.js");
+	filterer.file_doesnt_pass("foo.js.map");
+}
+
+#[tokio::test]
+async fn extensions_leading_dot_or_star() {
+	for ext in ["js", ".js", "*.js"] {
+		let filterer = filt(&[], &[], &[ext]).await;
+
+		filterer.file_does_pass("foo.js");
+		filterer.file_does_pass("/test/foo.js");
+		filterer.file_doesnt_pass("foo.ts");
+		filterer.file_doesnt_pass("foojs");
+	}
+
+	let filterer = filt(&[], &[], &[".d.ts", "!*.map", "!.min.js"]).await;
+	filterer.file_does_pass("index.d.ts");
+	filterer.file_doesnt_pass("index.ts");
+	filterer.file_doesnt_pass("app.js.map");
+	filterer.file_doesnt_pass("app.min.js");
+}
+
+#[tokio::test]
+async fn extensions_denied_only() {
+	let filterer = filt(&[], &[], &["!min.js", "!map"]).await;
+
+	filterer.file_does_pass("app.js");
+	filterer.file_does_pass("style.css");
+	filterer.file_does_pass("Makefile");
+	filterer.file_doesnt_pass("app.min.js");
+	filterer.file_doesnt_pass("app.js.map");
+	filterer.dir_does_pass("maps.map");
+}
+
+#[tokio::test]
+async fn extensions_denied_take_precedence_over_allowed() {
+	let filterer = filt(&[], &[], &["js", "!min.js"]).await;
+
+	filterer.file_does_pass("app.js");
+	filterer.file_doesnt_pass("app.min.js");
+	filterer.file_doesnt_pass("style.css");
+	filterer.file_doesnt_pass("Makefile");
+}
+
+#[tokio::test]
+async fn extensions_denied_case_insensitive() {
+	let origin = dunce::canonicalize(".").unwrap();
+	let filterer = GlobsetFilterer::builder(&origin)
+		.extension("!MAP", None)
+		.extensions_case_insensitive(true)
+		.build()
+		.await
+		.expect("building filterer");
+
+	filterer.file_doesnt_pass("app.js.map");
+	filterer.file_doesnt_pass("app.js.Map");
+	filterer.file_does_pass("app.js");
+}
+
+#[tokio::test]
+async fn verbose_reports_reason() {
+	let origin = dunce::canonicalize(".").unwrap();
+	let filterer = GlobsetFilterer::builder(&origin)
+		.filter("src/**", None)
+		.ignore("*.bak", Some(origin.join("src")))
+		.extension("rs", None)
+		.build()
+		.await
+		.expect("building filterer");
+
+	let event = |path: &str| {
+		Event::new(
+			vec![Tag::Path {
+				path: origin.join(path),
+				file_type: Some(FileType::File),
+			}],
+			Default::default(),
+		)
+	};
+
+	assert_eq!(
+		filterer
+			.check_event_verbose(&event("src/lib.rs"))
+			.expect("checking event"),
+		FilterReason::Passed
+	);
+	assert_eq!(
+		filterer
+			.check_event_verbose(&event("src/lib.rs.bak"))
+			.expect("checking event"),
+		FilterReason::Ignored {
+			path: origin.join("src/lib.rs.bak"),
+			glob: "*.bak".into(),
+			source: Some(origin.join("src")),
+		}
+	);
+	assert_eq!(
+		filterer
+			.check_event_verbose(&event("tests/lib.rs"))
+			.expect("checking event"),
+		FilterReason::NotFiltered {
+			path: origin.join("tests/lib.rs"),
+		}
+	);
+	assert_eq!(
+		filterer
+			.check_event_verbose(&event("src/lib.toml"))
+			.expect("checking event"),
+		FilterReason::Extension {
+			path: origin.join("src/lib.toml"),
+		}
+	);
+}
+
+#[tokio::test]
+async fn mixed_batch_passes_if_any_path_passes() {
+	let origin = dunce::canonicalize(".").unwrap();
+	let filterer = GlobsetFilterer::builder(&origin)
+		.ignore("*.tmp", None)
+		.extension("rs", None)
+		.build()
+		.await
+		.expect("building filterer");
+
+	let event = |paths: &[&str]| {
+		Event::new(
+			paths
+				.iter()
+				.map(|path| Tag::Path {
+					path: origin.join(path),
+					file_type: Some(FileType::File),
+				})
+				.collect(),
+			Default::default(),
+		)
+	};
+
+	// a rename from an ignored name to a wanted one
+	let rename = event(&["lib.rs.tmp", "lib.rs"]);
+	assert_eq!(
+		filterer
+			.check_event_verbose(&rename)
+			.expect("checking event"),
+		FilterReason::Passed
+	);
+	assert_eq!(
+		filterer
+			.passing_paths(&rename)
+			.into_iter()
+			.map(|(path, _)| path.to_owned())
+			.collect::<Vec<_>>(),
+		vec![origin.join("lib.rs")]
+	);
+
+	// nothing wanted: the first path's reason is reported
+	let unwanted = event(&["a.tmp", "b.toml"]);
+	assert_eq!(
+		filterer
+			.check_event_verbose(&unwanted)
+			.expect("checking event"),
+		FilterReason::Ignored {
+			path: origin.join("a.tmp"),
+			glob: "*.tmp".into(),
+			source: None,
+		}
+	);
+	assert!(filterer.passing_paths(&unwanted).is_empty());
+}
+
+#[tokio::test]
+async fn passing_paths_are_deduplicated() {
+	let origin = dunce::canonicalize(".").unwrap();
+	let filterer = GlobsetFilterer::builder(&origin)
+		.build()
+		.await
+		.expect("building filterer");
+
+	let event = Event::new(
+		["a.rs", "b.rs", "a.rs"]
+			.iter()
+			.map(|path| Tag::Path {
+				path: origin.join(path),
+				file_type: None,
+			})
+			.collect(),
+		Default::default(),
+	);
+
+	assert_eq!(
+		filterer
+			.passing_paths(&event)
+			.into_iter()
+			.map(|(path, _)| path.to_owned())
+			.collect::<Vec<_>>(),
+		vec![origin.join("a.rs"), origin.join("b.rs")]
+	);
+}
+
+#[tokio::test]
+async fn filter_paths_reports_each_path() {
+	let origin = dunce::canonicalize(".").unwrap();
+	let filterer = GlobsetFilterer::builder(&origin)
+		.ignore("*.tmp", None)
+		.extension("rs", None)
+		.build()
+		.await
+		.expect("building filterer");
+
+	let event = Event::new(
+		["src/main.rs", "src/main.tmp", "README.md", "src/main.rs"]
+			.iter()
+			.map(|path| Tag::Path {
+				path: origin.join(path),
+				file_type: Some(FileType::File),
+			})
+			.collect(),
+		Default::default(),
+	);
+
+	assert_eq!(
+		filterer
+			.filter_paths(&event)
+			.into_iter()
+			.map(|(path, passed)| (path.to_owned(), passed))
+			.collect::<Vec<_>>(),
+		vec![
+			(origin.join("src/main.rs"), true),
+			(origin.join("src/main.tmp"), false),
+			(origin.join("README.md"), false),
+		]
+	);
+	assert!(filterer.check_event(&event).expect("checking event"));
+	assert_eq!(filterer.stats().passed, 1);
+}
+
+#[tokio::test]
+async fn stats_count_events_by_reason() {
+	let filterer = filt(&["src/**"], &["*.tmp"], &["rs", "!snap"]).await;
+
+	filterer.file_does_pass("src/main.rs");
+	filterer.file_does_pass("src/lib.rs");
+	filterer.file_doesnt_pass("src/main.tmp");
+	filterer.file_doesnt_pass("tests/main.rs");
+	filterer.file_doesnt_pass("src/main.ts");
+	filterer.file_doesnt_pass("src/main.snap");
+	filterer
+		.check_event_verbose(&Event::new(
+			vec![Tag::Path {
+				path: "src/uncounted.tmp".into(),
+				file_type: None,
+			}],
+			Default::default(),
+		))
+		.expect("checking event");
+
+	let stats = filterer.stats();
+	assert_eq!(stats.passed, 2);
+	assert_eq!(stats.ignored, 1);
+	assert_eq!(stats.not_filtered, 1);
+	assert_eq!(stats.extension, 1);
+	assert_eq!(stats.denied_extension, 1);
+	assert_eq!(stats.ignore_file, 0);
+	assert_eq!(stats.rejected(), 4);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn non_unicode_name_against_compound_extension_errors() {
+	use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+	let origin = dunce::canonicalize(".").unwrap();
+	let filterer = filt(&[], &[], &["tar.gz"]).await;
+	let name = OsStr::from_bytes(b"backup\xff.tar.gz");
+	let event = Event::new(
+		vec![Tag::Path {
+			path: origin.join(name),
+			file_type: Some(FileType::File),
+		}],
+		Default::default(),
+	);
+
+	match filterer.check_event(&event) {
+		Err(RuntimeError::FilterCheck {
+			path,
+			glob,
+			glob_source,
+			..
+		}) => {
+			assert_eq!(path, origin.join(name));
+			assert_eq!(glob, "*.tar.gz");
+			assert_eq!(glob_source, None);
+		}
+		other => panic!("expected a filter check error, got {:?}", other),
+	}
+	assert_eq!(filterer.stats().errored, 1);
+
+	let filterer = filt(&[], &[], &["gz"]).await;
+	assert!(filterer.check_event(&event).expect("checking event"));
+}
+
+#[tokio::test]
+async fn shared_gitignores() {
+	let origin = dunce::canonicalize(".").unwrap();
+	let build = |globs: &[&str]| {
+		let mut builder = GitignoreBuilder::new(&origin);
+		for glob in globs {
+			builder.add_line(None, glob).expect("adding glob");
+		}
+		builder.build().expect("building globs")
+	};
+
+	let ignores = build(&["target", "*.log"]);
+	let sources = GlobsetFilterer::from_gitignores(
+		&origin,
+		build(&["src/**"]),
+		ignores.clone(),
+		vec![OsString::from("rs")],
+	);
+	let docs = GlobsetFilterer::from_gitignores(&origin, build(&[]), ignores, vec![]);
+
+	sources.file_does_pass("src/main.rs");
+	sources.file_doesnt_pass("src/notes.md");
+	sources.file_doesnt_pass("src/debug.log");
+	sources.file_doesnt_pass("README.md");
+	docs.file_does_pass("README.md");
+	docs.file_doesnt_pass("debug.log");
+	docs.dir_doesnt_pass("target");
+}
+
+#[tokio::test]
+async fn globs_match_relative_to_each_root() {
+	let origin = dunce::canonicalize(".").unwrap();
+	let filterer = GlobsetFilterer::builder("/")
+		.filter("src/**", None)
+		.ignore("src/generated/**", None)
+		.root(origin.join("a"))
+		.root(origin.join("b"))
+		.build()
+		.await
+		.expect("building filterer");
+
+	filterer.file_does_pass("/src/main.rs");
+	filterer.file_does_pass("a/src/main.rs");
+	filterer.file_does_pass("b/src/lib.rs");
+	filterer.file_doesnt_pass("a/README.md");
+	filterer.file_doesnt_pass("b/src/generated/schema.rs");
+	filterer.file_doesnt_pass("c/src/main.rs");
+}
+
+#[tokio::test]
+async fn nested_roots_use_the_closest() {
+	let origin = dunce::canonicalize(".").unwrap();
+	let filterer = GlobsetFilterer::builder(&origin)
+		.ignore("/*.log", None)
+		.root(origin.join("apps"))
+		.root(origin.join("apps/web"))
+		.build()
+		.await
+		.expect("building filterer");
+
+	filterer.file_doesnt_pass("debug.log");
+	filterer.file_doesnt_pass("apps/web/debug.log");
+	filterer.file_does_pass("apps/web/logs/debug.log");
+}
+
+#[tokio::test]
+async fn ignore_hidden_paths() {
+	let origin = dunce::canonicalize(".").unwrap();
+	let filterer = GlobsetFilterer::builder(&origin)
+		.ignore_hidden(true)
+		.root(origin.join(".config/app"))
+		.build()
+		.await
+		.expect("building filterer");
+
+	filterer.file_does_pass("src/lib.rs");
+	filterer.file_does_pass("src/file.with.dots");
+	filterer.file_doesnt_pass(".env");
+	filterer.dir_doesnt_pass(".github");
+	filterer.file_doesnt_pass(".github/workflows/ci.yml");
+	filterer.file_doesnt_pass("src/.cache/lib.rs");
+
+	// within a root, only the part of the path within it counts
+	filterer.file_does_pass(".config/app/settings.toml");
+	filterer.file_doesnt_pass(".config/app/.secret");
+
+	// outside of the origin and roots, only the file name counts
+	filterer.file_does_pass("/.elsewhere/visible");
+	filterer.file_doesnt_pass("/elsewhere/.hidden");
+
+	assert_eq!(
+		filterer
+			.check_event_verbose(&Event::new(
+				vec![Tag::Path {
+					path: origin.join(".env"),
+					file_type: Some(FileType::File),
+				}],
+				Default::default()
+			))
+			.expect("checking event"),
+		FilterReason::Hidden {
+			path: origin.join(".env"),
+		}
+	);
+}
+
+#[tokio::test]
+async fn hidden_paths_pass_by_default() {
+	let filterer = filt(&[], &[], &[]).await;
+
+	filterer.file_does_pass(".env");
+	filterer.file_does_pass(".github/workflows/ci.yml");
+}
+
+#[tokio::test]
+async fn introspects_configuration() {
+	let filterer = GlobsetFilterer::builder(".")
+		.filter("*.rs", None)
+		.filter("!build.rs", None)
+		.ignore("target/", None)
+		.extension(".rs", None)
+		.extension("*.toml", None)
+		.extension("!bak", None)
+		.build()
+		.await
+		.expect("building filterer");
+
+	assert_eq!(filterer.filter_count(), 2);
+	assert_eq!(filterer.ignore_count(), 1);
+	assert_eq!(
+		filterer.extensions(),
+		vec![OsString::from("rs"), OsString::from("toml")]
+	);
+	assert_eq!(filterer.denied_extensions(), vec![OsString::from("bak")]);
+
+	let empty = filt(&[], &[], &[]).await;
+	assert_eq!(empty.filter_count(), 0);
+	assert_eq!(empty.ignore_count(), 0);
+	assert!(empty.extensions().is_empty());
+}
@@ -0,0 +1,102 @@
+use std::{
+	fs::{create_dir_all, remove_dir_all, write},
+	path::{Path, PathBuf},
+};
+
+use watchexec::{
+	event::{Event, Tag},
+	filter::{globset::GlobsetFilterer, size::SizeFilterer, AndFilterer, Filterer},
+};
+
+fn scratch(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!(
+		"watchexec-filter-size-{}-{}",
+		name,
+		std::process::id()
+	));
+	remove_dir_all(&dir).ok();
+	create_dir_all(&dir).unwrap();
+	dunce::canonicalize(dir).unwrap()
+}
+
+fn event(paths: &[&Path]) -> Event {
+	Event::new(
+		paths
+			.iter()
+			.map(|path| Tag::Path {
+				path: path.to_path_buf(),
+				file_type: None,
+			})
+			.collect(),
+		Default::default(),
+	)
+}
+
+fn check(filterer: &impl Filterer, paths: &[&Path]) -> bool {
+	filterer.check_event(&event(paths)).unwrap()
+}
+
+#[test]
+fn files_in_range() {
+	let dir = scratch("range");
+	let small = dir.join("small.txt");
+	let medium = dir.join("medium.txt");
+	let large = dir.join("large.txt");
+	write(&small, [0; 10]).unwrap();
+	write(&medium, [0; 100]).unwrap();
+	write(&large, [0; 1000]).unwrap();
+
+	let filterer = SizeFilterer::new(10..=100);
+	assert!(check(&filterer, &[&small]));
+	assert!(check(&filterer, &[&medium]));
+	assert!(!check(&filterer, &[&large]));
+	assert!(!check(&filterer, &[&small, &large]));
+	assert!(check(&filterer, &[]));
+
+	let filterer = SizeFilterer::new(500..=u64::MAX);
+	assert!(!check(&filterer, &[&medium]));
+	assert!(check(&filterer, &[&large]));
+
+	remove_dir_all(dir).ok();
+}
+
+#[test]
+fn dirs_and_missing_paths() {
+	let dir = scratch("dirs");
+	let sub = dir.join("sub");
+	let gone = dir.join("gone.txt");
+	create_dir_all(&sub).unwrap();
+
+	let filterer = SizeFilterer::new(1..=10);
+	assert!(check(&filterer, &[&sub]));
+	assert!(check(&filterer, &[&gone]));
+
+	let filterer = SizeFilterer::new(1..=10)
+		.pass_dirs(false)
+		.pass_missing(false);
+	assert!(!check(&filterer, &[&sub]));
+	assert!(!check(&filterer, &[&gone]));
+
+	remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn combined_with_globset() {
+	let dir = scratch("globset");
+	let small_rs = dir.join("small.rs");
+	let large_rs = dir.join("large.rs");
+	let small_md = dir.join("small.md");
+	write(&small_rs, [0; 10]).unwrap();
+	write(&large_rs, [0; 1000]).unwrap();
+	write(&small_md, [0; 10]).unwrap();
+
+	let globset = GlobsetFilterer::new(&dir, [], [], [], ["rs".into()])
+		.await
+		.unwrap();
+	let filterer = AndFilterer::new(globset, SizeFilterer::new(0..=100));
+	assert!(check(&filterer, &[&small_rs]));
+	assert!(!check(&filterer, &[&large_rs]));
+	assert!(!check(&filterer, &[&small_md]));
+
+	remove_dir_all(dir).ok();
+}
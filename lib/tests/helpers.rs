@@ -1,4 +1,4 @@
-#![allow(dead_code)]
+#![allow(dead_code, unused_imports, clippy::result_large_err)]
 
 use std::{
 	ffi::OsString,
@@ -56,10 +56,7 @@ pub trait PathHarness: Filterer {
 		path: PathBuf,
 		file_type: Option<FileType>,
 	) -> std::result::Result<bool, RuntimeError> {
-		let event = Event {
-			tags: vec![Tag::Path { path, file_type }],
-			metadata: Default::default(),
-		};
+		let event = Event::new(vec![Tag::Path { path, file_type }], Default::default());
 
 		self.check_event(&event)
 	}
@@ -179,10 +176,7 @@ pub trait TaggedHarness {
 
 impl TaggedHarness for TaggedFilterer {
 	fn check_tag(&self, tag: Tag) -> std::result::Result<bool, RuntimeError> {
-		let event = Event {
-			tags: vec![tag],
-			metadata: Default::default(),
-		};
+		let event = Event::new(vec![tag], Default::default());
 
 		self.check_event(&event)
 	}
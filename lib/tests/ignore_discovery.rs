@@ -0,0 +1,71 @@
+use std::{
+	fs::{create_dir_all, remove_dir_all, write},
+	path::PathBuf,
+};
+
+use watchexec::{
+	event::{Event, FileType, Tag},
+	filter::Filterer,
+	ignore::{from_origin, IgnoreFilterer},
+	project::ProjectType,
+};
+
+fn scratch(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!(
+		"watchexec-ignore-discovery-{}-{}",
+		name,
+		std::process::id()
+	));
+	remove_dir_all(&dir).ok();
+	create_dir_all(&dir).unwrap();
+	dunce::canonicalize(dir).unwrap()
+}
+
+fn passes(filterer: &IgnoreFilterer, path: PathBuf) -> bool {
+	filterer
+		.check_event(&Event::new(
+			vec![Tag::Path {
+				path,
+				file_type: Some(FileType::File),
+			}],
+			Default::default(),
+		))
+		.unwrap()
+}
+
+#[tokio::test]
+async fn nested_gitignores_apply_to_their_subtree() {
+	let dir = scratch("nested");
+	create_dir_all(dir.join(".git")).unwrap();
+	create_dir_all(dir.join("web/node_modules")).unwrap();
+	create_dir_all(dir.join("api")).unwrap();
+	write(dir.join(".gitignore"), "target\n").unwrap();
+	write(dir.join("web/.gitignore"), "*.js\n").unwrap();
+
+	let (files, errors) = from_origin(&dir).await;
+	assert!(errors.is_empty(), "{errors:?}");
+
+	let mut gitignores = files
+		.iter()
+		.filter(|file| file.applies_to == Some(ProjectType::Git))
+		.map(|file| (file.path.clone(), file.applies_in.clone()))
+		.collect::<Vec<_>>();
+	gitignores.sort();
+	assert_eq!(
+		gitignores,
+		vec![
+			(dir.join(".gitignore"), Some(dir.clone())),
+			(dir.join("web/.gitignore"), Some(dir.join("web"))),
+		]
+	);
+
+	let filterer = IgnoreFilterer::new(&dir, &files).await.unwrap();
+	assert!(!passes(&filterer, dir.join("target/debug/app")));
+	assert!(!passes(&filterer, dir.join("web/target/out")));
+	assert!(!passes(&filterer, dir.join("web/index.js")));
+	assert!(!passes(&filterer, dir.join("web/src/app.js")));
+	assert!(passes(&filterer, dir.join("api/index.js")));
+	assert!(passes(&filterer, dir.join("index.js")));
+
+	remove_dir_all(dir).ok();
+}
@@ -0,0 +1,85 @@
+#![cfg(unix)]
+
+use std::time::Duration;
+
+use tokio::{process::Command, sync::mpsc, time::timeout};
+use watchexec::{
+	command::Supervisor,
+	error::RuntimeError,
+	event::{Event, Tag},
+};
+
+/// Whether a process (or process group, with a negative id) can still be signalled.
+fn exists(id: i64) -> bool {
+	std::process::Command::new("kill")
+		.arg("-0")
+		.arg("--")
+		.arg(id.to_string())
+		.stderr(std::process::Stdio::null())
+		.status()
+		.unwrap()
+		.success()
+}
+
+async fn kill_sleep(grouped: bool) {
+	let (errors, _errors_r) = mpsc::channel(8);
+	let (events, mut events_r) = mpsc::channel(8);
+
+	let mut sup = Supervisor::spawn(errors, events, Command::new("sleep").arg("100"), grouped)
+		.expect("spawning sleep");
+
+	let id = i64::from(sup.id());
+	let target = if grouped { -id } else { id };
+	assert!(sup.is_running());
+	assert!(exists(target));
+
+	sup.kill().await;
+	timeout(Duration::from_secs(5), sup.wait())
+		.await
+		.expect("sleep wasn't killed in time")
+		.expect("waiting on supervisor");
+
+	assert!(!sup.is_running());
+	assert!(!exists(target), "process is still around after kill");
+
+	let event: Event = events_r.recv().await.expect("completion event");
+	assert!(event
+		.tags
+		.iter()
+		.any(|tag| matches!(tag, Tag::ProcessCompletion(_))));
+}
+
+#[tokio::test]
+async fn kill_stops_grouped_process() {
+	kill_sleep(true).await;
+}
+
+#[tokio::test]
+async fn kill_stops_ungrouped_process() {
+	kill_sleep(false).await;
+}
+
+#[tokio::test]
+async fn spawn_error_names_program() {
+	let (errors, _errors_r) = mpsc::channel(8);
+	let (events, _events_r) = mpsc::channel(8);
+
+	let err = Supervisor::spawn(
+		errors,
+		events,
+		&mut Command::new("watchexec-no-such-program"),
+		true,
+	)
+	.map(|_| ())
+	.unwrap_err();
+
+	assert!(
+		matches!(&err, RuntimeError::CommandSpawn { program, .. } if program == "watchexec-no-such-program"),
+		"{:?}",
+		err
+	);
+	assert_eq!(
+		err.to_string(),
+		"command not found: watchexec-no-such-program"
+	);
+}
@@ -0,0 +1,89 @@
+#![cfg(unix)]
+
+use std::{
+	fs::{create_dir_all, remove_dir_all},
+	os::unix::fs::symlink,
+	path::PathBuf,
+};
+
+use watchexec::paths::symlink_targets;
+
+fn scratch(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!(
+		"watchexec-symlink-targets-{}-{}",
+		name,
+		std::process::id()
+	));
+	remove_dir_all(&dir).ok();
+	create_dir_all(&dir).unwrap();
+	dunce::canonicalize(dir).unwrap()
+}
+
+#[test]
+fn no_links_no_targets() {
+	let dir = scratch("none");
+	create_dir_all(dir.join("root/sub")).unwrap();
+
+	assert_eq!(symlink_targets([dir.join("root")]), Vec::<PathBuf>::new());
+	remove_dir_all(dir).ok();
+}
+
+#[test]
+fn outside_target() {
+	let dir = scratch("outside");
+	create_dir_all(dir.join("root")).unwrap();
+	create_dir_all(dir.join("real/nested")).unwrap();
+	symlink(dir.join("real"), dir.join("root/src")).unwrap();
+
+	assert_eq!(symlink_targets([dir.join("root")]), vec![dir.join("real")]);
+	remove_dir_all(dir).ok();
+}
+
+#[test]
+fn inside_target_is_skipped() {
+	let dir = scratch("inside");
+	create_dir_all(dir.join("root/real")).unwrap();
+	symlink(dir.join("root/real"), dir.join("root/link")).unwrap();
+
+	assert_eq!(symlink_targets([dir.join("root")]), Vec::<PathBuf>::new());
+	remove_dir_all(dir).ok();
+}
+
+#[test]
+fn nested_links_are_followed() {
+	let dir = scratch("nested");
+	create_dir_all(dir.join("root")).unwrap();
+	create_dir_all(dir.join("one")).unwrap();
+	create_dir_all(dir.join("two")).unwrap();
+	symlink(dir.join("one"), dir.join("root/one")).unwrap();
+	symlink(dir.join("two"), dir.join("one/two")).unwrap();
+
+	assert_eq!(
+		symlink_targets([dir.join("root")]),
+		vec![dir.join("one"), dir.join("two")]
+	);
+	remove_dir_all(dir).ok();
+}
+
+#[test]
+fn loops_terminate() {
+	let dir = scratch("loops");
+	create_dir_all(dir.join("root")).unwrap();
+	create_dir_all(dir.join("other")).unwrap();
+	symlink(dir.join("root"), dir.join("root/self")).unwrap();
+	symlink(dir.join("other"), dir.join("root/other")).unwrap();
+	symlink(dir.join("root"), dir.join("other/back")).unwrap();
+
+	assert_eq!(symlink_targets([dir.join("root")]), vec![dir.join("other")]);
+	remove_dir_all(dir).ok();
+}
+
+#[test]
+fn broken_links_are_skipped() {
+	let dir = scratch("broken");
+	create_dir_all(dir.join("root")).unwrap();
+	symlink(dir.join("nowhere"), dir.join("root/broken")).unwrap();
+
+	assert_eq!(symlink_targets([dir.join("root")]), Vec::<PathBuf>::new());
+	remove_dir_all(dir).ok();
+}